@@ -0,0 +1,55 @@
+//! Compares running a tight loop from its bytes, decoding every step, with running it pre-decoded.
+//! Run with `cargo bench -p kaylee`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use kaylee::instructions::data::Load;
+use kaylee::instructions::machine::Halt;
+use kaylee::instructions::math::{WrappingAdd, WrappingSubtract};
+use kaylee::instructions::program::JumpNotZero;
+use kaylee::program::{DecodedProgram, Program};
+use kaylee::vm::Kaylee;
+
+const RUNS: u32 = 20;
+
+fn countdown() -> Program {
+    Program::from(vec![
+        Load::OPCODE, 1, 0, 1,              // 0: LOAD $1 #1
+        Load::OPCODE, 2, 0xFF, 0xFF,        // 4: LOAD $2 #65535
+        WrappingAdd::OPCODE, 3, 3, 2,       // 8: ADDW $3 $3 $2
+        WrappingSubtract::OPCODE, 2, 2, 1,  // 12: SUBW $2 $2 $1
+        JumpNotZero::OPCODE, 0, 0, 8,       // 16: JUMPNZ #8
+        Halt::OPCODE, 0, 0, 0,              // 20: HALT
+    ])
+}
+
+fn time<F: FnMut()>(mut run: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run();
+    }
+
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let program = countdown();
+
+    let interpreted = time(|| {
+        let mut vm = Kaylee::new();
+        vm.run(black_box(program.clone())).unwrap();
+        black_box(vm.register(3).unwrap());
+    });
+
+    let decoded_program = DecodedProgram::from(program.clone());
+    let decoded = time(|| {
+        let mut vm = Kaylee::new();
+        vm.run_decoded(black_box(&decoded_program)).unwrap();
+        black_box(vm.register(3).unwrap());
+    });
+
+    println!("countdown from 65535, average of {RUNS} runs");
+    println!("  run:         {interpreted:?}");
+    println!("  run_decoded: {decoded:?}");
+    println!("  speedup:     {:.2}x", interpreted.as_secs_f64() / decoded.as_secs_f64());
+}
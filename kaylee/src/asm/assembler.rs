@@ -1,67 +1,301 @@
+use std::collections::BTreeMap;
+
+use crate::config::VmConfig;
 use crate::instructions::{InstructionRegistry, OperandType};
-use crate::program::Program;
+use crate::program::{Constant, Program};
+use crate::vm::Kaylee;
 
 #[derive(Debug, PartialEq)]
 pub enum AssemblerError {
+    /// The instruction at this index (counting from 0) names a register the VM does not have
+    RegisterOutOfBounds { instruction: usize, register: i32 },
+    /// An `@label` operand names a label that is never defined
+    UnknownLabel(String),
+    /// The same label is defined twice
+    DuplicateLabel(String),
+    /// A `.byte` value does not fit in a byte
+    InvalidByte(i32),
+    /// The instruction at this index (counting from 0) has more operands than its signature
+    OperandCount { instruction: usize, expected: usize, found: usize },
+    /// The instruction at this index (counting from 0) has a constant too large or too small for its operand
+    ConstantOutOfRange { instruction: usize, value: i32 },
+    /// The instruction at this index (counting from 0) has an operand whose `$`, `%`, `#` or `@` does not match its signature
+    OperandMismatch { instruction: usize, operand: String },
     Other(String),
 }
 
 pub struct Assembler {
-    // There will be state needed eventually
+    register_count: usize,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Assembler {
+    /// Places raw bytes instead of an instruction
+    pub const BYTE_DIRECTIVE: &'static str = ".byte";
+    /// Starts the lines that become constants
+    pub const DATA_SECTION: &'static str = ".data";
+    /// Starts the lines that become instructions, as a program does before any `.data`
+    pub const CODE_SECTION: &'static str = ".code";
+
     pub fn new() -> Self {
-        Assembler {}
+        Assembler::for_config(&VmConfig::new())
+    }
+
+    /// An assembler that checks register operands against the registers a configured VM will have
+    pub fn for_config(config: &VmConfig) -> Self {
+        Assembler { register_count: config.register_count() }
     }
 
-    /// @todo: This is awful. Absolutely no error checking
+    /// Lines are instructions, `label:` definitions, or `.byte` directives that place their values as they are.
+    /// An `@label` operand is replaced with the ProgramIndex of the label, which is kept in the Program's symbols.
+    /// After `.data`, lines are `.int`, `.string` or `.bytes` constants until `.code`. Each adds an entry to the
+    /// constant pool, and a label before one names the index of its entry.
     pub fn assemble_parsed_asm(&self, parsed: Vec<Vec<&str>>) -> Result<Program, AssemblerError> {
+        let labels = Assembler::find_labels(&parsed)?;
         let mut bytes: Vec<u8> = Vec::new();
+        let mut data: Vec<u8> = Vec::new();
+        let mut constants = Vec::new();
+        let mut section = Section::Code;
 
-        for instruction in parsed {
-            let item = InstructionRegistry::get(instruction[0]);
+        for (index, instruction) in parsed.into_iter().enumerate() {
+            match instruction[0] {
+                Assembler::CODE_SECTION => section = Section::Code,
+                Assembler::DATA_SECTION => section = Section::Data,
+                label if label.ends_with(':') => {}
+                _ if section == Section::Data => constants.push(Assembler::constant(&instruction, &mut data)?),
+                Assembler::BYTE_DIRECTIVE => {
+                    for value in &instruction[1..] {
+                        bytes.push(Assembler::byte(value)?);
+                    }
+                }
+                _ => match Assembler::expand_load(&instruction)? {
+                    Some(lines) => {
+                        for line in lines {
+                            let line = line.iter().map(String::as_str).collect::<Vec<_>>();
+                            self.assemble_instruction(index, &line, &labels, &mut bytes)?;
+                        }
+                    }
+                    None => self.assemble_instruction(index, &instruction, &labels, &mut bytes)?,
+                },
+            }
+        }
 
-            // Push the opcode
-            bytes.push(item.unwrap().1.clone());
+        let program = labels.iter()
+            .filter(|(_, (section, _))| *section == Section::Code)
+            .fold(Program::from(bytes).with_data(data), |program, (label, (_, index))| program.with_symbol(label, *index));
 
-            for i in 1..(instruction.len()) {
-                if let Some(value) = instruction.get(i) {
-                    // this is an operand, so we have to break it into u8 chunks
-                    let number = value.parse::<i32>().unwrap();
-                    let operand_bytes = number.to_be_bytes();
+        Ok(constants.into_iter().fold(program, Program::with_constant))
+    }
 
-                    let spot: &OperandType = &item.unwrap().2[i - 1];
+    fn assemble_instruction(&self, index: usize, instruction: &[&str], labels: &Labels, bytes: &mut Vec<u8>) -> Result<(), AssemblerError> {
+        let item = InstructionRegistry::get(instruction[0])
+            .ok_or_else(|| AssemblerError::Other(format!("Unknown instruction {}", instruction[0])))?;
+        let start = bytes.len();
 
-                    let byte_count = match spot {
-                        OperandType::None => 0 as u8,
-                        OperandType::RegisterId => 1 as u8,
-                        OperandType::ConstantByte => 1 as u8,
-                        OperandType::ConstantHalfWord => 2 as u8,
-                        OperandType::ConstantWord => 3 as u8,
-                    };
+        // Trailing operands may be left out, and are zeros, but there can be no more than the signature has
+        let expected = item.2.iter().filter(|operand| !matches!(operand, OperandType::None)).count();
+        let found = instruction.len() - 1;
+        if found > expected {
+            return Err(AssemblerError::OperandCount { instruction: index, expected, found });
+        }
+
+        // Push the opcode
+        bytes.push(item.1);
+
+        for i in 1..(instruction.len()) {
+            if let Some(value) = instruction.get(i) {
+                let spot: &OperandType = &item.2[i - 1];
+
+                // Registers are `$` or `%`, as the signature says, and constants are `#` or an `@label`
+                let sigil = value.chars().next();
+                let rest = &value[sigil.map_or(0, char::len_utf8)..];
+                let constant = matches!(spot, OperandType::ConstantByte | OperandType::ConstantHalfWord | OperandType::ConstantWord);
+
+                // this is an operand, so we have to break it into u8 chunks
+                let number = match (sigil, spot) {
+                    (Some('$'), OperandType::RegisterId) | (Some('%'), OperandType::FloatRegisterId) => Assembler::number(rest)?,
+                    (Some('#'), _) if constant => Assembler::number(rest)?,
+                    (Some('@'), _) if constant => labels.get(rest).ok_or_else(|| AssemblerError::UnknownLabel(String::from(rest)))?.1 as i32,
+                    _ => return Err(AssemblerError::OperandMismatch { instruction: index, operand: value.to_string() }),
+                };
+                let operand_bytes = number.to_be_bytes();
+
+                let registers = match spot {
+                    OperandType::RegisterId => Some(self.register_count),
+                    OperandType::FloatRegisterId => Some(Kaylee::FLOAT_REGISTER_COUNT),
+                    _ => None,
+                };
+
+                if registers.is_some_and(|registers| number < 0 || number as usize >= registers) {
+                    return Err(AssemblerError::RegisterOutOfBounds { instruction: index, register: number });
+                }
+
+                let byte_count: u8 = match spot {
+                    OperandType::None => 0,
+                    OperandType::RegisterId | OperandType::FloatRegisterId => 1,
+                    OperandType::ConstantByte => 1,
+                    OperandType::ConstantHalfWord => 2,
+                    OperandType::ConstantWord => 3,
+                };
+
+                // A constant may be written unsigned, or as a negative number in two's complement
+                let bits = 8 * byte_count as u32;
+                if byte_count > 0 && !(-(1_i64 << (bits - 1))..(1_i64 << bits)).contains(&(number as i64)) {
+                    return Err(AssemblerError::ConstantOutOfRange { instruction: index, value: number });
+                }
+
+                let start_slice = (4 - byte_count) as usize;
+
+                bytes.extend(&operand_bytes[start_slice..]);
+            }
+        }
+
+        // Every instruction is four bytes. Omitted operands and unused slots are zeros.
+        bytes.resize(start + 4, 0);
+        Ok(())
+    }
+
+    /// The instructions that load the value of a `LOAD` line whose constant does not fit in its unsigned HalfWord:
+    /// `LOADS` for -32768 to -1, otherwise a `LOAD` of the lower 16 bits then a `LOADUP` of the upper 16 bits
+    fn expand_load(line: &[&str]) -> Result<Option<Vec<Vec<String>>>, AssemblerError> {
+        let ["LOAD", register, value] = line else {
+            return Ok(None);
+        };
+
+        // Labels always fit, and an operand that is not a constant is reported when the line is assembled
+        let Some(value) = value.strip_prefix('#') else {
+            return Ok(None);
+        };
+
+        let number = Assembler::number(value)?;
+        let load = |operation: &str, value: i32| vec![String::from(operation), register.to_string(), format!("#{value}")];
+
+        Ok(match number {
+            0..=0xFFFF => None,
+            -0x8000..=-1 => Some(vec![load("LOADS", number)]),
+            _ => Some(vec![load("LOAD", number & 0xFFFF), load("LOADUP", (number >> 16) & 0xFFFF)]),
+        })
+    }
+
+    /// Place the value of a `.data` line into the data section and return its constant pool entry.
+    /// Strings are UTF-8 and end with a zero byte, so `PRINTS` can print them.
+    fn constant(line: &[&str], data: &mut Vec<u8>) -> Result<Constant, AssemblerError> {
+        let address = data.len();
+
+        match line {
+            [".int", value] => return Ok(Constant::Integer(Assembler::literal(value)?)),
+            [".string", literal] => {
+                data.extend(Assembler::string(literal)?.as_bytes());
+                data.push(0);
+            }
+            [".bytes", values @ ..] => {
+                for value in values {
+                    data.push(Assembler::byte(value)?);
+                }
+            }
+            _ => return Err(AssemblerError::Other(format!("{} is not a constant", line.join(" ")))),
+        }
+
+        Ok(Constant::Address(address))
+    }
+
+    fn number(value: &str) -> Result<i32, AssemblerError> {
+        value.parse::<i32>().map_err(|_| AssemblerError::Other(format!("Invalid number {value}")))
+    }
+
+    /// The value of a `#` constant in a directive, like `.int #7`
+    fn literal(value: &str) -> Result<i32, AssemblerError> {
+        let number = value.strip_prefix('#').ok_or_else(|| AssemblerError::Other(format!("{value} is not a # constant")))?;
+        Assembler::number(number)
+    }
+
+    fn byte(value: &str) -> Result<u8, AssemblerError> {
+        let number = Assembler::literal(value)?;
+        u8::try_from(number).map_err(|_| AssemblerError::InvalidByte(number))
+    }
+
+    /// The text of a quoted string token, with `\n`, `\t`, `\"` and `\\` escapes replaced
+    fn string(literal: &str) -> Result<String, AssemblerError> {
+        let invalid = || AssemblerError::Other(format!("Invalid string {literal}"));
+        let body = literal.strip_prefix('"').and_then(|body| body.strip_suffix('"')).ok_or_else(invalid)?;
+
+        let mut text = String::new();
+        let mut characters = body.chars();
+        while let Some(character) = characters.next() {
+            text.push(match character {
+                '\\' => match characters.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    _ => return Err(invalid()),
+                },
+                character => character,
+            });
+        }
+
+        Ok(text)
+    }
+
+    /// What each label names: a ProgramIndex in the code, or an index of the constant pool.
+    /// Instructions are four bytes, or eight for a `LOAD` of a large constant,
+    /// `.byte` lines one byte per value, and each `.data` line one constant.
+    fn find_labels(parsed: &[Vec<&str>]) -> Result<Labels, AssemblerError> {
+        let mut labels = BTreeMap::new();
+        let mut section = Section::Code;
+        let mut index = 0;
+        let mut constant = 0;
 
-                    let start_slice = (4 - byte_count) as usize;
+        for line in parsed {
+            match line[0] {
+                Assembler::CODE_SECTION => section = Section::Code,
+                Assembler::DATA_SECTION => section = Section::Data,
+                label if label.ends_with(':') => {
+                    let label = &label[..label.len() - 1];
+                    let value = match section {
+                        Section::Code => index,
+                        Section::Data => constant,
+                    };
 
-                    bytes.extend(&operand_bytes[start_slice..]);
+                    if labels.insert(String::from(label), (section, value)).is_some() {
+                        return Err(AssemblerError::DuplicateLabel(String::from(label)));
+                    }
                 }
+                _ if section == Section::Data => constant += 1,
+                Assembler::BYTE_DIRECTIVE => index += line.len() - 1,
+                _ => index += 4 * Assembler::expand_load(line)?.map_or(1, |lines| lines.len()),
             }
         }
 
-        Ok(Program::from(bytes))
+        Ok(labels)
     }
 }
 
+/// The part of the Program lines are assembled into
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Section {
+    Code,
+    Data,
+}
+
+type Labels = BTreeMap<String, (Section, usize)>;
+
 #[cfg(test)]
 mod tests {
-    use crate::asm::assembler::Assembler;
-    use crate::program::Program;
+    use crate::asm::assembler::{Assembler, AssemblerError};
+    use crate::config::VmConfig;
+    use crate::program::{Constant, Program};
 
     #[test]
     pub fn test_into_bytecode() {
         let parsed = vec![
-            vec!["LOAD", "1", "500"],
-            vec!["ADD", "2", "3", "2"],
+            vec!["LOAD", "$1", "#500"],
+            vec!["ADD", "$2", "$3", "$2"],
         ];
 
         let expected = Program::from(vec![
@@ -74,4 +308,158 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    pub fn test_pads_instructions_to_four_bytes() {
+        let parsed = vec![
+            vec!["PUSH", "$3"],
+            vec!["HALT"],
+            vec!["DIE", "#2", "#300"],
+        ];
+
+        let expected = Program::from(vec![
+            43, 3, 0, 0,
+            1, 0, 0, 0,
+            2, 2, 1, 44,
+        ]);
+
+        let assembler = Assembler::new();
+        assert_eq!(expected, assembler.assemble_parsed_asm(parsed).unwrap());
+    }
+
+    #[test]
+    pub fn test_checks_registers_against_the_config() {
+        let parsed = || vec![
+            vec!["LOAD", "$1", "#5"],
+            vec!["ADD", "$40", "$1", "$1"],
+        ];
+
+        let error = Assembler::new().assemble_parsed_asm(parsed()).unwrap_err();
+        assert_eq!(AssemblerError::RegisterOutOfBounds { instruction: 1, register: 40 }, error);
+
+        let assembler = Assembler::for_config(&VmConfig::new().with_register_count(64));
+        assert!(assembler.assemble_parsed_asm(parsed()).is_ok());
+
+        let error = assembler.assemble_parsed_asm(vec![vec!["FLOADH", "%32", "#1"]]).unwrap_err();
+        assert_eq!(AssemblerError::RegisterOutOfBounds { instruction: 0, register: 32 }, error);
+    }
+
+    #[test]
+    pub fn test_labels_and_bytes() {
+        let parsed = vec![
+            vec![".byte", "#255", "#7"],
+            vec!["start:"],
+            vec!["JUMPNZ", "@end"],
+            vec!["JUMP", "@start"],
+            vec!["end:"],
+            vec!["HALT"],
+        ];
+
+        let program = Assembler::new().assemble_parsed_asm(parsed).unwrap();
+
+        assert_eq!(&vec![
+            255, 7,
+            57, 0, 0, 10,
+            50, 0, 0, 2,
+            1, 0, 0, 0,
+        ], program.bytes());
+        assert_eq!(Some(&10), program.symbols().get("end"));
+
+        let error = Assembler::new().assemble_parsed_asm(vec![vec!["JUMP", "@nowhere"]]).unwrap_err();
+        assert_eq!(AssemblerError::UnknownLabel(String::from("nowhere")), error);
+
+        let error = Assembler::new().assemble_parsed_asm(vec![vec!["a:"], vec!["a:"]]).unwrap_err();
+        assert_eq!(AssemblerError::DuplicateLabel(String::from("a")), error);
+
+        let error = Assembler::new().assemble_parsed_asm(vec![vec![".byte", "#256"]]).unwrap_err();
+        assert_eq!(AssemblerError::InvalidByte(256), error);
+
+        let error = Assembler::new().assemble_parsed_asm(vec![vec!["HALT"], vec!["ADD", "$1", "$2", "$3", "$4"]]).unwrap_err();
+        assert_eq!(AssemblerError::OperandCount { instruction: 1, expected: 3, found: 4 }, error);
+
+        let error = Assembler::new().assemble_parsed_asm(vec![vec!["YIELD", "#1"]]).unwrap_err();
+        assert_eq!(AssemblerError::OperandCount { instruction: 0, expected: 0, found: 1 }, error);
+    }
+
+    #[test]
+    pub fn test_data_section() {
+        let parsed = vec![
+            vec!["LOADC", "$1", "@greeting"],
+            vec![".data"],
+            vec!["big:"],
+            vec![".int", "#-100000"],
+            vec!["greeting:"],
+            vec![".string", r#""Hi\n\"you\"""#],
+            vec!["table:"],
+            vec![".bytes", "#1", "#2"],
+            vec![".code"],
+            vec!["HALT"],
+        ];
+
+        let program = Assembler::new().assemble_parsed_asm(parsed).unwrap();
+
+        assert_eq!(&vec![45, 1, 0, 1, 1, 0, 0, 0], program.bytes());
+        assert_eq!(b"Hi\n\"you\"\0\x01\x02", program.data());
+        assert_eq!(&[Constant::Integer(-100_000), Constant::Address(0), Constant::Address(9)], program.constants());
+        assert_eq!(None, program.symbols().get("greeting"));
+
+        let error = Assembler::new().assemble_parsed_asm(vec![vec![".data"], vec!["HALT"]]).unwrap_err();
+        assert_eq!(AssemblerError::Other(String::from("HALT is not a constant")), error);
+    }
+
+    #[test]
+    pub fn test_picks_the_load_encoding() {
+        let parsed = vec![
+            vec!["LOAD", "$1", "#65535"],
+            vec!["LOAD", "$2", "#-1"],
+            vec!["LOAD", "$3", "#100000"],
+            vec!["LOAD", "$4", "#-100000"],
+            vec!["end:"],
+            vec!["JUMP", "@end"],
+        ];
+
+        let program = Assembler::new().assemble_parsed_asm(parsed).unwrap();
+
+        assert_eq!(&vec![
+            30, 1, 255, 255,
+            46, 2, 255, 255,
+            30, 3, 134, 160,
+            47, 3, 0, 1,
+            30, 4, 121, 96,
+            47, 4, 255, 254,
+            50, 0, 0, 24,
+        ], program.bytes());
+
+        let error = Assembler::new().assemble_parsed_asm(vec![vec!["LOADB", "$1", "#65536"]]).unwrap_err();
+        assert_eq!(AssemblerError::ConstantOutOfRange { instruction: 0, value: 65536 }, error);
+
+        let error = Assembler::new().assemble_parsed_asm(vec![vec!["HALT"], vec!["PUSH", "$-1"]]).unwrap_err();
+        assert_eq!(AssemblerError::RegisterOutOfBounds { instruction: 1, register: -1 }, error);
+    }
+
+    #[test]
+    pub fn test_operands_must_match_the_signature() {
+        let mismatch = |parsed: Vec<Vec<&str>>| Assembler::new().assemble_parsed_asm(parsed).unwrap_err();
+
+        assert_eq!(
+            AssemblerError::OperandMismatch { instruction: 0, operand: String::from("#1") },
+            mismatch(vec![vec!["LOAD", "#1", "$5"]])
+        );
+        assert_eq!(
+            AssemblerError::OperandMismatch { instruction: 1, operand: String::from("$1") },
+            mismatch(vec![vec!["HALT"], vec!["FLOADH", "$1", "#0"]])
+        );
+        assert_eq!(
+            AssemblerError::OperandMismatch { instruction: 0, operand: String::from("#3") },
+            mismatch(vec![vec!["ADD", "$1", "$2", "#3"]])
+        );
+        assert_eq!(
+            AssemblerError::OperandMismatch { instruction: 1, operand: String::from("@start") },
+            mismatch(vec![vec!["start:"], vec!["PUSH", "@start"]])
+        );
+        assert_eq!(
+            AssemblerError::Other(String::from("$7 is not a # constant")),
+            mismatch(vec![vec![".byte", "$7"]])
+        );
+    }
 }
@@ -1,30 +1,67 @@
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_while1};
-use nom::character::complete::{digit1, multispace0, newline, space0, space1};
+use nom::bytes::complete::{tag, take_while, take_while1};
+use nom::character::complete::{anychar, char, digit1, multispace0, newline, none_of, not_line_ending, space0, space1};
 use nom::character::is_alphabetic;
+use nom::combinator::{map, opt, recognize};
 use nom::error::ErrorKind;
 use nom::IResult;
-use nom::multi::{many0, separated_list0, separated_list1};
-use nom::sequence::{delimited, preceded};
+use nom::multi::{many0_count, separated_list0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
 
 use crate::asm::Parsed;
 
 /// Parse any source string into a Parsed vector of strings
 /// Does not actually parse to token enumerations. It simply splits a source into substrings.
 /// The assembler takes these split strings and assembles them into true bytecode
-pub fn parse_asm(s: &str) -> IResult<&str, Parsed, (&str, ErrorKind)> {
-    // separated_list0(many0(newline), line)(s)
-    separated_list0(newline, line)(s)
+/// Lines holding only a comment are left out.
+pub fn parse_asm(s: &str) -> IResult<&str, Parsed<'_>, (&str, ErrorKind)> {
+    map(separated_list0(newline, line), |lines| lines.into_iter().filter(|line| !line.is_empty()).collect())(s)
 }
 
-/// Parse a single line into a vector of tokens
+/// Parse a single line into a vector of tokens. A `//` comment may follow the instruction or fill the line.
 pub fn line(s: &str) -> IResult<&str, Vec<&str>, (&str, ErrorKind)> {
-    delimited(multispace0, instruction_parser, space0)(s)
+    delimited(
+        multispace0,
+        alt((label_definition, instruction_parser, map(comment, |_| Vec::new()))),
+        pair(space0, opt(comment)),
+    )(s)
+}
+
+/// Parse a `//` comment, up to the end of the line
+fn comment(s: &str) -> IResult<&str, &str, (&str, ErrorKind)> {
+    recognize(pair(tag("//"), not_line_ending))(s)
+}
+
+/// Parse a label definition, like `loop:`, into a single token that keeps its colon
+fn label_definition(s: &str) -> IResult<&str, Vec<&str>, (&str, ErrorKind)> {
+    map(recognize(terminated(label_name, char(':'))), |label| vec![label])(s)
+}
+
+/// Parse a label name: a letter or underscore, then letters, digits and underscores
+fn label_name(s: &str) -> IResult<&str, &str, (&str, ErrorKind)> {
+    recognize(pair(
+        take_while1(|c: char| c.is_ascii_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+    ))(s)
 }
 
 /// Parse a single instruction into an operation and operands
 fn instruction_parser(s: &str) -> IResult<&str, Vec<&str>, (&str, ErrorKind)> {
-    separated_list1(space1, alt((operation_keyword, operand_parser)))(s)
+    separated_list1(space1, alt((operation_keyword, operand_parser, label_reference, string_literal)))(s)
+}
+
+/// Parse a double-quoted string on one line, like `"Hello\n"`, into a token that keeps its quotes and escapes
+fn string_literal(s: &str) -> IResult<&str, &str, (&str, ErrorKind)> {
+    recognize(tuple((
+        char('"'),
+        many0_count(alt((recognize(pair(char('\\'), anychar)), recognize(none_of("\"\\\n"))))),
+        char('"'),
+    )))(s)
+}
+
+/// Parse a reference to a label, like `@loop`, into a token that keeps its `@`
+fn label_reference(s: &str) -> IResult<&str, &str, (&str, ErrorKind)> {
+    recognize(preceded(tag("@"), label_name))(s)
 }
 
 /// Parse a single keyword into a keyword token
@@ -37,9 +74,10 @@ fn is_valid_keyword_character(c: char) -> bool {
     is_alphabetic(c as u8) || c == '.' || c == '_'
 }
 
-/// Parse an operand
+/// Parse an operand into a token that keeps its `$`, `%` or `#`, so the assembler can check it against the signature.
+/// Constants may be negative, like `#-5`
 fn operand_parser(s: &str) -> IResult<&str, &str, (&str, ErrorKind)> {
-    preceded(alt((tag("$"), tag("#"))), digit1)(s)
+    recognize(pair(alt((tag("$"), tag("%"), tag("#"))), pair(opt(char('-')), digit1)))(s)
 }
 
 
@@ -76,8 +114,10 @@ mod test {
 
     #[test]
     pub fn test_operand_parser() {
-        assert_eq!(("", "1"), operand_parser("$1").unwrap());
-        assert_eq!(("", "233"), operand_parser("#233").unwrap());
+        assert_eq!(("", "$1"), operand_parser("$1").unwrap());
+        assert_eq!(("", "#233"), operand_parser("#233").unwrap());
+        assert_eq!(("", "%4"), operand_parser("%4").unwrap());
+        assert_eq!(("", "#-100000"), operand_parser("#-100000").unwrap());
 
         assert_eq!(
             operand_parser("^1"),
@@ -87,13 +127,13 @@ mod test {
 
     #[test]
     pub fn test_instruction() {
-        assert_eq!(vec!["LOAD", "0", "500"], instruction_parser("LOAD $0 #500").unwrap().1);
-        assert_eq!(vec!["LOAD", "3", "18"], instruction_parser("LOAD #3 $18").unwrap().1);
+        assert_eq!(vec!["LOAD", "$0", "#500"], instruction_parser("LOAD $0 #500").unwrap().1);
+        assert_eq!(vec!["LOAD", "#3", "$18"], instruction_parser("LOAD #3 $18").unwrap().1);
     }
 
     #[test]
     pub fn test_parse_single_line_instruction() {
-        assert_eq!(vec![vec!["LOAD", "1", "500"]], parse_asm("LOAD $1 #500").unwrap().1);
+        assert_eq!(vec![vec!["LOAD", "$1", "#500"]], parse_asm("LOAD $1 #500").unwrap().1);
     }
 
     #[test]
@@ -113,12 +153,51 @@ DIE #1
 "#;
 
         let expected = vec![
-            vec!["LOAD", "1", "500"],
-            vec!["ADD", "2", "3", "2"],
-            vec!["DIE", "1"],
+            vec!["LOAD", "$1", "#500"],
+            vec!["ADD", "$2", "$3", "$2"],
+            vec!["DIE", "#1"],
             vec!["HALT"],
         ];
 
         assert_eq!(expected, parse_asm(input).unwrap().1);
     }
+
+    #[test]
+    pub fn test_parse_labels_and_comments() {
+        let input = r#"
+// Count down from 100
+loop:
+    SUBW $2 $2 $1       // 0008: 4B 02 02 01
+    JUMPNZ @loop
+.byte #255 #0
+"#;
+
+        let expected = vec![
+            vec!["loop:"],
+            vec!["SUBW", "$2", "$2", "$1"],
+            vec!["JUMPNZ", "@loop"],
+            vec![".byte", "#255", "#0"],
+        ];
+
+        assert_eq!(expected, parse_asm(input).unwrap().1);
+    }
+
+    #[test]
+    pub fn test_parse_data_section() {
+        let input = r#"
+.data
+greeting:
+    .string "Hello, \"world\" // not a comment\n"    // a comment
+    .int #7
+"#;
+
+        let expected = vec![
+            vec![".data"],
+            vec!["greeting:"],
+            vec![".string", r#""Hello, \"world\" // not a comment\n""#],
+            vec![".int", "#7"],
+        ];
+
+        assert_eq!(expected, parse_asm(input).unwrap().1);
+    }
 }
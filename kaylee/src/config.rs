@@ -0,0 +1,338 @@
+//! Options for building a `Kaylee`: how many registers it has, how much memory, its limits,
+//! and which groups of instructions programs may use.
+use std::fmt::{Display, Formatter};
+
+use crate::vm::{Byte, Kaylee};
+
+/// Errors concerning a `VmConfig` that cannot build a VM
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConfigError {
+    /// Register counts must be between `VmConfig::MIN_REGISTER_COUNT` and `VmConfig::MAX_REGISTER_COUNT`
+    RegisterCount(usize),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::RegisterCount(count) => write!(
+                f,
+                "a VM needs between {} and {} registers, not {count}",
+                VmConfig::MIN_REGISTER_COUNT,
+                VmConfig::MAX_REGISTER_COUNT,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A family of instructions, matching the opcode ranges reserved by each module in `instructions`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InstructionGroup {
+    Machine,
+    Data,
+    Program,
+    Math,
+    Compare,
+    Logical,
+    System,
+    Library,
+    Float,
+    Misc,
+}
+
+impl InstructionGroup {
+    pub const ALL: [InstructionGroup; 10] = [
+        InstructionGroup::Machine,
+        InstructionGroup::Data,
+        InstructionGroup::Program,
+        InstructionGroup::Math,
+        InstructionGroup::Compare,
+        InstructionGroup::Logical,
+        InstructionGroup::System,
+        InstructionGroup::Library,
+        InstructionGroup::Float,
+        InstructionGroup::Misc,
+    ];
+
+    /// The group whose reserved range holds an opcode
+    pub fn of(opcode: Byte) -> InstructionGroup {
+        match opcode {
+            0..=29 => InstructionGroup::Machine,
+            30..=49 => InstructionGroup::Data,
+            50..=69 => InstructionGroup::Program,
+            70..=99 => InstructionGroup::Math,
+            100..=119 => InstructionGroup::Compare,
+            120..=129 => InstructionGroup::Logical,
+            130..=179 => InstructionGroup::System,
+            180..=219 => InstructionGroup::Library,
+            220..=239 => InstructionGroup::Float,
+            240..=255 => InstructionGroup::Misc,
+        }
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// A set of InstructionGroups
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct InstructionGroups(u16);
+
+impl Default for InstructionGroups {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl InstructionGroups {
+    pub fn all() -> Self {
+        InstructionGroup::ALL.iter().fold(InstructionGroups::none(), |groups, group| groups.with(*group))
+    }
+
+    pub fn none() -> Self {
+        InstructionGroups(0)
+    }
+
+    pub fn with(self, group: InstructionGroup) -> Self {
+        InstructionGroups(self.0 | group.bit())
+    }
+
+    pub fn without(self, group: InstructionGroup) -> Self {
+        InstructionGroups(self.0 & !group.bit())
+    }
+
+    pub fn contains(&self, group: InstructionGroup) -> bool {
+        self.0 & group.bit() != 0
+    }
+
+    /// True if the group holding the opcode is in the set
+    pub fn allows(&self, opcode: Byte) -> bool {
+        self.contains(InstructionGroup::of(opcode))
+    }
+
+    pub fn to_bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        InstructionGroups(bits & InstructionGroups::all().0)
+    }
+}
+
+/// Builds a `Kaylee` with more options than `Kaylee::new`
+///
+/// ```
+/// use kaylee::config::{InstructionGroup, VmConfig};
+///
+/// let vm = VmConfig::new()
+///     .with_register_count(64)
+///     .with_memory_size(1024)
+///     .with_instruction_budget(10_000)
+///     .without_group(InstructionGroup::System)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(64, vm.register_count());
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VmConfig {
+    pub(crate) register_count: usize,
+    pub(crate) memory_size: usize,
+    pub(crate) max_stack_depth: usize,
+    pub(crate) instruction_budget: Option<u64>,
+    pub(crate) groups: InstructionGroups,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VmConfig {
+    /// Trap handlers receive their fault in the last two registers, so every VM has at least two
+    pub const MIN_REGISTER_COUNT: usize = 2;
+    /// RegisterIds are a single byte
+    pub const MAX_REGISTER_COUNT: usize = 256;
+
+    /// The configuration `Kaylee::new` uses
+    pub fn new() -> Self {
+        VmConfig {
+            register_count: Kaylee::REGISTER_COUNT,
+            memory_size: Kaylee::DEFAULT_MEMORY_SIZE,
+            max_stack_depth: Kaylee::DEFAULT_STACK_DEPTH,
+            instruction_budget: None,
+            groups: InstructionGroups::all(),
+        }
+    }
+
+    pub fn with_register_count(mut self, count: usize) -> Self {
+        self.register_count = count;
+        self
+    }
+
+    pub fn with_memory_size(mut self, size: usize) -> Self {
+        self.memory_size = size;
+        self
+    }
+
+    pub fn with_max_stack_depth(mut self, depth: usize) -> Self {
+        self.max_stack_depth = depth;
+        self
+    }
+
+    /// Every instruction the VM executes counts against this budget, across every run.
+    /// Once it is used up, `run_metered` and `run_async` stop with `RunOutcome::BudgetExhausted`
+    pub fn with_instruction_budget(mut self, instructions: u64) -> Self {
+        self.instruction_budget = Some(instructions);
+        self
+    }
+
+    /// Only allow instructions from these groups. Executing any other stops the VM with `DisabledInstruction`
+    pub fn with_groups(mut self, groups: InstructionGroups) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    pub fn without_group(mut self, group: InstructionGroup) -> Self {
+        self.groups = self.groups.without(group);
+        self
+    }
+
+    pub fn register_count(&self) -> usize {
+        self.register_count
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.memory_size
+    }
+
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    pub fn instruction_budget(&self) -> Option<u64> {
+        self.instruction_budget
+    }
+
+    pub fn groups(&self) -> InstructionGroups {
+        self.groups
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(VmConfig::MIN_REGISTER_COUNT..=VmConfig::MAX_REGISTER_COUNT).contains(&self.register_count) {
+            return Err(ConfigError::RegisterCount(self.register_count));
+        }
+
+        Ok(())
+    }
+
+    /// Build a VM with this configuration
+    pub fn build(self) -> Result<Kaylee, ConfigError> {
+        Kaylee::from_config(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ConfigError, InstructionGroup, InstructionGroups, VmConfig};
+    use crate::gas::Gas;
+    use crate::instructions::data::Load;
+    use crate::instructions::float::FloatLoadHigh;
+    use crate::instructions::machine::Halt;
+    use crate::instructions::math::Divide;
+    use crate::instructions::program::Trap;
+    use crate::instructions::system::Print;
+    use crate::program::Program;
+    use crate::vm::{ExecutionError, ExitStatus, Kaylee, RunOutcome};
+
+    #[test]
+    fn test_builds_a_vm_from_the_config() {
+        let vm = VmConfig::new()
+            .with_register_count(64)
+            .with_memory_size(128)
+            .with_max_stack_depth(3)
+            .build()
+            .unwrap();
+
+        assert_eq!(64, vm.register_count());
+        assert_eq!(128, vm.memory().len());
+        assert_eq!(VmConfig::new().with_register_count(64).with_memory_size(128).with_max_stack_depth(3), vm.config());
+        assert_eq!(VmConfig::new(), Kaylee::new().config());
+
+        assert_eq!(Err(ConfigError::RegisterCount(1)), VmConfig::new().with_register_count(1).validate());
+        assert_eq!(Ok(()), VmConfig::new().with_register_count(2).validate());
+        assert!(VmConfig::new().with_register_count(257).build().is_err());
+    }
+
+    #[test]
+    fn test_registers_past_the_configured_count() {
+        let program = Program::from(vec![Load::OPCODE, 40, 0, 7]);
+
+        let mut vm = Kaylee::new();
+        assert_eq!(ExecutionError::RegisterOutOfBounds(40), vm.run(program.clone()).unwrap_err().error);
+
+        let mut vm = VmConfig::new().with_register_count(41).build().unwrap();
+        vm.run(program).unwrap();
+        assert_eq!(7, vm.register(40).unwrap());
+    }
+
+    #[test]
+    fn test_fault_registers_follow_the_register_count() {
+        let program = Program::from(vec![
+            Trap::OPCODE, 4, 0, 12,         // 0: TRAP #4 #12
+            Divide::OPCODE, 0, 0, 1,        // 4: DIV $0 $0 $1
+            Halt::OPCODE, 0, 0, 0,          // 8: HALT
+            Halt::OPCODE, 3, 0, 0,          // 12: HALT #3
+        ]);
+
+        let mut vm = VmConfig::new().with_register_count(8).build().unwrap();
+
+        assert_eq!(ExitStatus::Halted(3), vm.run(program).unwrap());
+        assert_eq!((6, 7), (vm.fault_code_register(), vm.fault_index_register()));
+        assert_eq!(ExecutionError::DivideByZero.code() as i32, vm.register(6).unwrap());
+        assert_eq!(4, vm.register(7).unwrap());
+    }
+
+    #[test]
+    fn test_instruction_budget() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 1,          // LOAD $1 #1
+            Load::OPCODE, 2, 0, 2,          // LOAD $2 #2
+            Halt::OPCODE, 0, 0, 0,          // HALT
+        ]);
+
+        let mut vm = VmConfig::new().with_instruction_budget(2).build().unwrap();
+
+        assert_eq!(RunOutcome::BudgetExhausted, vm.run_metered(&program, &mut Gas::new(100)).unwrap());
+        assert_eq!(8, vm.program_counter());
+        assert_eq!(2, vm.register(2).unwrap());
+
+        // The budget is the VM's, so more gas does not buy more instructions
+        let mut gas = Gas::new(100);
+        assert_eq!(RunOutcome::BudgetExhausted, vm.run_metered(&program, &mut gas).unwrap());
+        assert_eq!(100, gas.remaining());
+    }
+
+    #[test]
+    fn test_disabled_instruction_groups() {
+        let mut vm = VmConfig::new()
+            .without_group(InstructionGroup::System)
+            .without_group(InstructionGroup::Float)
+            .build()
+            .unwrap();
+
+        let error = vm.run(Program::from(vec![Print::OPCODE, 1, 0, 0])).unwrap_err();
+        assert_eq!(ExecutionError::DisabledInstruction(Print::OPCODE), error.error);
+
+        let error = vm.run(Program::from(vec![FloatLoadHigh::OPCODE, 1, 0, 0])).unwrap_err();
+        assert_eq!(ExecutionError::DisabledInstruction(FloatLoadHigh::OPCODE), error.error);
+
+        let groups = InstructionGroups::none().with(InstructionGroup::Data);
+        assert!(groups.allows(Load::OPCODE));
+        assert!(!groups.allows(Halt::OPCODE));
+        assert_eq!(groups, InstructionGroups::from_bits(groups.to_bits()));
+    }
+}
@@ -0,0 +1,318 @@
+//! Handles for controlling a `Kaylee` running in an async task with `Kaylee::run_async`.
+//! Both handles can be cloned and used from any thread. Neither depends on a particular async runtime.
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::task::{Context, Poll, Waker};
+
+/// A flag that wakes the tasks waiting on it when it changes.
+/// Each waiter keeps at most one Waker here, and removes it when it stops waiting,
+/// so a long-lived signal does not collect the Wakers of runs that are long gone.
+#[derive(Debug, Default)]
+struct Signal {
+    set: AtomicBool,
+    next_waiter: AtomicU64,
+    wakers: Mutex<BTreeMap<u64, Waker>>,
+}
+
+impl Signal {
+    fn is_set(&self) -> bool {
+        self.set.load(Ordering::SeqCst)
+    }
+
+    fn store(&self, value: bool) {
+        self.set.store(value, Ordering::SeqCst);
+
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        for waker in wakers.into_values() {
+            waker.wake();
+        }
+    }
+
+    /// A new id to register Wakers under
+    fn waiter(&self) -> u64 {
+        self.next_waiter.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Wake `waker` on the next change, instead of any Waker the waiter registered before
+    fn register(&self, waiter: u64, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !wakers.get(&waiter).is_some_and(|registered| registered.will_wake(waker)) {
+            wakers.insert(waiter, waker.clone());
+        }
+    }
+
+    fn deregister(&self, waiter: u64) {
+        self.wakers.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&waiter);
+    }
+}
+
+/// Stops a `run_async` at the next instruction boundary. One token can cancel many runs.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    signal: Arc<Signal>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.signal.store(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.signal.is_set()
+    }
+}
+
+/// Pauses and resumes a VM's `run_async`. Get one with `Kaylee::pause_handle` before starting the run.
+/// A paused run waits without using its executor thread until it is resumed or cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle {
+    signal: Arc<Signal>,
+}
+
+impl PauseHandle {
+    pub fn pause(&self) {
+        self.signal.store(true);
+    }
+
+    pub fn resume(&self) {
+        self.signal.store(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.signal.is_set()
+    }
+}
+
+/// Completes once the run is resumed or cancelled. Its Wakers are removed from both handles when it is dropped.
+pub(crate) struct WhilePaused<'a> {
+    pause: &'a PauseHandle,
+    token: &'a CancellationToken,
+    pause_waiter: u64,
+    token_waiter: u64,
+}
+
+impl<'a> WhilePaused<'a> {
+    pub(crate) fn new(pause: &'a PauseHandle, token: &'a CancellationToken) -> Self {
+        WhilePaused { pause, token, pause_waiter: pause.signal.waiter(), token_waiter: token.signal.waiter() }
+    }
+}
+
+impl Drop for WhilePaused<'_> {
+    fn drop(&mut self) {
+        self.pause.signal.deregister(self.pause_waiter);
+        self.token.signal.deregister(self.token_waiter);
+    }
+}
+
+impl Future for WhilePaused<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.pause.is_paused() || self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        self.pause.signal.register(self.pause_waiter, cx.waker());
+        self.token.signal.register(self.token_waiter, cx.waker());
+
+        // Check again, in case the handles changed while the waker was being registered
+        match !self.pause.is_paused() || self.token.is_cancelled() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+}
+
+/// Gives the executor a chance to run other tasks, once
+#[derive(Default)]
+pub(crate) struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+    use std::time::Duration;
+
+    use crate::config::VmConfig;
+    use crate::control::{CancellationToken, PauseHandle, WhilePaused};
+    use crate::instructions::data::Load;
+    use crate::instructions::machine::Halt;
+    use crate::instructions::math::Add;
+    use crate::instructions::program::JumpBackward;
+    use crate::program::Program;
+    use crate::vm::{ExitStatus, Kaylee, RunOutcome};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// A minimal executor: poll on this thread, parking until woken. Returns the output and how many times it was pending.
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut pending = 0;
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, pending),
+                Poll::Pending => {
+                    pending += 1;
+                    thread::park();
+                }
+            }
+        }
+    }
+
+    fn counter() -> Program {
+        Program::from(vec![
+            Load::OPCODE, 1, 0, 1,          // 0: LOAD $1 #1
+            Add::OPCODE, 2, 2, 1,           // 4: ADD $2 $2 $1
+            Add::OPCODE, 2, 2, 1,           // 8: ADD $2 $2 $1
+            Add::OPCODE, 2, 2, 1,           // 12: ADD $2 $2 $1
+            Halt::OPCODE, 0, 0, 0,          // 16: HALT
+        ])
+    }
+
+    fn spin() -> Program {
+        Program::from(vec![
+            Add::OPCODE, 2, 2, 1,           // 0: ADD $2 $2 $1
+            JumpBackward::OPCODE, 0, 0, 1,  // 4: JUMPB #1
+        ])
+    }
+
+    #[test]
+    fn test_yields_every_n_instructions() {
+        let mut vm = Kaylee::new();
+        let token = CancellationToken::new();
+
+        let (outcome, pending) = block_on(vm.run_async(&counter(), 2, &token));
+
+        assert_eq!(RunOutcome::Exited(ExitStatus::Halted(0)), outcome.unwrap());
+        assert_eq!(3, vm.register(2).unwrap());
+        assert_eq!(2, pending);
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let mut vm = Kaylee::new();
+        let token = CancellationToken::new();
+
+        let canceller = token.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+
+        let (outcome, _) = block_on(vm.run_async(&spin(), 100, &token));
+        handle.join().unwrap();
+
+        assert_eq!(RunOutcome::Cancelled, outcome.unwrap());
+        assert!(vm.instructions_executed() > 0);
+        assert!(!vm.is_halted());
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut vm = Kaylee::new();
+        let token = CancellationToken::new();
+        let pause = vm.pause_handle();
+        pause.pause();
+
+        let resumer = pause.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            resumer.resume();
+        });
+
+        let (outcome, pending) = block_on(vm.run_async(&counter(), 100, &token));
+        handle.join().unwrap();
+
+        assert_eq!(RunOutcome::Exited(ExitStatus::Halted(0)), outcome.unwrap());
+        assert!(pending >= 1);
+        assert!(!pause.is_paused());
+    }
+
+    #[test]
+    fn test_cancelling_a_paused_run() {
+        let mut vm = Kaylee::new();
+        let token = CancellationToken::new();
+        vm.pause_handle().pause();
+
+        let canceller = token.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+
+        let (outcome, _) = block_on(vm.run_async(&counter(), 100, &token));
+        handle.join().unwrap();
+
+        assert_eq!(RunOutcome::Cancelled, outcome.unwrap());
+        assert_eq!(0, vm.program_counter());
+    }
+
+    #[test]
+    fn test_instruction_budget_stops_the_run() {
+        let mut vm = VmConfig::new().with_instruction_budget(50).build().unwrap();
+        vm.set_register(1, 1).unwrap();
+        let token = CancellationToken::new();
+
+        let (outcome, _) = block_on(vm.run_async(&spin(), 100, &token));
+
+        assert_eq!(RunOutcome::BudgetExhausted, outcome.unwrap());
+        assert_eq!(50, vm.instructions_executed());
+        assert_eq!(25, vm.register(2).unwrap());
+    }
+
+    #[test]
+    fn test_waiting_does_not_leave_wakers_behind() {
+        let pause = PauseHandle::default();
+        let token = CancellationToken::new();
+        pause.pause();
+
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        // Many paused runs with the same long-lived handles, each given up while it waits
+        for _ in 0..10 {
+            let mut waiting = pin!(WhilePaused::new(&pause, &token));
+            assert!(waiting.as_mut().poll(&mut cx).is_pending());
+            assert!(waiting.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(1, token.signal.wakers.lock().unwrap().len());
+        }
+
+        assert!(pause.signal.wakers.lock().unwrap().is_empty());
+        assert!(token.signal.wakers.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,404 @@
+//! Drives a `Kaylee` instance one instruction at a time for tooling.
+//! Breakpoints stop execution *before* the instruction at a ProgramIndex runs.
+//! Watchpoints stop execution *after* an instruction writes a watched register, even when it writes the value
+//! the register already held. Each thread has its own registers, and switching threads is not a write.
+//! With a VM built `with_undo_log`, the Debugger can also run backwards. Reversing stops *before* the instruction
+//! that wrote a watched register, with `old` and `new` being the values before and after it ran.
+use std::collections::BTreeSet;
+
+use crate::instructions::program::Call;
+use crate::program::{Program, ProgramIndex};
+use crate::vm::{Kaylee, RegisterId, RegisterValue, RegisterWrite, RuntimeError};
+
+/// Why the Debugger handed control back to the caller
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StopReason {
+    /// A single step completed without hitting anything else
+    Step,
+    /// The program counter reached a breakpoint. The instruction has not been executed yet.
+    Breakpoint(ProgramIndex),
+    /// An instruction wrote a watched register
+    Watchpoint {
+        register: RegisterId,
+        old: RegisterValue,
+        new: RegisterValue,
+    },
+    /// The VM executed a `HALT`
+    Halted,
+    /// The program counter moved past the final instruction
+    Finished,
+    /// An instruction faulted. The VM is left as it was when the fault happened.
+    Fault(RuntimeError),
+    /// Running backwards reached the oldest instruction in the undo log
+    StartOfHistory,
+}
+
+pub struct Debugger {
+    vm: Kaylee,
+    program: Program,
+    breakpoints: BTreeSet<ProgramIndex>,
+    watchpoints: BTreeSet<RegisterId>,
+}
+
+impl Debugger {
+    /// Debug a Program from the VM's program counter. `Kaylee::load` the Program into the VM first
+    /// if it has a data section, constant pool or entry point.
+    pub fn new(mut vm: Kaylee, program: Program) -> Self {
+        vm.log_register_writes();
+
+        Debugger {
+            vm,
+            program,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn vm(&self) -> &Kaylee {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut Kaylee {
+        &mut self.vm
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Give back the VM and Program being debugged
+    pub fn into_inner(self) -> (Kaylee, Program) {
+        (self.vm, self.program)
+    }
+
+    pub fn add_breakpoint(&mut self, index: ProgramIndex) {
+        self.breakpoints.insert(index);
+    }
+
+    /// Returns false if there was no breakpoint at the ProgramIndex
+    pub fn remove_breakpoint(&mut self, index: ProgramIndex) -> bool {
+        self.breakpoints.remove(&index)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item=&ProgramIndex> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_watchpoint(&mut self, register: RegisterId) {
+        self.watchpoints.insert(register);
+    }
+
+    /// Returns false if the register was not being watched
+    pub fn remove_watchpoint(&mut self, register: RegisterId) -> bool {
+        self.watchpoints.remove(&register)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item=&RegisterId> {
+        self.watchpoints.iter()
+    }
+
+    /// Execute exactly one instruction, following jumps and calls
+    pub fn step_into(&mut self) -> StopReason {
+        if self.vm.is_halted() {
+            return StopReason::Halted;
+        }
+
+        // Leave out writes made outside the debugger, through `vm_mut`
+        self.vm.take_register_writes();
+
+        match self.vm.step(&self.program) {
+            Err(error) => return StopReason::Fault(error),
+            Ok(None) => return StopReason::Finished,
+            Ok(Some(_)) => {}
+        }
+
+        if let Some(reason) = Debugger::watchpoint(&self.vm.take_register_writes(), |register| self.watchpoints.contains(&register)) {
+            return reason;
+        }
+
+        if self.vm.is_halted() {
+            return StopReason::Halted;
+        }
+
+        StopReason::Step
+    }
+
+    /// Execute one instruction. If it is a `CALL`, keep going until the subroutine returns.
+    /// Breakpoints and watchpoints inside the subroutine still stop execution.
+    pub fn step_over(&mut self) -> StopReason {
+        let index = self.vm.program_counter();
+        if index >= self.program.len() || self.program[index] != Call::OPCODE {
+            return self.step_into();
+        }
+
+        // Every instruction is four bytes, so the CALL returns to the next one
+        let depth = self.vm.stack().len();
+        let return_index = index + 4;
+
+        let mut reason = self.step_into();
+        while reason == StopReason::Step {
+            if self.vm.program_counter() == return_index && self.vm.stack().len() == depth {
+                break;
+            }
+
+            reason = self.stop_at_breakpoint().unwrap_or_else(|| self.step_into());
+        }
+
+        reason
+    }
+
+    /// Run until a breakpoint or watchpoint is hit, or the program stops.
+    /// A breakpoint at the current ProgramIndex is stepped over, so execution can continue from it.
+    pub fn continue_execution(&mut self) -> StopReason {
+        let mut reason = self.step_into();
+        while reason == StopReason::Step {
+            reason = self.stop_at_breakpoint().unwrap_or_else(|| self.step_into());
+        }
+
+        reason
+    }
+
+    /// Undo the most recent instruction
+    pub fn step_back(&mut self) -> StopReason {
+        match self.vm.step_back() {
+            true => StopReason::Step,
+            false => StopReason::StartOfHistory,
+        }
+    }
+
+    /// Run backwards until a breakpoint is reached, or an instruction that wrote a watched register is undone.
+    /// A breakpoint at the current ProgramIndex is stepped over, so reversing can continue from it.
+    pub fn reverse_continue(&mut self) -> StopReason {
+        loop {
+            let writes = self.vm.last_register_writes().to_vec();
+
+            if !self.vm.step_back() {
+                return StopReason::StartOfHistory;
+            }
+
+            if let Some(reason) = Debugger::watchpoint(&writes, |register| self.watchpoints.contains(&register)) {
+                return reason;
+            }
+
+            if let Some(reason) = self.stop_at_breakpoint() {
+                return reason;
+            }
+        }
+    }
+
+    /// Run backwards to just before the most recent instruction that wrote a register
+    pub fn reverse_to_last_write(&mut self, register: RegisterId) -> StopReason {
+        loop {
+            let writes = self.vm.last_register_writes().to_vec();
+
+            if !self.vm.step_back() {
+                return StopReason::StartOfHistory;
+            }
+
+            if let Some(reason) = Debugger::watchpoint(&writes, |written| written == register) {
+                return reason;
+            }
+        }
+    }
+
+    fn stop_at_breakpoint(&self) -> Option<StopReason> {
+        let index = self.vm.program_counter();
+        self.breakpoints.contains(&index).then_some(StopReason::Breakpoint(index))
+    }
+
+    /// A Watchpoint for the first watched register in one instruction's writes,
+    /// from its value before the instruction wrote it to the last value the instruction wrote
+    fn watchpoint(writes: &[RegisterWrite], watched: impl Fn(RegisterId) -> bool) -> Option<StopReason> {
+        let first = writes.iter().find(|write| watched(write.register))?;
+        let last = writes.iter().rev().find(|write| write.register == first.register)?;
+
+        Some(StopReason::Watchpoint { register: first.register, old: first.old, new: last.new })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::{Debugger, StopReason};
+    use crate::instructions::data::Load;
+    use crate::instructions::machine::{Halt, Spawn, Yield};
+    use crate::instructions::math::{Add, Divide};
+    use crate::instructions::program::{Call, Return};
+    use crate::program::Program;
+    use crate::threads::MAIN_THREAD;
+    use crate::vm::{ExecutionError, Kaylee};
+
+    fn program() -> Program {
+        Program::from(vec![
+            Load::OPCODE, 1, 0, 10,     // 0: LOAD $1 #10
+            Call::OPCODE, 0, 0, 20,     // 4: CALL #20
+            Load::OPCODE, 3, 0, 1,      // 8: LOAD $3 #1
+            Halt::OPCODE, 0, 0, 0,      // 12: HALT
+            Load::OPCODE, 4, 0, 1,      // 16: LOAD $4 #1
+            Add::OPCODE, 2, 2, 1,       // 20: ADD $2 $2 $1
+            Add::OPCODE, 2, 2, 1,       // 24: ADD $2 $2 $1
+            Return::OPCODE, 0, 0, 0,    // 28: RET
+        ])
+    }
+
+    #[test]
+    fn test_step_into_follows_calls() {
+        let mut debugger = Debugger::new(Kaylee::new(), program());
+
+        assert_eq!(StopReason::Step, debugger.step_into());
+        assert_eq!(StopReason::Step, debugger.step_into());
+        assert_eq!(20, debugger.vm().program_counter());
+    }
+
+    #[test]
+    fn test_step_over_runs_the_whole_call() {
+        let mut debugger = Debugger::new(Kaylee::new(), program());
+
+        assert_eq!(StopReason::Step, debugger.step_over());
+        assert_eq!(StopReason::Step, debugger.step_over());
+        assert_eq!(8, debugger.vm().program_counter());
+        assert_eq!(20, debugger.vm().register(2).unwrap());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = Debugger::new(Kaylee::new(), program());
+        debugger.add_breakpoint(24);
+        debugger.add_breakpoint(8);
+
+        assert_eq!(StopReason::Breakpoint(24), debugger.continue_execution());
+        assert_eq!(10, debugger.vm().register(2).unwrap());
+
+        assert_eq!(StopReason::Breakpoint(8), debugger.continue_execution());
+        assert_eq!(20, debugger.vm().register(2).unwrap());
+
+        assert_eq!(StopReason::Halted, debugger.continue_execution());
+        assert_eq!(0, debugger.vm().register(4).unwrap());
+        assert_eq!(StopReason::Halted, debugger.step_into());
+    }
+
+    #[test]
+    fn test_step_over_stops_at_breakpoints_inside_the_call() {
+        let mut debugger = Debugger::new(Kaylee::new(), program());
+        debugger.add_breakpoint(28);
+
+        debugger.step_over();
+        assert_eq!(StopReason::Breakpoint(28), debugger.step_over());
+        assert!(debugger.remove_breakpoint(28));
+        assert!(!debugger.remove_breakpoint(28));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = Debugger::new(Kaylee::new(), program());
+        debugger.add_watchpoint(2);
+
+        assert_eq!(StopReason::Watchpoint { register: 2, old: 0, new: 10 }, debugger.continue_execution());
+        assert_eq!(24, debugger.vm().program_counter());
+
+        assert_eq!(StopReason::Watchpoint { register: 2, old: 10, new: 20 }, debugger.continue_execution());
+        assert_eq!(StopReason::Halted, debugger.continue_execution());
+    }
+
+    #[test]
+    fn test_watchpoints_stop_on_writes_of_the_same_value() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 5,      // 0: LOAD $1 #5
+            Load::OPCODE, 1, 0, 5,      // 4: LOAD $1 #5
+            Halt::OPCODE, 0, 0, 0,      // 8: HALT
+        ]);
+
+        let mut debugger = Debugger::new(Kaylee::new().with_undo_log(100), program);
+        debugger.add_watchpoint(1);
+
+        assert_eq!(StopReason::Watchpoint { register: 1, old: 0, new: 5 }, debugger.continue_execution());
+        assert_eq!(StopReason::Watchpoint { register: 1, old: 5, new: 5 }, debugger.continue_execution());
+        assert_eq!(8, debugger.vm().program_counter());
+
+        assert_eq!(StopReason::Watchpoint { register: 1, old: 5, new: 5 }, debugger.reverse_continue());
+        assert_eq!(4, debugger.vm().program_counter());
+        assert_eq!(StopReason::Watchpoint { register: 1, old: 0, new: 5 }, debugger.reverse_to_last_write(1));
+        assert_eq!(0, debugger.vm().program_counter());
+    }
+
+    #[test]
+    fn test_watchpoints_ignore_thread_switches() {
+        let program = Program::from(vec![
+            Spawn::OPCODE, 10, 0, 20,   // 0: SPAWN $10 #20
+            Load::OPCODE, 2, 0, 9,      // 4: LOAD $2 #9
+            Yield::OPCODE, 0, 0, 0,     // 8: YIELD
+            Yield::OPCODE, 0, 0, 0,     // 12: YIELD
+            Halt::OPCODE, 0, 0, 0,      // 16: HALT
+            Yield::OPCODE, 0, 0, 0,     // 20: YIELD
+            Load::OPCODE, 2, 0, 1,      // 24: LOAD $2 #1, then the thread runs off the end
+        ]);
+
+        let mut debugger = Debugger::new(Kaylee::new().with_undo_log(100), program);
+        debugger.add_watchpoint(2);
+
+        assert_eq!(StopReason::Watchpoint { register: 2, old: 0, new: 9 }, debugger.continue_execution());
+        assert_eq!(StopReason::Watchpoint { register: 2, old: 0, new: 1 }, debugger.continue_execution());
+        assert_ne!(MAIN_THREAD, debugger.vm().current_thread());
+        assert_eq!(StopReason::Halted, debugger.continue_execution());
+
+        assert_eq!(StopReason::Watchpoint { register: 2, old: 0, new: 1 }, debugger.reverse_continue());
+        assert_eq!(24, debugger.vm().program_counter());
+        assert_eq!(StopReason::Watchpoint { register: 2, old: 0, new: 9 }, debugger.reverse_continue());
+        assert_eq!(4, debugger.vm().program_counter());
+        assert_eq!(MAIN_THREAD, debugger.vm().current_thread());
+    }
+
+    #[test]
+    fn test_step_back_and_reverse_continue() {
+        let mut debugger = Debugger::new(Kaylee::new().with_undo_log(100), program());
+        assert_eq!(StopReason::Halted, debugger.continue_execution());
+
+        assert_eq!(StopReason::Step, debugger.step_back());
+        assert_eq!(12, debugger.vm().program_counter());
+        assert!(!debugger.vm().is_halted());
+
+        debugger.add_breakpoint(24);
+        assert_eq!(StopReason::Breakpoint(24), debugger.reverse_continue());
+        assert_eq!(10, debugger.vm().register(2).unwrap());
+
+        debugger.add_watchpoint(1);
+        assert_eq!(StopReason::Watchpoint { register: 1, old: 0, new: 10 }, debugger.reverse_continue());
+        assert_eq!(0, debugger.vm().program_counter());
+        assert_eq!(StopReason::StartOfHistory, debugger.reverse_continue());
+
+        let mut debugger = Debugger::new(Kaylee::new(), program());
+        debugger.step_into();
+        assert_eq!(StopReason::StartOfHistory, debugger.step_back());
+    }
+
+    #[test]
+    fn test_reverse_to_last_write() {
+        let mut debugger = Debugger::new(Kaylee::new().with_undo_log(100), program());
+        debugger.continue_execution();
+
+        // The second ADD was the last to change $2
+        assert_eq!(StopReason::Watchpoint { register: 2, old: 10, new: 20 }, debugger.reverse_to_last_write(2));
+        assert_eq!(24, debugger.vm().program_counter());
+
+        assert_eq!(StopReason::Watchpoint { register: 2, old: 0, new: 10 }, debugger.reverse_to_last_write(2));
+        assert_eq!(20, debugger.vm().program_counter());
+
+        assert_eq!(StopReason::StartOfHistory, debugger.reverse_to_last_write(2));
+    }
+
+    #[test]
+    fn test_finished_and_faults() {
+        let mut debugger = Debugger::new(Kaylee::new(), Program::from(vec![
+            Load::OPCODE, 1, 0, 10,
+        ]));
+        assert_eq!(StopReason::Finished, debugger.continue_execution());
+
+        let mut debugger = Debugger::new(Kaylee::new(), Program::from(vec![
+            Divide::OPCODE, 1, 1, 2,
+        ]));
+
+        match debugger.continue_execution() {
+            StopReason::Fault(error) => assert_eq!(ExecutionError::DivideByZero, error.error),
+            reason => panic!("Expected a fault, got {reason:?}"),
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Turns any bytecode back into Kaylee assembly that assembles to the same bytes.
+//! The program is read four bytes at a time. Each line ends with a comment holding its address and raw bytes.
+//! Bytes that are not a valid instruction, or that the assembler would encode differently
+//! (non-zero padding, registers the VM does not have, a cut short final instruction) become a `.byte` line.
+//! Constant jump targets that land on the start of a line get a synthesized label, like `L0010`.
+use std::collections::BTreeSet;
+
+use crate::asm::assembler::Assembler;
+use crate::config::VmConfig;
+use crate::instructions::{decode_operation, Operation, OperandType, OperandValues, signature_length, target_operand};
+use crate::program::{Program, ProgramIndex};
+use crate::vm::{Byte, Kaylee};
+
+/// One line of the listing: an instruction, or bytes to place as they are
+struct Line<'a> {
+    offset: ProgramIndex,
+    bytes: &'a [Byte],
+    instruction: Option<(Operation, OperandValues)>,
+}
+
+impl Line<'_> {
+    /// The constant ProgramIndex the instruction may jump to
+    fn target(&self) -> Option<ProgramIndex> {
+        let (_, operand_values) = self.instruction?;
+        target_operand(self.bytes[0]).map(|operand| operand_values[operand].as_program_index())
+    }
+}
+
+pub struct Disassembler {
+    register_count: usize,
+}
+
+impl Default for Disassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Disassembler {
+    pub fn new() -> Self {
+        Disassembler::for_config(&VmConfig::new())
+    }
+
+    /// A disassembler for programs that run on a configured VM, which may have more registers
+    pub fn for_config(config: &VmConfig) -> Self {
+        Disassembler { register_count: config.register_count() }
+    }
+
+    /// A listing of `bytes`, one line per four bytes, with labels for the constant jump targets
+    pub fn disassemble(&self, bytes: &[Byte]) -> String {
+        let program = Program::from(bytes.to_vec());
+        let lines = (0..bytes.len()).step_by(4).map(|offset| self.line(&program, offset)).collect::<Vec<_>>();
+
+        // A label can go before any line, or after the last one
+        let labels = lines.iter()
+            .filter_map(Line::target)
+            .filter(|target| (target % 4 == 0 && *target < bytes.len()) || *target == bytes.len())
+            .collect::<BTreeSet<_>>();
+
+        let mut output = String::new();
+        for line in &lines {
+            if labels.contains(&line.offset) {
+                output.push_str(&format!("{}:\n", Disassembler::label(line.offset)));
+            }
+
+            let text = match line.instruction {
+                Some((operation, operand_values)) => Disassembler::instruction(line.bytes[0], operation, operand_values, &labels),
+                None => {
+                    let values = line.bytes.iter().map(|byte| format!(" #{byte}")).collect::<String>();
+                    format!("{}{values}", Assembler::BYTE_DIRECTIVE)
+                }
+            };
+
+            let hex = line.bytes.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<_>>().join(" ");
+            output.push_str(&format!("    {text:<24}// {:04X}: {hex}\n", line.offset));
+        }
+
+        if labels.contains(&bytes.len()) {
+            output.push_str(&format!("{}:\n", Disassembler::label(bytes.len())));
+        }
+
+        output
+    }
+
+    /// Decode the line at `offset`, leaving out the instruction if it would not assemble back to the same bytes
+    fn line<'a>(&self, program: &'a Program, offset: ProgramIndex) -> Line<'a> {
+        let end = program.len().min(offset + 4);
+        let bytes = &program.bytes()[offset..end];
+
+        let mut program_counter = offset;
+        let instruction = match decode_operation(program, &mut program_counter) {
+            Some(Ok(decoded)) if bytes.len() == 4 && self.reassembles(bytes, decoded) => Some(decoded),
+            _ => None,
+        };
+
+        Line { offset, bytes, instruction }
+    }
+
+    /// True if the padding is zero and every register exists, which is what the assembler produces
+    fn reassembles(&self, bytes: &[Byte], (operation, operand_values): (Operation, OperandValues)) -> bool {
+        let signature = operation.signature();
+
+        if bytes[1 + signature_length(&signature)..].iter().any(|byte| *byte != 0) {
+            return false;
+        }
+
+        signature.operands.iter().zip(operand_values).all(|(operand, value)| match operand {
+            OperandType::RegisterId => value.as_register_id() < self.register_count,
+            OperandType::FloatRegisterId => value.as_register_id() < Kaylee::FLOAT_REGISTER_COUNT,
+            _ => true,
+        })
+    }
+
+    /// The assembly for an instruction, with its constant jump target replaced by a label if it has one
+    fn instruction(opcode: Byte, operation: Operation, operand_values: OperandValues, labels: &BTreeSet<ProgramIndex>) -> String {
+        let signature = operation.signature();
+        let mut text = signature.identifier.clone();
+
+        for (index, (operand, value)) in signature.operands.iter().zip(operand_values).enumerate() {
+            let operand = match operand {
+                OperandType::None => continue,
+                OperandType::RegisterId => format!(" ${}", value.as_register_id()),
+                OperandType::FloatRegisterId => format!(" %{}", value.as_register_id()),
+                _ if target_operand(opcode) == Some(index) && labels.contains(&value.as_program_index()) => {
+                    format!(" @{}", Disassembler::label(value.as_program_index()))
+                }
+                _ => format!(" #{}", value.as_constant_value()),
+            };
+
+            text.push_str(&operand);
+        }
+
+        text
+    }
+
+    fn label(index: ProgramIndex) -> String {
+        format!("L{index:04X}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::Source;
+    use crate::config::VmConfig;
+    use crate::disassembler::Disassembler;
+    use crate::instructions::data::Load;
+    use crate::instructions::machine::Halt;
+    use crate::instructions::math::{Add, WrappingSubtract};
+    use crate::instructions::program::{Jump, JumpNotZero};
+    use crate::program::Program;
+
+    fn reassemble(text: &str) -> Vec<u8> {
+        Program::try_from(Source::from(String::from(text))).unwrap().bytes().clone()
+    }
+
+    #[test]
+    fn test_listing_with_labels() {
+        let bytes = vec![
+            Load::OPCODE, 1, 0, 1,              // 0: LOAD $1 #1
+            Load::OPCODE, 2, 0, 100,            // 4: LOAD $2 #100
+            Add::OPCODE, 3, 3, 2,               // 8: ADD $3 $3 $2
+            WrappingSubtract::OPCODE, 2, 2, 1,  // 12: SUBW $2 $2 $1
+            JumpNotZero::OPCODE, 0, 0, 8,       // 16: JUMPNZ #8
+            Jump::OPCODE, 0, 0, 28,             // 20: JUMP #28
+            255, 1, 2, 3,                       // 24: not an instruction
+        ];
+
+        let expected = concat!(
+            "    LOAD $1 #1              // 0000: 1E 01 00 01\n",
+            "    LOAD $2 #100            // 0004: 1E 02 00 64\n",
+            "L0008:\n",
+            "    ADD $3 $3 $2            // 0008: 46 03 03 02\n",
+            "    SUBW $2 $2 $1           // 000C: 4B 02 02 01\n",
+            "    JUMPNZ @L0008           // 0010: 39 00 00 08\n",
+            "    JUMP @L001C             // 0014: 32 00 00 1C\n",
+            "    .byte #255 #1 #2 #3     // 0018: FF 01 02 03\n",
+            "L001C:\n",
+        );
+
+        let listing = Disassembler::new().disassemble(&bytes);
+        assert_eq!(expected, listing);
+        assert_eq!(bytes, reassemble(&listing));
+    }
+
+    #[test]
+    fn test_undecodable_bytes_reassemble() {
+        let bytes = vec![
+            Halt::OPCODE, 0, 0, 7,              // 0: HALT with non-zero padding
+            Add::OPCODE, 40, 1, 1,              // 4: ADD $40 $1 $1, past the default register count
+            Jump::OPCODE, 0, 0, 6,              // 8: JUMP #6, inside another line
+            Add::OPCODE, 1,                     // 12: ADD, cut short
+        ];
+
+        let listing = Disassembler::new().disassemble(&bytes);
+        assert!(listing.contains(".byte #1 #0 #0 #7"));
+        assert!(listing.contains(".byte #70 #40 #1 #1"));
+        assert!(listing.contains("JUMP #6"));
+        assert!(listing.contains(".byte #70 #1 "));
+        assert_eq!(bytes, reassemble(&listing));
+
+        let listing = Disassembler::for_config(&VmConfig::new().with_register_count(64)).disassemble(&bytes);
+        assert!(listing.contains("ADD $40 $1 $1"));
+    }
+
+    #[test]
+    fn test_any_bytes_reassemble() {
+        // A simple linear congruential generator, so the bytes are the same on every run
+        let mut seed: u32 = 7;
+        let bytes = (0..4096).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        }).collect::<Vec<_>>();
+
+        for length in [0, 1, 5, 4096] {
+            let listing = Disassembler::new().disassemble(&bytes[..length]);
+            assert_eq!(&bytes[..length], reassemble(&listing).as_slice());
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Instruction budgets for running untrusted programs with `Kaylee::run_metered`.
+use std::collections::HashMap;
+
+use crate::vm::Byte;
+
+/// A budget of work the VM may do before handing control back.
+/// Every instruction costs 1 unless the opcode has its own cost, so by default this is an instruction count.
+#[derive(Debug, Clone)]
+pub struct Gas {
+    remaining: u64,
+    default_cost: u64,
+    costs: HashMap<Byte, u64>,
+}
+
+impl Gas {
+    pub fn new(limit: u64) -> Self {
+        Gas {
+            remaining: limit,
+            default_cost: 1,
+            costs: HashMap::new(),
+        }
+    }
+
+    /// Set the cost of every instruction that does not have its own cost
+    pub fn with_default_cost(mut self, cost: u64) -> Self {
+        self.default_cost = cost;
+        self
+    }
+
+    /// Set the cost of a single opcode
+    pub fn with_cost(mut self, opcode: Byte, cost: u64) -> Self {
+        self.costs.insert(opcode, cost);
+        self
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Add more gas, for example before resuming a program that ran out
+    pub fn refill(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_add(amount);
+    }
+
+    pub fn cost_of(&self, opcode: Byte) -> u64 {
+        *self.costs.get(&opcode).unwrap_or(&self.default_cost)
+    }
+
+    /// Deduct the cost of an opcode. Returns false, leaving the budget alone, if there is not enough gas left.
+    pub(crate) fn consume(&mut self, opcode: Byte) -> bool {
+        match self.remaining.checked_sub(self.cost_of(opcode)) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gas::Gas;
+    use crate::instructions::data::Load;
+    use crate::instructions::machine::{Halt, Spawn, Yield};
+    use crate::instructions::math::Add;
+    use crate::instructions::program::JumpBackward;
+    use crate::program::Program;
+    use crate::vm::{ExitStatus, Kaylee, RunOutcome};
+
+    #[test]
+    fn test_infinite_loop_exhausts_budget() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 1,          // LOAD $1 #1
+            Add::OPCODE, 2, 2, 1,           // ADD $2 $2 $1
+            JumpBackward::OPCODE, 0, 0, 1,  // JUMPB #1
+        ]);
+
+        let mut vm = Kaylee::new();
+        let mut gas = Gas::new(10);
+
+        assert_eq!(RunOutcome::BudgetExhausted, vm.run_metered(&program, &mut gas).unwrap());
+        assert_eq!(0, gas.remaining());
+        assert_eq!(5, vm.register(2).unwrap());
+        assert_eq!(8, vm.program_counter());
+
+        // Resumes from the same program counter
+        gas.refill(4);
+        assert_eq!(RunOutcome::BudgetExhausted, vm.run_metered(&program, &mut gas).unwrap());
+        assert_eq!(7, vm.register(2).unwrap());
+    }
+
+    #[test]
+    fn test_opcode_costs() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 1,      // LOAD $1 #1
+            Add::OPCODE, 2, 2, 1,       // ADD $2 $2 $1
+            Add::OPCODE, 2, 2, 1,       // ADD $2 $2 $1
+            Halt::OPCODE, 0, 0, 0,      // HALT
+        ]);
+
+        let mut vm = Kaylee::new();
+        let mut gas = Gas::new(8).with_cost(Add::OPCODE, 5);
+
+        // Not enough gas for the second ADD, which is left unexecuted
+        assert_eq!(RunOutcome::BudgetExhausted, vm.run_metered(&program, &mut gas).unwrap());
+        assert_eq!(2, gas.remaining());
+        assert_eq!(8, vm.program_counter());
+
+        gas.refill(10);
+        assert_eq!(RunOutcome::Exited(ExitStatus::Halted(0)), vm.run_metered(&program, &mut gas).unwrap());
+        assert_eq!(6, gas.remaining());
+        assert_eq!(2, vm.register(2).unwrap());
+    }
+
+    #[test]
+    fn test_finishes_within_budget() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 1,
+        ]);
+
+        let mut vm = Kaylee::new();
+        let mut gas = Gas::new(100).with_default_cost(3);
+
+        assert_eq!(RunOutcome::Exited(ExitStatus::Finished), vm.run_metered(&program, &mut gas).unwrap());
+        assert_eq!(97, gas.remaining());
+    }
+
+    #[test]
+    fn test_spawned_thread_running_off_the_end() {
+        let program = Program::from(vec![
+            Spawn::OPCODE, 10, 0, 16,   // 0: SPAWN $10 #16
+            Yield::OPCODE, 0, 0, 0,     // 4: YIELD
+            Load::OPCODE, 2, 0, 5,      // 8: LOAD $2 #5
+            Halt::OPCODE, 3, 0, 0,      // 12: HALT #3
+            Load::OPCODE, 3, 0, 1,      // 16: LOAD $3 #1, then the thread runs off the end
+        ]);
+
+        let mut vm = Kaylee::new();
+        let mut gas = Gas::new(100);
+
+        assert_eq!(RunOutcome::Exited(ExitStatus::Halted(3)), vm.run_metered(&program, &mut gas).unwrap());
+        assert_eq!(5, vm.register(2).unwrap());
+        assert_eq!(95, gas.remaining());
+    }
+}
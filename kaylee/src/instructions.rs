@@ -1,24 +1,25 @@
-use std::fmt::Error;
-
 use linkme::distributed_slice;
 
-use crate::instructions::compare::{Equal, GreaterThan, GreaterThanOrEqual, LessThan, LessThanOrEqual, NotEqual};
-use crate::instructions::data::Load;
-use crate::instructions::machine::Halt;
-use crate::instructions::math::{Add, Divide, Multiply, Subtract};
-use crate::instructions::program::{Jump, JumpBackward, JumpEqual, JumpForward};
+use crate::instructions::compare::{Compare, Equal, GreaterThan, GreaterThanOrEqual, LessThan, LessThanOrEqual, NotEqual};
+use crate::instructions::data::{Load, LoadByte, LoadConstant, LoadSigned, LoadUpper, Pop, Push, LoadByteRelative, LoadHalfWord, LoadHalfWordRelative, LoadWord, LoadWordRelative, StoreByte, StoreByteRelative, StoreHalfWord, StoreHalfWordRelative, StoreWord, StoreWordRelative};
+use crate::instructions::float::{FloatAdd, FloatCompare, FloatDivide, FloatEqual, FloatLessThan, FloatLessThanOrEqual, FloatLoadHigh, FloatLoadLow, FloatMultiply, FloatSubtract, FloatToInteger, IntegerToFloat};
+use crate::instructions::machine::{Abort, Halt, Join, Spawn, Yield};
+use crate::instructions::math::{Add, Divide, Multiply, SaturatingAdd, SaturatingMultiply, SaturatingSubtract, Subtract, WrappingAdd, WrappingMultiply, WrappingSubtract};
+use crate::instructions::program::{Call, Jump, JumpBackward, JumpCarry, JumpEqual, JumpForward, JumpNegative, JumpNotNegative, JumpNotZero, JumpOverflow, JumpZero, RemoveTrap, Return, ReturnFromTrap, Trap};
+use crate::instructions::system::{Clock, Print, PrintCharacter, PrintString, Random, Read, Syscall};
 use crate::program::{Program, ProgramIndex};
-use crate::vm::{Byte, ExecutionResult, HalfWord, Kaylee, RegisterId, RegisterValue, Word};
-
-mod machine;
-mod data;
-mod math;
-mod program;
-mod compare;
-mod logical;
-mod system;
-mod library;
-mod misc;
+use crate::vm::{Byte, ExecutionError, ExecutionResult, Flags, FloatRegisterValue, HalfWord, Kaylee, RegisterId, RegisterValue, RuntimeError, Word};
+
+pub mod machine;
+pub mod data;
+pub mod math;
+pub mod program;
+pub mod compare;
+pub mod logical;
+pub mod system;
+pub mod library;
+pub mod float;
+pub mod misc;
 
 /// Type for the three operand slots allowed for each instruction
 pub type RegisteredInstruction = (&'static str, u8, [OperandType; 3]);
@@ -48,14 +49,20 @@ impl InstructionRegistry {
 }
 
 /// Errors concerning decoding instruction bytecode
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum InstructionDecodeError {
     InvalidValueSize,
-    IllegalOpcode,
+    IllegalOpcode(Byte),
+    TruncatedInstruction,
 }
 
 /// Decode the next instruction in the Program stream
 pub fn decode_next_instruction(instructions: &Program, program_counter: &mut usize) -> Option<Result<Box<dyn Instruction>, InstructionDecodeError>> {
+    decode_operation(instructions, program_counter).map(|decoded| decoded.map(|(operation, operand_values)| (operation.boxed)(operand_values)))
+}
+
+/// Decode the Operation and OperandValues of the next instruction in the Program stream, without allocating
+pub(crate) fn decode_operation(instructions: &Program, program_counter: &mut usize) -> Option<Result<(Operation, OperandValues), InstructionDecodeError>> {
     // @todo: I am not super happy with this decoding scheme. It should probably grab the entire slice (4 bytes) and handle them together
     if *program_counter >= instructions.len() {
         return None;
@@ -64,46 +71,143 @@ pub fn decode_next_instruction(instructions: &Program, program_counter: &mut usi
     let opcode: Byte = instructions[*program_counter];
     *program_counter += 1;
 
-    Some(match opcode {
-        Halt::OPCODE => build::<Halt>(instructions, program_counter),
-        Load::OPCODE => build::<Load>(instructions, program_counter),
-
-        Add::OPCODE => build::<Add>(instructions, program_counter),
-        Subtract::OPCODE => build::<Subtract>(instructions, program_counter),
-        Multiply::OPCODE => build::<Multiply>(instructions, program_counter),
-        Divide::OPCODE => build::<Divide>(instructions, program_counter),
-
-        Jump::OPCODE => build::<Jump>(instructions, program_counter),
-        JumpForward::OPCODE => build::<JumpForward>(instructions, program_counter),
-        JumpBackward::OPCODE => build::<JumpBackward>(instructions, program_counter),
-        JumpEqual::OPCODE => build::<JumpEqual>(instructions, program_counter),
-
-        Equal::OPCODE => build::<Equal>(instructions, program_counter),
-        NotEqual::OPCODE => build::<NotEqual>(instructions, program_counter),
-        GreaterThan::OPCODE => build::<GreaterThan>(instructions, program_counter),
-        LessThan::OPCODE => build::<LessThan>(instructions, program_counter),
-        GreaterThanOrEqual::OPCODE => build::<GreaterThanOrEqual>(instructions, program_counter),
-        LessThanOrEqual::OPCODE => build::<LessThanOrEqual>(instructions, program_counter),
-
-        _ => {
-            Err(InstructionDecodeError::IllegalOpcode)
+    let operation = match Operation::for_opcode(opcode) {
+        Some(operation) => operation,
+        None => return Some(Err(InstructionDecodeError::IllegalOpcode(opcode))),
+    };
+
+    Some(consume_and_parse_values((operation.signature)(), instructions, program_counter).map(|operand_values| (operation, operand_values)))
+}
+
+/// Everything needed to build or execute one kind of instruction, without knowing its concrete type
+#[derive(Clone, Copy)]
+pub(crate) struct Operation {
+    signature: fn() -> InstructionSignature,
+    boxed: fn(OperandValues) -> Box<dyn Instruction>,
+    execute: fn(OperandValues, ProgramIndex, &mut Kaylee) -> Result<ExecutionResult, RuntimeError>,
+}
+
+impl Operation {
+    /// Execute the instruction at `program_index` with these OperandValues
+    pub(crate) fn execute(&self, operand_values: OperandValues, program_index: ProgramIndex, vm: &mut Kaylee) -> Result<ExecutionResult, RuntimeError> {
+        (self.execute)(operand_values, program_index, vm)
+    }
+
+    /// The opcode and operand layout of the instruction
+    pub(crate) fn signature(&self) -> InstructionSignature {
+        (self.signature)()
+    }
+
+    /// The human-readable form of the instruction with these OperandValues
+    pub(crate) fn display(&self, operand_values: OperandValues) -> String {
+        (self.boxed)(operand_values).display()
+    }
+
+    fn of<T: 'static + Instruction>() -> Self {
+        Operation {
+            signature: T::signature,
+            boxed: |operand_values| Box::new(T::new(operand_values)),
+            execute: execute_operation::<T>,
         }
-    })
+    }
+
+    /// Find the Operation for an opcode, if the opcode belongs to a known instruction
+    pub(crate) fn for_opcode(opcode: Byte) -> Option<Self> {
+        Some(match opcode {
+            Halt::OPCODE => Operation::of::<Halt>(),
+            Abort::OPCODE => Operation::of::<Abort>(),
+            Spawn::OPCODE => Operation::of::<Spawn>(),
+            Yield::OPCODE => Operation::of::<Yield>(),
+            Join::OPCODE => Operation::of::<Join>(),
+            Load::OPCODE => Operation::of::<Load>(),
+            LoadByte::OPCODE => Operation::of::<LoadByte>(),
+            LoadHalfWord::OPCODE => Operation::of::<LoadHalfWord>(),
+            LoadWord::OPCODE => Operation::of::<LoadWord>(),
+            StoreByte::OPCODE => Operation::of::<StoreByte>(),
+            StoreHalfWord::OPCODE => Operation::of::<StoreHalfWord>(),
+            StoreWord::OPCODE => Operation::of::<StoreWord>(),
+            LoadByteRelative::OPCODE => Operation::of::<LoadByteRelative>(),
+            LoadHalfWordRelative::OPCODE => Operation::of::<LoadHalfWordRelative>(),
+            LoadWordRelative::OPCODE => Operation::of::<LoadWordRelative>(),
+            StoreByteRelative::OPCODE => Operation::of::<StoreByteRelative>(),
+            StoreHalfWordRelative::OPCODE => Operation::of::<StoreHalfWordRelative>(),
+            StoreWordRelative::OPCODE => Operation::of::<StoreWordRelative>(),
+            Push::OPCODE => Operation::of::<Push>(),
+            Pop::OPCODE => Operation::of::<Pop>(),
+            LoadConstant::OPCODE => Operation::of::<LoadConstant>(),
+            LoadSigned::OPCODE => Operation::of::<LoadSigned>(),
+            LoadUpper::OPCODE => Operation::of::<LoadUpper>(),
+
+            Add::OPCODE => Operation::of::<Add>(),
+            Subtract::OPCODE => Operation::of::<Subtract>(),
+            Multiply::OPCODE => Operation::of::<Multiply>(),
+            Divide::OPCODE => Operation::of::<Divide>(),
+            WrappingAdd::OPCODE => Operation::of::<WrappingAdd>(),
+            WrappingSubtract::OPCODE => Operation::of::<WrappingSubtract>(),
+            WrappingMultiply::OPCODE => Operation::of::<WrappingMultiply>(),
+            SaturatingAdd::OPCODE => Operation::of::<SaturatingAdd>(),
+            SaturatingSubtract::OPCODE => Operation::of::<SaturatingSubtract>(),
+            SaturatingMultiply::OPCODE => Operation::of::<SaturatingMultiply>(),
+
+            Jump::OPCODE => Operation::of::<Jump>(),
+            JumpForward::OPCODE => Operation::of::<JumpForward>(),
+            JumpBackward::OPCODE => Operation::of::<JumpBackward>(),
+            JumpEqual::OPCODE => Operation::of::<JumpEqual>(),
+            Call::OPCODE => Operation::of::<Call>(),
+            Return::OPCODE => Operation::of::<Return>(),
+            JumpZero::OPCODE => Operation::of::<JumpZero>(),
+            JumpNotZero::OPCODE => Operation::of::<JumpNotZero>(),
+            JumpNegative::OPCODE => Operation::of::<JumpNegative>(),
+            JumpNotNegative::OPCODE => Operation::of::<JumpNotNegative>(),
+            JumpCarry::OPCODE => Operation::of::<JumpCarry>(),
+            JumpOverflow::OPCODE => Operation::of::<JumpOverflow>(),
+            Trap::OPCODE => Operation::of::<Trap>(),
+            RemoveTrap::OPCODE => Operation::of::<RemoveTrap>(),
+            ReturnFromTrap::OPCODE => Operation::of::<ReturnFromTrap>(),
+
+            Equal::OPCODE => Operation::of::<Equal>(),
+            NotEqual::OPCODE => Operation::of::<NotEqual>(),
+            GreaterThan::OPCODE => Operation::of::<GreaterThan>(),
+            LessThan::OPCODE => Operation::of::<LessThan>(),
+            GreaterThanOrEqual::OPCODE => Operation::of::<GreaterThanOrEqual>(),
+            LessThanOrEqual::OPCODE => Operation::of::<LessThanOrEqual>(),
+            Compare::OPCODE => Operation::of::<Compare>(),
+
+            Syscall::OPCODE => Operation::of::<Syscall>(),
+            Print::OPCODE => Operation::of::<Print>(),
+            PrintCharacter::OPCODE => Operation::of::<PrintCharacter>(),
+            PrintString::OPCODE => Operation::of::<PrintString>(),
+            Read::OPCODE => Operation::of::<Read>(),
+            Clock::OPCODE => Operation::of::<Clock>(),
+            Random::OPCODE => Operation::of::<Random>(),
+
+            FloatLoadHigh::OPCODE => Operation::of::<FloatLoadHigh>(),
+            FloatLoadLow::OPCODE => Operation::of::<FloatLoadLow>(),
+            FloatAdd::OPCODE => Operation::of::<FloatAdd>(),
+            FloatSubtract::OPCODE => Operation::of::<FloatSubtract>(),
+            FloatMultiply::OPCODE => Operation::of::<FloatMultiply>(),
+            FloatDivide::OPCODE => Operation::of::<FloatDivide>(),
+            FloatCompare::OPCODE => Operation::of::<FloatCompare>(),
+            FloatEqual::OPCODE => Operation::of::<FloatEqual>(),
+            FloatLessThan::OPCODE => Operation::of::<FloatLessThan>(),
+            FloatLessThanOrEqual::OPCODE => Operation::of::<FloatLessThanOrEqual>(),
+            IntegerToFloat::OPCODE => Operation::of::<IntegerToFloat>(),
+            FloatToInteger::OPCODE => Operation::of::<FloatToInteger>(),
+
+            _ => return None,
+        })
+    }
 }
 
-/// Build the Instruction TraitObject from the program stream
-pub fn build<T: 'static + Instruction>(instructions: &Program, program_counter: &mut usize) -> Result<Box<dyn Instruction>, InstructionDecodeError> {
-    Ok(
-        Box::new(
-            T::new(
-                consume_and_parse_values(
-                    T::signature(),
-                    instructions,
-                    program_counter,
-                )?
-            )
-        )
-    )
+/// Builds the instruction on the stack and executes it, reporting any fault with the instruction that raised it
+fn execute_operation<T: 'static + Instruction>(operand_values: OperandValues, program_index: ProgramIndex, vm: &mut Kaylee) -> Result<ExecutionResult, RuntimeError> {
+    let instruction = T::new(operand_values);
+
+    vm.execute_instruction(program_index, &instruction).map_err(|error| RuntimeError {
+        error,
+        program_index,
+        instruction: Some(instruction.display()),
+    })
 }
 
 /// Decodes the operand values from the Instruction Stream
@@ -111,12 +215,16 @@ pub fn consume_and_parse_values(signature: InstructionSignature, instructions: &
     let mut operand_values: OperandValues = [OperandValue::None, OperandValue::None, OperandValue::None];
 
     let original_pc = *program_counter;
+    if original_pc + signature_length(&signature) > instructions.len() {
+        return Err(InstructionDecodeError::TruncatedInstruction);
+    }
+
     for (index, bytes) in signature.operands.iter().enumerate() {
         match bytes {
             OperandType::None => {
                 operand_values[index] = OperandValue::None;
             }
-            OperandType::RegisterId | OperandType::ConstantByte => {
+            OperandType::RegisterId | OperandType::FloatRegisterId | OperandType::ConstantByte => {
                 operand_values[index] = OperandValue::Byte(instructions[*program_counter]);
                 *program_counter += 1;
             }
@@ -130,7 +238,7 @@ pub fn consume_and_parse_values(signature: InstructionSignature, instructions: &
                 let b = (instructions[*program_counter + 1] as Word) << 8;
                 let c = instructions[*program_counter + 2] as Word;
 
-                let value = (a | b | c) as u32;
+                let value = a | b | c;
 
                 operand_values[index] = OperandValue::Word(value);
 
@@ -145,6 +253,27 @@ pub fn consume_and_parse_values(signature: InstructionSignature, instructions: &
     Ok(operand_values)
 }
 
+/// The number of operand bytes an instruction signature will consume from the Instruction Stream
+pub(crate) fn signature_length(signature: &InstructionSignature) -> usize {
+    signature.operands.iter().map(|operand| match operand {
+        OperandType::None => 0,
+        OperandType::RegisterId | OperandType::FloatRegisterId | OperandType::ConstantByte => 1,
+        OperandType::ConstantHalfWord => 2,
+        OperandType::ConstantWord => 3,
+    }).sum()
+}
+
+/// The operand holding the ProgramIndex an instruction may jump to, for instructions whose target is a constant.
+/// Relative jumps and targets held in registers have none.
+pub(crate) fn target_operand(opcode: Byte) -> Option<usize> {
+    match opcode {
+        Jump::OPCODE | Call::OPCODE | JumpZero::OPCODE | JumpNotZero::OPCODE | JumpNegative::OPCODE
+        | JumpNotNegative::OPCODE | JumpCarry::OPCODE | JumpOverflow::OPCODE => Some(0),
+        Trap::OPCODE | Spawn::OPCODE => Some(1),
+        _ => None,
+    }
+}
+
 /// Prints an instruction in an Instruction Stream in a human readable format
 pub fn display_instruction_with_values<T: 'static + Instruction>(instruction: &T) -> String {
     let mut output = String::new();
@@ -157,6 +286,10 @@ pub fn display_instruction_with_values<T: 'static + Instruction>(instruction: &T
                 let value = instruction.operand_value(index).unwrap().as_constant_value();
                 output.push_str(format!(" ${value}").as_str())
             }
+            OperandType::FloatRegisterId => {
+                let value = instruction.operand_value(index).unwrap().as_constant_value();
+                output.push_str(format!(" %{value}").as_str())
+            }
             _ => {
                 let value = instruction.operand_value(index).unwrap().as_constant_value();
                 output.push_str(format!(" #{value}").as_str())
@@ -167,32 +300,104 @@ pub fn display_instruction_with_values<T: 'static + Instruction>(instruction: &T
     output
 }
 
-/// Helper for a common instruction execution. Executes callback with the values from two operands, setting a destination register
-fn basic_register_execution<I: Instruction, F: Fn(RegisterValue, RegisterValue) -> RegisterValue>(instruction: &I, vm: &mut Kaylee, callback: F) -> RegisterValue {
-    let destination = instruction.operand_values()[0].as_register_id();
+/// Reads the left (operand 1) and right (operand 2) register terms of an instruction
+fn register_terms<I: Instruction>(instruction: &I, vm: &mut Kaylee) -> Result<(RegisterValue, RegisterValue), ExecutionError> {
+    let left = instruction.get_register_value_for_operand(1, vm)?;
+    let right = instruction.get_register_value_for_operand(2, vm)?;
 
-    let left = instruction.get_register_value_for_operand(1, vm).unwrap();
-    let right = instruction.get_register_value_for_operand(2, vm).unwrap();
+    Ok((left, right))
+}
+
+/// Helper for comparison instructions. Stores the result of callback as 0 or 1 in the destination register
+/// and sets the status flags from comparing the two terms
+fn compare_register_execution<I: Instruction, F: Fn(RegisterValue, RegisterValue) -> bool>(instruction: &I, vm: &mut Kaylee, callback: F) -> Result<bool, ExecutionError> {
+    let destination = instruction.operand_values()[0].as_register_id();
+    let (left, right) = register_terms(instruction, vm)?;
 
     let result = callback(left, right);
 
-    vm.set_register(destination, result as RegisterValue).unwrap();
-    result
+    vm.set_flags(Flags::compare(left, right));
+    vm.set_register(destination, result as RegisterValue)?;
+    Ok(result)
+}
+
+/// What an arithmetic instruction does when its signed result does not fit in a register
+#[derive(Clone, Copy)]
+enum Overflow {
+    /// Fault with `ArithmeticOverflow`, leaving the destination register untouched
+    Trap,
+    /// Keep the low 32 bits of the result (two's complement wrap around)
+    Wrap,
+    /// Clamp the result to `i32::MIN` or `i32::MAX`
+    Saturate,
+}
+
+/// The outcome of an arithmetic operation, before an `Overflow` behaviour picks which result to keep
+struct Arithmetic {
+    wrapped: RegisterValue,
+    saturated: RegisterValue,
+    carry: bool,
+    overflow: bool,
+}
+
+impl Arithmetic {
+    fn add(left: RegisterValue, right: RegisterValue) -> Self {
+        let (wrapped, overflow) = left.overflowing_add(right);
+        let carry = (left as u32).overflowing_add(right as u32).1;
+
+        Arithmetic { wrapped, saturated: left.saturating_add(right), carry, overflow }
+    }
+
+    fn subtract(left: RegisterValue, right: RegisterValue) -> Self {
+        let (wrapped, overflow) = left.overflowing_sub(right);
+        let carry = (left as u32) < (right as u32);
+
+        Arithmetic { wrapped, saturated: left.saturating_sub(right), carry, overflow }
+    }
+
+    fn multiply(left: RegisterValue, right: RegisterValue) -> Self {
+        let (wrapped, overflow) = left.overflowing_mul(right);
+
+        Arithmetic { wrapped, saturated: left.saturating_mul(right), carry: overflow, overflow }
+    }
+}
+
+/// Helper for arithmetic instructions. Applies the operation to the two register terms and keeps the result
+/// chosen by the overflow behaviour. The status flags are set from that result, even when the instruction traps.
+fn arithmetic_register_execution<I: Instruction>(instruction: &I, vm: &mut Kaylee, operation: fn(RegisterValue, RegisterValue) -> Arithmetic, overflow: Overflow) -> Result<RegisterValue, ExecutionError> {
+    let destination = instruction.operand_values()[0].as_register_id();
+    let (left, right) = register_terms(instruction, vm)?;
+
+    let arithmetic = operation(left, right);
+    let result = match overflow {
+        Overflow::Trap | Overflow::Wrap => arithmetic.wrapped,
+        Overflow::Saturate => arithmetic.saturated,
+    };
+
+    vm.set_flags(Flags::arithmetic(result, arithmetic.carry, arithmetic.overflow));
+
+    if arithmetic.overflow && matches!(overflow, Overflow::Trap) {
+        return Err(ExecutionError::ArithmeticOverflow);
+    }
+
+    vm.set_register(destination, result)?;
+    Ok(result)
 }
 
 /// Potential types of Operands
 pub enum OperandType {
     None,
     RegisterId,
+    FloatRegisterId,
     ConstantByte,
     ConstantHalfWord,
     ConstantWord,
 }
 
-type OperandValues = [OperandValue; 3];
+pub(crate) type OperandValues = [OperandValue; 3];
 
 /// Value for an operand in the Instruction Stream
-#[derive(PartialOrd, PartialEq, Debug)]
+#[derive(PartialOrd, PartialEq, Debug, Clone, Copy)]
 pub enum OperandValue {
     Byte(Byte),
     HalfWord(HalfWord),
@@ -222,7 +427,7 @@ impl OperandValue {
     /// Get the OperandValue as a RegisterId
     // @todo: I tried to do these conversions using TryFrom and a generic `into<T>(&self) -> T` function, but neither worked.
     // @todo: There is certainly a more idiomatic way
-    fn as_register_id(&self) -> RegisterId {
+    pub(crate) fn as_register_id(&self) -> RegisterId {
         match self {
             OperandValue::Byte(value) => *value as usize,
             OperandValue::HalfWord(value) => *value as usize,
@@ -232,12 +437,12 @@ impl OperandValue {
     }
 
     /// Get the OperandValue as a Program Index Target
-    fn as_program_index(&self) -> ProgramIndex {
+    pub(crate) fn as_program_index(&self) -> ProgramIndex {
         self.as_register_id() as ProgramIndex
     }
 
     /// Get the OperandValue as a constant literal value (integer)
-    fn as_constant_value(&self) -> RegisterValue {
+    pub(crate) fn as_constant_value(&self) -> RegisterValue {
         match self {
             OperandValue::Byte(value) => *value as RegisterValue,
             OperandValue::HalfWord(value) => *value as RegisterValue,
@@ -245,16 +450,6 @@ impl OperandValue {
             OperandValue::None => panic!("Did not receive a destination register")
         }
     }
-
-    /// Get the OperandValue as a string
-    pub(crate) fn as_string(&self) -> String {
-        match self {
-            OperandValue::Byte(value) => value.to_string(),
-            OperandValue::HalfWord(value) => value.to_string(),
-            OperandValue::Word(value) => value.to_string(),
-            OperandValue::None => panic!("Did not receive a destination register")
-        }
-    }
 }
 
 /// Defines an Instruction's Signature
@@ -272,7 +467,7 @@ pub struct InstructionDocumentation {
 /// Allows an Instruction to be executable
 pub trait Executable {
     // @todo: The only thing (other than the OPCODE constant) that is actually required w/o macro
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error>;
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError>;
 }
 
 /// Defines the Instruction itself
@@ -305,8 +500,14 @@ pub trait Instruction: Executable {
     }
 
     /// Get a concrete value from a register by looking at the target in an OperandValue
-    fn get_register_value_for_operand(&self, operand_value_index: usize, vm: &mut Kaylee) -> Result<RegisterValue, ()> {
+    fn get_register_value_for_operand(&self, operand_value_index: usize, vm: &mut Kaylee) -> Result<RegisterValue, ExecutionError> {
         let register = self.operand_values()[operand_value_index].as_register_id();
         vm.register(register)
     }
+
+    /// Get a concrete value from a float register by looking at the target in an OperandValue
+    fn get_float_register_value_for_operand(&self, operand_value_index: usize, vm: &mut Kaylee) -> Result<FloatRegisterValue, ExecutionError> {
+        let register = self.operand_values()[operand_value_index].as_register_id();
+        vm.float_register(register)
+    }
 }
\ No newline at end of file
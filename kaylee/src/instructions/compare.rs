@@ -1,12 +1,13 @@
 //! Instructions for comparisons
 //! Opcodes reserved: 100 - 119
-use std::fmt::Error;
-
+//!
+//! Every comparison sets the status flags as if the right term were subtracted from the left:
+//! zero when equal, negative when left < right, carry when left < right unsigned, and overflow if the subtraction would overflow.
 use kaylee_derive::Instruction;
 
 use crate::instructions;
 use crate::instructions::{display_instruction_with_values, Executable, Instruction, InstructionDocumentation, InstructionSignature, OperandType, OperandValues};
-use crate::vm::{ExecutionResult, Kaylee, RegisterValue};
+use crate::vm::{ExecutionError, ExecutionResult, Flags, Kaylee, RegisterValue};
 
 /// Equal: Stores a boolean in a destination with the comparison result from two register values
 /// Operands:
@@ -30,15 +31,11 @@ pub struct Equal {
 }
 
 impl Executable for Equal {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left == right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
-        match result {
-            1 => Ok(ExecutionResult::Equality(true)),
-            0 => Ok(ExecutionResult::Equality(false)),
-            _ => panic!("Equality returned something other than a 0 or 1")
-        }
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let callback = |left: RegisterValue, right: RegisterValue| { left == right };
+
+        let result = instructions::compare_register_execution(self, vm, callback)?;
+        Ok(ExecutionResult::Equality(result))
     }
 }
 
@@ -64,15 +61,11 @@ pub struct NotEqual {
 }
 
 impl Executable for NotEqual {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left != right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
-        match result {
-            1 => Ok(ExecutionResult::Equality(true)),
-            0 => Ok(ExecutionResult::Equality(false)),
-            _ => panic!("Equality returned something other than a 0 or 1")
-        }
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let callback = |left: RegisterValue, right: RegisterValue| { left != right };
+
+        let result = instructions::compare_register_execution(self, vm, callback)?;
+        Ok(ExecutionResult::Equality(result))
     }
 }
 
@@ -98,15 +91,11 @@ pub struct GreaterThan {
 }
 
 impl Executable for GreaterThan {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left > right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
-        match result {
-            1 => Ok(ExecutionResult::Equality(true)),
-            0 => Ok(ExecutionResult::Equality(false)),
-            _ => panic!("Equality returned something other than a 0 or 1")
-        }
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let callback = |left: RegisterValue, right: RegisterValue| { left > right };
+
+        let result = instructions::compare_register_execution(self, vm, callback)?;
+        Ok(ExecutionResult::Equality(result))
     }
 }
 
@@ -132,15 +121,11 @@ pub struct LessThan {
 }
 
 impl Executable for LessThan {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left < right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
-        match result {
-            1 => Ok(ExecutionResult::Equality(true)),
-            0 => Ok(ExecutionResult::Equality(false)),
-            _ => panic!("Equality returned something other than a 0 or 1")
-        }
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let callback = |left: RegisterValue, right: RegisterValue| { left < right };
+
+        let result = instructions::compare_register_execution(self, vm, callback)?;
+        Ok(ExecutionResult::Equality(result))
     }
 }
 
@@ -166,15 +151,11 @@ pub struct GreaterThanOrEqual {
 }
 
 impl Executable for GreaterThanOrEqual {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left >= right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
-        match result {
-            1 => Ok(ExecutionResult::Equality(true)),
-            0 => Ok(ExecutionResult::Equality(false)),
-            _ => panic!("Equality returned something other than a 0 or 1")
-        }
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let callback = |left: RegisterValue, right: RegisterValue| { left >= right };
+
+        let result = instructions::compare_register_execution(self, vm, callback)?;
+        Ok(ExecutionResult::Equality(result))
     }
 }
 
@@ -200,23 +181,48 @@ pub struct LessThanOrEqual {
 }
 
 impl Executable for LessThanOrEqual {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left <= right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
-        match result {
-            1 => Ok(ExecutionResult::Equality(true)),
-            0 => Ok(ExecutionResult::Equality(false)),
-            _ => panic!("Equality returned something other than a 0 or 1")
-        }
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let callback = |left: RegisterValue, right: RegisterValue| { left <= right };
+
+        let result = instructions::compare_register_execution(self, vm, callback)?;
+        Ok(ExecutionResult::Equality(result))
+    }
+}
+
+/// Compare: Compares two register values, only setting the status flags. Pair it with a conditional branch.
+/// Operands:
+///     - 0: `$L` | 1 Byte | RegisterId | RegisterId of the left term
+///     - 1: `$R` | 1 Byte | RegisterId | RegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// CMP $10 $30 // `74 0A 1E 00` - Sets the flags from comparing the values in registers 10 and 30
+/// ```
+#[derive(Instruction)]
+#[opcode = 116]
+#[signature = "CMP $L $R"]
+pub struct Compare {
+    operand_values: OperandValues,
+}
+
+impl Executable for Compare {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let left = self.get_register_value_for_operand(0, vm)?;
+        let right = self.get_register_value_for_operand(1, vm)?;
+
+        vm.set_flags(Flags::compare(left, right));
+        Ok(ExecutionResult::Equality(left == right))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::instructions::compare::{Equal, GreaterThan, GreaterThanOrEqual, LessThan, LessThanOrEqual, NotEqual};
+    use crate::instructions::compare::{Compare, Equal, GreaterThan, GreaterThanOrEqual, LessThan, LessThanOrEqual, NotEqual};
     use crate::program::Program;
-    use crate::vm::Kaylee;
+    use crate::vm::{Flags, Kaylee};
 
     #[test]
     fn test_equal() {
@@ -231,7 +237,7 @@ mod tests {
         vm.set_register(3, 200).unwrap();
         vm.set_register(4, 500).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(1, vm.register(30).unwrap());
         assert_eq!(0, vm.register(31).unwrap());
@@ -250,7 +256,7 @@ mod tests {
         vm.set_register(3, 300).unwrap();
         vm.set_register(4, 300).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(1, vm.register(30).unwrap());
         assert_eq!(0, vm.register(31).unwrap());
@@ -269,7 +275,7 @@ mod tests {
         vm.set_register(3, 200).unwrap();
         vm.set_register(4, 300).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(1, vm.register(30).unwrap());
         assert_eq!(0, vm.register(31).unwrap());
@@ -288,7 +294,7 @@ mod tests {
         vm.set_register(3, 400).unwrap();
         vm.set_register(4, 300).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(1, vm.register(30).unwrap());
         assert_eq!(0, vm.register(31).unwrap());
@@ -312,7 +318,7 @@ mod tests {
         vm.set_register(5, 200).unwrap();
         vm.set_register(6, 300).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(1, vm.register(28).unwrap());
         assert_eq!(1, vm.register(29).unwrap());
@@ -337,10 +343,43 @@ mod tests {
         vm.set_register(5, 400).unwrap();
         vm.set_register(6, 300).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(1, vm.register(28).unwrap());
         assert_eq!(1, vm.register(29).unwrap());
         assert_eq!(0, vm.register(30).unwrap());
     }
+
+    #[test]
+    fn test_compare_sets_flags_only() {
+        let program = Program::from(vec![
+            Compare::OPCODE, 1, 2, 0,
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, -1).unwrap();
+        vm.set_register(2, 1).unwrap();
+
+        vm.run(program).unwrap();
+
+        // -1 < 1 signed, but 0xFFFFFFFF > 1 unsigned
+        assert_eq!(Flags { zero: false, negative: true, carry: false, overflow: false }, vm.flags());
+        assert_eq!([0, -1, 1], vm.all_registers()[..3]);
+    }
+
+    #[test]
+    fn test_comparisons_set_flags() {
+        let program = Program::from(vec![
+            GreaterThan::OPCODE, 30, 1, 2,
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 200).unwrap();
+        vm.set_register(2, 200).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(0, vm.register(30).unwrap());
+        assert_eq!(Flags { zero: true, negative: false, carry: false, overflow: false }, vm.flags());
+    }
 }
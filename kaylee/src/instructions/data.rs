@@ -1,11 +1,10 @@
 //! Instructions for manipulating data (registers and memory)
 //! Opcodes reserved: 30 - 49
-use std::fmt::Error;
-
 use kaylee_derive::Instruction;
 
 use crate::instructions::{display_instruction_with_values, Executable, Instruction, InstructionDocumentation, InstructionSignature, OperandType, OperandValues};
-use crate::vm::{ExecutionResult, Kaylee};
+use crate::program::Constant;
+use crate::vm::{ExecutionError, ExecutionResult, Kaylee, MemoryAddress, RegisterValue};
 
 /// LOAD: Loads a value into a designated register
 /// Operands:
@@ -15,14 +14,15 @@ use crate::vm::{ExecutionResult, Kaylee};
 ///
 /// Errors/ Panics
 ///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
-///     - `AssemblerError` or `ProgramPanic`: If Constant value is too large for 2 bytes
+///
+/// The assembler loads any `i32` with `LOAD`, picking `LOADS`, or `LOAD` then `LOADUP`, for values outside 0-65535
 ///
 /// Examples
 /// ```asm
 /// LOAD $1 #500 // `1E 01 01 FF` - Loads 500 into Register 1
 /// LOAD $31 #01 // `1E 1F 00 01` - Loads 1 into Register 31
 /// LOAD $40 #10 // `1E 28 00 0A` - Assembler Error because 40 is not a valid register
-/// LOAD $15 #1,000,000 // `1E 0F 00 0A` - Assembler Error because constant value is out of bounds
+/// LOAD $15 #1000000 // `1E 0F 42 40 2F 0F 00 0F` - Assembled as LOAD $15 #16960 then LOADUP $15 #15
 /// ```
 #[derive(Instruction)]
 #[opcode = 30]
@@ -32,20 +32,567 @@ pub struct Load {
 }
 
 impl Executable for Load {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
         let destination = self.operand_value(0).unwrap().as_register_id();
         let value = self.operand_value(1).unwrap().as_constant_value();
 
-        vm.set_register(destination, value).unwrap();
+        vm.set_register(destination, value)?;
         Ok(ExecutionResult::Value(value))
     }
 }
 
+/// LoadByte: Loads a single byte from an absolute memory address into a register, zero-extended
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `#2` | 2 Bytes | MemoryAddress | Memory address of the first byte to load
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If the byte at the address is outside of memory
+///
+/// Examples
+/// ```asm
+/// LOADB $1 #500 // `1F 01 01 F4` - Loads memory starting at address 500 into Register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 31]
+#[signature = "LOADB $D #2"]
+pub struct LoadByte {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadByte {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let address = self.operand_values[1].as_constant_value() as MemoryAddress;
+
+        load_from_memory(vm, destination, address, 1)
+    }
+}
+
+/// LoadHalfWord: Loads two bytes (big-endian) from an absolute memory address into a register, zero-extended
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `#2` | 2 Bytes | MemoryAddress | Memory address of the first byte to load
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If any of the 2 bytes at the address are outside of memory
+///
+/// Examples
+/// ```asm
+/// LOADH $1 #500 // `20 01 01 F4` - Loads memory starting at address 500 into Register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 32]
+#[signature = "LOADH $D #2"]
+pub struct LoadHalfWord {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadHalfWord {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let address = self.operand_values[1].as_constant_value() as MemoryAddress;
+
+        load_from_memory(vm, destination, address, 2)
+    }
+}
+
+/// LoadWord: Loads four bytes (big-endian) from an absolute memory address into a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `#2` | 2 Bytes | MemoryAddress | Memory address of the first byte to load
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If any of the 4 bytes at the address are outside of memory
+///
+/// Examples
+/// ```asm
+/// LOADW $1 #500 // `21 01 01 F4` - Loads memory starting at address 500 into Register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 33]
+#[signature = "LOADW $D #2"]
+pub struct LoadWord {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadWord {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let address = self.operand_values[1].as_constant_value() as MemoryAddress;
+
+        load_from_memory(vm, destination, address, 4)
+    }
+}
+
+/// StoreByte: Stores the lowest byte of a register into memory at an absolute address
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the source register (0-31)
+///     - 1: `#2` | 2 Bytes | MemoryAddress | Memory address of the first byte to store
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If the byte at the address is outside of memory
+///
+/// Examples
+/// ```asm
+/// STOREB $1 #500 // `22 01 01 F4` - Stores Register 1 into memory starting at address 500
+/// ```
+#[derive(Instruction)]
+#[opcode = 34]
+#[signature = "STOREB $S #2"]
+pub struct StoreByte {
+    operand_values: OperandValues,
+}
+
+impl Executable for StoreByte {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+        let address = self.operand_values[1].as_constant_value() as MemoryAddress;
+
+        store_to_memory(vm, value, address, 1)
+    }
+}
+
+/// StoreHalfWord: Stores the lowest two bytes of a register into memory at an absolute address (big-endian)
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the source register (0-31)
+///     - 1: `#2` | 2 Bytes | MemoryAddress | Memory address of the first byte to store
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If any of the 2 bytes at the address are outside of memory
+///
+/// Examples
+/// ```asm
+/// STOREH $1 #500 // `23 01 01 F4` - Stores Register 1 into memory starting at address 500
+/// ```
+#[derive(Instruction)]
+#[opcode = 35]
+#[signature = "STOREH $S #2"]
+pub struct StoreHalfWord {
+    operand_values: OperandValues,
+}
+
+impl Executable for StoreHalfWord {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+        let address = self.operand_values[1].as_constant_value() as MemoryAddress;
+
+        store_to_memory(vm, value, address, 2)
+    }
+}
+
+/// StoreWord: Stores the value of a register into memory at an absolute address (big-endian)
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the source register (0-31)
+///     - 1: `#2` | 2 Bytes | MemoryAddress | Memory address of the first byte to store
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If any of the 4 bytes at the address are outside of memory
+///
+/// Examples
+/// ```asm
+/// STOREW $1 #500 // `24 01 01 F4` - Stores Register 1 into memory starting at address 500
+/// ```
+#[derive(Instruction)]
+#[opcode = 36]
+#[signature = "STOREW $S #2"]
+pub struct StoreWord {
+    operand_values: OperandValues,
+}
+
+impl Executable for StoreWord {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+        let address = self.operand_values[1].as_constant_value() as MemoryAddress;
+
+        store_to_memory(vm, value, address, 4)
+    }
+}
+
+/// LoadByteRelative: Loads a single byte from a register-relative memory address into a register, zero-extended
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$B` | 1 Byte | RegisterId | RegisterId holding the base memory address
+///     - 2: `#1` | 1 Byte | Offset | Offset added to the base address
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///     - `RuntimeError`: If the byte at the address is outside of memory
+///
+/// Examples
+/// ```asm
+/// LOADBR $1 $2 #4 // `25 01 02 04` - Loads memory starting at the address in Register 2 plus 4 into Register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 37]
+#[signature = "LOADBR $D $B #1"]
+pub struct LoadByteRelative {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadByteRelative {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let address = relative_address(self, vm)?;
+
+        load_from_memory(vm, destination, address, 1)
+    }
+}
+
+/// LoadHalfWordRelative: Loads two bytes (big-endian) from a register-relative memory address into a register, zero-extended
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$B` | 1 Byte | RegisterId | RegisterId holding the base memory address
+///     - 2: `#1` | 1 Byte | Offset | Offset added to the base address
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///     - `RuntimeError`: If any of the 2 bytes at the address are outside of memory
+///
+/// Examples
+/// ```asm
+/// LOADHR $1 $2 #4 // `26 01 02 04` - Loads memory starting at the address in Register 2 plus 4 into Register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 38]
+#[signature = "LOADHR $D $B #1"]
+pub struct LoadHalfWordRelative {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadHalfWordRelative {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let address = relative_address(self, vm)?;
+
+        load_from_memory(vm, destination, address, 2)
+    }
+}
+
+/// LoadWordRelative: Loads four bytes (big-endian) from a register-relative memory address into a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$B` | 1 Byte | RegisterId | RegisterId holding the base memory address
+///     - 2: `#1` | 1 Byte | Offset | Offset added to the base address
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///     - `RuntimeError`: If any of the 4 bytes at the address are outside of memory
+///
+/// Examples
+/// ```asm
+/// LOADWR $1 $2 #4 // `27 01 02 04` - Loads memory starting at the address in Register 2 plus 4 into Register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 39]
+#[signature = "LOADWR $D $B #1"]
+pub struct LoadWordRelative {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadWordRelative {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let address = relative_address(self, vm)?;
+
+        load_from_memory(vm, destination, address, 4)
+    }
+}
+
+/// StoreByteRelative: Stores the lowest byte of a register into memory at a register-relative address
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the source register (0-31)
+///     - 1: `$B` | 1 Byte | RegisterId | RegisterId holding the base memory address
+///     - 2: `#1` | 1 Byte | Offset | Offset added to the base address
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///     - `RuntimeError`: If the byte at the address is outside of memory
+///
+/// Examples
+/// ```asm
+/// STOREBR $1 $2 #4 // `28 01 02 04` - Stores Register 1 into memory starting at the address in Register 2 plus 4
+/// ```
+#[derive(Instruction)]
+#[opcode = 40]
+#[signature = "STOREBR $S $B #1"]
+pub struct StoreByteRelative {
+    operand_values: OperandValues,
+}
+
+impl Executable for StoreByteRelative {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+        let address = relative_address(self, vm)?;
+
+        store_to_memory(vm, value, address, 1)
+    }
+}
+
+/// StoreHalfWordRelative: Stores the lowest two bytes of a register into memory at a register-relative address (big-endian)
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the source register (0-31)
+///     - 1: `$B` | 1 Byte | RegisterId | RegisterId holding the base memory address
+///     - 2: `#1` | 1 Byte | Offset | Offset added to the base address
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///     - `RuntimeError`: If any of the 2 bytes at the address are outside of memory
+///
+/// Examples
+/// ```asm
+/// STOREHR $1 $2 #4 // `29 01 02 04` - Stores Register 1 into memory starting at the address in Register 2 plus 4
+/// ```
+#[derive(Instruction)]
+#[opcode = 41]
+#[signature = "STOREHR $S $B #1"]
+pub struct StoreHalfWordRelative {
+    operand_values: OperandValues,
+}
+
+impl Executable for StoreHalfWordRelative {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+        let address = relative_address(self, vm)?;
+
+        store_to_memory(vm, value, address, 2)
+    }
+}
+
+/// StoreWordRelative: Stores the value of a register into memory at a register-relative address (big-endian)
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the source register (0-31)
+///     - 1: `$B` | 1 Byte | RegisterId | RegisterId holding the base memory address
+///     - 2: `#1` | 1 Byte | Offset | Offset added to the base address
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///     - `RuntimeError`: If any of the 4 bytes at the address are outside of memory
+///
+/// Examples
+/// ```asm
+/// STOREWR $1 $2 #4 // `2A 01 02 04` - Stores Register 1 into memory starting at the address in Register 2 plus 4
+/// ```
+#[derive(Instruction)]
+#[opcode = 42]
+#[signature = "STOREWR $S $B #1"]
+pub struct StoreWordRelative {
+    operand_values: OperandValues,
+}
+
+impl Executable for StoreWordRelative {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+        let address = relative_address(self, vm)?;
+
+        store_to_memory(vm, value, address, 4)
+    }
+}
+
+/// Push: Pushes the value of a register onto the top of the stack
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the source register (0-31)
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If the stack is already at its maximum depth
+///
+/// Examples
+/// ```asm
+/// PUSH $1 // `2B 01 00 00` - Pushes the value of Register 1 onto the stack
+/// ```
+#[derive(Instruction)]
+#[opcode = 43]
+#[signature = "PUSH $S"]
+pub struct Push {
+    operand_values: OperandValues,
+}
+
+impl Executable for Push {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+        vm.push_stack(value)?;
+
+        Ok(ExecutionResult::Value(value))
+    }
+}
+
+/// Pop: Removes the value on top of the stack and loads it into a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If the stack is empty
+///
+/// Examples
+/// ```asm
+/// POP $1 // `2C 01 00 00` - Pops the top of the stack into Register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 44]
+#[signature = "POP $D"]
+pub struct Pop {
+    operand_values: OperandValues,
+}
+
+impl Executable for Pop {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let value = vm.pop_stack()?;
+
+        vm.set_register(destination, value)?;
+        Ok(ExecutionResult::Value(value))
+    }
+}
+
+/// LoadConstant: Loads an entry of the constant pool into a register: an integer as it is,
+/// or the memory address of a string or byte array in the data section
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `#2` | 2 Bytes | HalfWord | Index of the constant pool entry
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///     - `RuntimeError`: If the loaded Program has no constant at the index
+///
+/// Examples
+/// ```asm
+/// LOADC $1 #0 // `2D 01 00 00` - Loads constant 0 into Register 1
+/// LOADC $2 @greeting // `2D 02 00 01` - Loads the constant labelled `greeting`, the second in the pool
+/// ```
+#[derive(Instruction)]
+#[opcode = 45]
+#[signature = "LOADC $D #2"]
+pub struct LoadConstant {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadConstant {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let index = self.operand_values[1].as_constant_value() as usize;
+
+        let value = match vm.constant(index)? {
+            Constant::Integer(value) => value,
+            Constant::Address(address) => address as RegisterValue,
+        };
+
+        vm.set_register(destination, value)?;
+        Ok(ExecutionResult::Value(value))
+    }
+}
+
+/// LoadSigned: Loads a value into a designated register, sign-extended from 16 bits
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `#2` | 2 Bytes | HalfWord | Literal value to be loaded, from -32768 to 32767
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///
+/// Examples
+/// ```asm
+/// LOADS $1 #-1 // `2E 01 FF FF` - Loads -1 into Register 1
+/// LOAD $1 #-500 // `2E 01 FE 0C` - The assembler picks LOADS for a small negative LOAD
+/// ```
+#[derive(Instruction)]
+#[opcode = 46]
+#[signature = "LOADS $D #2"]
+pub struct LoadSigned {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadSigned {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let value = self.operand_values[1].as_constant_value() as i16 as RegisterValue;
+
+        vm.set_register(destination, value)?;
+        Ok(ExecutionResult::Value(value))
+    }
+}
+
+/// LoadUpper: Replaces the upper 16 bits of a register, keeping its lower 16 bits
+/// After a `LOAD` of the lower half, this loads any 32-bit value
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `#2` | 2 Bytes | HalfWord | The upper 16 bits
+///     - 2: NOT USED, given to Operand 1
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If register is out of bounds
+///
+/// Examples
+/// ```asm
+/// LOADUP $1 #1 // `2F 01 00 01` - Adds 65536 to a Register 1 that holds less than 65536
+/// LOAD $1 #100000 // `1E 01 86 A0 2F 01 00 01` - The assembler picks LOAD then LOADUP for a large LOAD
+/// ```
+#[derive(Instruction)]
+#[opcode = 47]
+#[signature = "LOADUP $D #2"]
+pub struct LoadUpper {
+    operand_values: OperandValues,
+}
+
+impl Executable for LoadUpper {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let upper = self.operand_values[1].as_constant_value();
+        let value = (upper << 16) | (vm.register(destination)? & 0xFFFF);
+
+        vm.set_register(destination, value)?;
+        Ok(ExecutionResult::Value(value))
+    }
+}
+
+/// Computes the address for register-relative instructions: the value of the base register (operand 1) plus the offset (operand 2)
+fn relative_address<I: Instruction>(instruction: &I, vm: &mut Kaylee) -> Result<MemoryAddress, ExecutionError> {
+    let base = instruction.get_register_value_for_operand(1, vm)?;
+    let offset = instruction.operand_values()[2].as_constant_value();
+
+    // A negative address wraps to a value that is always out of bounds
+    let address = base as i64 + offset as i64;
+    MemoryAddress::try_from(address).map_err(|_| ExecutionError::MemoryOutOfBounds(address as MemoryAddress))
+}
+
+/// Reads `width` big-endian bytes from memory and loads them, zero-extended, into the destination register
+fn load_from_memory(vm: &mut Kaylee, destination: usize, address: MemoryAddress, width: usize) -> Result<ExecutionResult, ExecutionError> {
+    let value = vm.read_memory(address, width)?
+        .iter()
+        .fold(0_u32, |value, byte| (value << 8) | *byte as u32) as RegisterValue;
+
+    vm.set_register(destination, value)?;
+    Ok(ExecutionResult::Value(value))
+}
+
+/// Writes the lowest `width` bytes of a value into memory, big-endian
+fn store_to_memory(vm: &mut Kaylee, value: RegisterValue, address: MemoryAddress, width: usize) -> Result<ExecutionResult, ExecutionError> {
+    let bytes = value.to_be_bytes();
+    vm.write_memory(address, &bytes[(4 - width)..])?;
+
+    Ok(ExecutionResult::NoAction)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::instructions::data::Load;
-    use crate::program::Program;
-    use crate::vm::Kaylee;
+    use crate::instructions::data::{Load, LoadByte, LoadConstant, LoadSigned, LoadUpper, Pop, Push, LoadByteRelative, LoadHalfWord, LoadHalfWordRelative, LoadWord, LoadWordRelative, StoreByte, StoreByteRelative, StoreHalfWord, StoreHalfWordRelative, StoreWord, StoreWordRelative};
+    use crate::program::{Constant, Program};
+    use crate::vm::{ExecutionError, Kaylee};
 
     #[test]
     fn test_load() {
@@ -55,9 +602,160 @@ mod tests {
         ]);
 
         let mut vm = Kaylee::new();
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(500, vm.register(4).unwrap());
         assert_eq!(12, vm.register(30).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_store_and_load_absolute() {
+        let program = Program::from(vec![
+            StoreByte::OPCODE, 1, 0, 10,      // STOREB $1 #10
+            StoreHalfWord::OPCODE, 2, 0, 20,  // STOREH $2 #20
+            StoreWord::OPCODE, 3, 0, 30,      // STOREW $3 #30
+            LoadByte::OPCODE, 11, 0, 10,      // LOADB $11 #10
+            LoadHalfWord::OPCODE, 12, 0, 20,  // LOADH $12 #20
+            LoadWord::OPCODE, 13, 0, 30,      // LOADW $13 #30
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 0x1FF).unwrap();
+        vm.set_register(2, 0x1ABCD).unwrap();
+        vm.set_register(3, -2).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(&[0xFF], vm.read_memory(10, 1).unwrap());
+        assert_eq!(&[0xAB, 0xCD], vm.read_memory(20, 2).unwrap());
+        assert_eq!(&[0xFF, 0xFF, 0xFF, 0xFE], vm.read_memory(30, 4).unwrap());
+
+        assert_eq!(0xFF, vm.register(11).unwrap());
+        assert_eq!(0xABCD, vm.register(12).unwrap());
+        assert_eq!(-2, vm.register(13).unwrap());
+    }
+
+    #[test]
+    fn test_store_and_load_relative() {
+        let program = Program::from(vec![
+            StoreByteRelative::OPCODE, 1, 0, 2,       // STOREBR $1 $0 #2
+            StoreHalfWordRelative::OPCODE, 2, 0, 4,   // STOREHR $2 $0 #4
+            StoreWordRelative::OPCODE, 3, 0, 8,       // STOREWR $3 $0 #8
+            LoadByteRelative::OPCODE, 11, 0, 2,       // LOADBR $11 $0 #2
+            LoadHalfWordRelative::OPCODE, 12, 0, 4,   // LOADHR $12 $0 #4
+            LoadWordRelative::OPCODE, 13, 0, 8,       // LOADWR $13 $0 #8
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(0, 1000).unwrap();
+        vm.set_register(1, 7).unwrap();
+        vm.set_register(2, 300).unwrap();
+        vm.set_register(3, 123456).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(&[7], vm.read_memory(1002, 1).unwrap());
+        assert_eq!(7, vm.register(11).unwrap());
+        assert_eq!(300, vm.register(12).unwrap());
+        assert_eq!(123456, vm.register(13).unwrap());
+    }
+
+    #[test]
+    fn test_memory_out_of_bounds() {
+        let mut vm = Kaylee::new().with_memory_size(16);
+        let error = vm.run(Program::from(vec![
+            StoreWord::OPCODE, 1, 0, 14, // STOREW $1 #14
+        ])).unwrap_err();
+
+        assert_eq!(ExecutionError::MemoryOutOfBounds(14), error.error);
+
+        let mut vm = Kaylee::new().with_memory_size(16);
+        vm.set_register(0, -4).unwrap();
+        let error = vm.run(Program::from(vec![
+            LoadByteRelative::OPCODE, 1, 0, 1, // LOADBR $1 $0 #1
+        ])).unwrap_err();
+
+        assert!(matches!(error.error, ExecutionError::MemoryOutOfBounds(_)));
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let program = Program::from(vec![
+            Push::OPCODE, 1, 0, 0, // PUSH $1
+            Push::OPCODE, 2, 0, 0, // PUSH $2
+            Pop::OPCODE, 3, 0, 0,  // POP $3
+            Pop::OPCODE, 4, 0, 0,  // POP $4
+            Push::OPCODE, 1, 0, 0, // PUSH $1
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 100).unwrap();
+        vm.set_register(2, 200).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(200, vm.register(3).unwrap());
+        assert_eq!(100, vm.register(4).unwrap());
+        assert_eq!(&[100], vm.stack());
+    }
+
+    #[test]
+    fn test_stack_overflow_and_underflow() {
+        let mut vm = Kaylee::new().with_max_stack_depth(2);
+        let error = vm.run(Program::from(vec![
+            Push::OPCODE, 1, 0, 0,
+            Push::OPCODE, 1, 0, 0,
+            Push::OPCODE, 1, 0, 0,
+        ])).unwrap_err();
+
+        assert_eq!(ExecutionError::StackOverflow, error.error);
+        assert_eq!(8, error.program_index);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run(Program::from(vec![
+            Pop::OPCODE, 1, 0, 0,
+        ])).unwrap_err();
+
+        assert_eq!(ExecutionError::StackUnderflow, error.error);
+    }
+
+    #[test]
+    fn test_load_constant() {
+        let program = Program::from(vec![
+            LoadConstant::OPCODE, 1, 0, 0,  // LOADC $1 #0
+            LoadConstant::OPCODE, 2, 0, 1,  // LOADC $2 #1
+            LoadByte::OPCODE, 3, 0, 5,      // LOADB $3 #5
+            LoadConstant::OPCODE, 4, 0, 2,  // LOADC $4 #2
+        ])
+            .with_data(vec![1, 2, 3, 4, 5, 6])
+            .with_constant(Constant::Integer(-100_000))
+            .with_constant(Constant::Address(5));
+
+        let mut vm = Kaylee::new();
+        vm.load(&program).unwrap();
+
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(ExecutionError::UnknownConstant(2), error.error);
+        assert_eq!(-100_000, vm.register(1).unwrap());
+        assert_eq!(5, vm.register(2).unwrap());
+        assert_eq!(6, vm.register(3).unwrap());
+    }
+
+    #[test]
+    fn test_load_signed_and_upper() {
+        let program = Program::from(vec![
+            LoadSigned::OPCODE, 1, 255, 255,  // LOADS $1 #-1
+            LoadSigned::OPCODE, 2, 0, 7,      // LOADS $2 #7
+            Load::OPCODE, 3, 134, 160,        // LOAD $3 #34464
+            LoadUpper::OPCODE, 3, 0, 1,       // LOADUP $3 #1
+            LoadUpper::OPCODE, 1, 0, 0,       // LOADUP $1 #0
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.run(program).unwrap();
+
+        assert_eq!(65535, vm.register(1).unwrap());
+        assert_eq!(7, vm.register(2).unwrap());
+        assert_eq!(100_000, vm.register(3).unwrap());
+    }
+}
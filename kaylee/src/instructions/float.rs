@@ -0,0 +1,491 @@
+//! Instructions for the float register bank
+//! Float registers hold an f32 each, and are written `%` in assembly (`%0` - `%31`), where integer registers are `$`
+//! Float arithmetic follows IEEE 754: it never faults, and dividing by zero gives an infinity or NaN.
+//! Only the comparisons update the status flags.
+//! Opcodes reserved: 220 - 239
+use kaylee_derive::Instruction;
+
+use crate::instructions::{display_instruction_with_values, Executable, Instruction, InstructionDocumentation, InstructionSignature, OperandType, OperandValues};
+use crate::vm::{ExecutionError, ExecutionResult, Flags, FloatRegisterValue, Kaylee, RegisterValue};
+
+/// Reads the left (operand 1) and right (operand 2) float register terms of an instruction
+fn float_terms<I: Instruction>(instruction: &I, vm: &mut Kaylee) -> Result<(FloatRegisterValue, FloatRegisterValue), ExecutionError> {
+    let left = instruction.get_float_register_value_for_operand(1, vm)?;
+    let right = instruction.get_float_register_value_for_operand(2, vm)?;
+
+    Ok((left, right))
+}
+
+/// Helper for float arithmetic. Stores the result of the callback in the destination float register
+fn float_arithmetic_execution<I: Instruction, F: Fn(FloatRegisterValue, FloatRegisterValue) -> FloatRegisterValue>(instruction: &I, vm: &mut Kaylee, callback: F) -> Result<ExecutionResult, ExecutionError> {
+    let destination = instruction.operand_values()[0].as_register_id();
+    let (left, right) = float_terms(instruction, vm)?;
+
+    vm.set_float_register(destination, callback(left, right))?;
+    Ok(ExecutionResult::NoAction)
+}
+
+/// Helper for float comparisons. Stores the result of the callback as 0 or 1 in the destination integer register
+/// and sets the status flags from comparing the two terms
+fn float_compare_execution<I: Instruction, F: Fn(FloatRegisterValue, FloatRegisterValue) -> bool>(instruction: &I, vm: &mut Kaylee, callback: F) -> Result<ExecutionResult, ExecutionError> {
+    let destination = instruction.operand_values()[0].as_register_id();
+    let (left, right) = float_terms(instruction, vm)?;
+
+    let result = callback(left, right);
+
+    vm.set_flags(Flags::compare_floats(left, right));
+    vm.set_register(destination, result as RegisterValue)?;
+    Ok(ExecutionResult::Equality(result))
+}
+
+/// FloatLoadHigh: Loads a constant into the upper 16 bits of a float register's IEEE 754 bits, clearing the lower 16
+/// Together with `FLOADL`, this loads any f32. Many common values, like 1.5, only need `FLOADH`.
+/// Operands:
+///     - 0: `%D` | 1 Byte | FloatRegisterId | FloatRegisterId of the destination register (0-31)
+///     - 1: `#2` | 2 Bytes | Constant | The upper 16 bits
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If the register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FLOADH %01 #16320 // `DC 01 3F C0` - Loads 1.5 (0x3FC00000) into float register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 220]
+#[signature = "FLOADH %D #2"]
+pub struct FloatLoadHigh {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatLoadHigh {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let high = self.operand_values[1].as_constant_value() as u32;
+
+        vm.set_float_register(destination, f32::from_bits(high << 16))?;
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// FloatLoadLow: Loads a constant into the lower 16 bits of a float register's IEEE 754 bits, keeping the upper 16
+/// Operands:
+///     - 0: `%D` | 1 Byte | FloatRegisterId | FloatRegisterId of the destination register (0-31)
+///     - 1: `#2` | 2 Bytes | Constant | The lower 16 bits
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If the register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FLOADH %01 #16457 // `DC 01 40 49` - Loads 0x40490000 into float register 1
+/// FLOADL %01 #4059  // `DD 01 0F DB` - Completes pi (0x40490FDB)
+/// ```
+#[derive(Instruction)]
+#[opcode = 221]
+#[signature = "FLOADL %D #2"]
+pub struct FloatLoadLow {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatLoadLow {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let low = self.operand_values[1].as_constant_value() as u32;
+
+        let high = vm.float_register(destination)?.to_bits() & 0xFFFF_0000;
+        vm.set_float_register(destination, f32::from_bits(high | low))?;
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// FloatAdd: Sums the values of two float registers and loads the result into a third float register.
+/// Operands:
+///     - 0: `%D` | 1 Byte | FloatRegisterId | FloatRegisterId of the destination register (0-31)
+///     - 1: `%L` | 1 Byte | FloatRegisterId | FloatRegisterId of the left term
+///     - 2: `%R` | 1 Byte | FloatRegisterId | FloatRegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FADD %01 %10 %30 // `DE 01 0A 1E` - (%10 + %30) stored in float register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 222]
+#[signature = "FADD %D %L %R"]
+pub struct FloatAdd {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatAdd {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        float_arithmetic_execution(self, vm, |left, right| left + right)
+    }
+}
+
+/// FloatSubtract: Subtracts the values of two float registers and loads the result into a third float register.
+/// Operands:
+///     - 0: `%D` | 1 Byte | FloatRegisterId | FloatRegisterId of the destination register (0-31)
+///     - 1: `%L` | 1 Byte | FloatRegisterId | FloatRegisterId of the left term
+///     - 2: `%R` | 1 Byte | FloatRegisterId | FloatRegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FSUB %01 %10 %30 // `DF 01 0A 1E` - (%10 - %30) stored in float register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 223]
+#[signature = "FSUB %D %L %R"]
+pub struct FloatSubtract {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatSubtract {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        float_arithmetic_execution(self, vm, |left, right| left - right)
+    }
+}
+
+/// FloatMultiply: Multiplies the values of two float registers and loads the result into a third float register.
+/// Operands:
+///     - 0: `%D` | 1 Byte | FloatRegisterId | FloatRegisterId of the destination register (0-31)
+///     - 1: `%L` | 1 Byte | FloatRegisterId | FloatRegisterId of the left term
+///     - 2: `%R` | 1 Byte | FloatRegisterId | FloatRegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FMUL %01 %10 %30 // `E0 01 0A 1E` - (%10 * %30) stored in float register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 224]
+#[signature = "FMUL %D %L %R"]
+pub struct FloatMultiply {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatMultiply {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        float_arithmetic_execution(self, vm, |left, right| left * right)
+    }
+}
+
+/// FloatDivide: Divides the values of two float registers and loads the result into a third float register. Dividing by zero gives an infinity, or NaN for 0 / 0
+/// Operands:
+///     - 0: `%D` | 1 Byte | FloatRegisterId | FloatRegisterId of the destination register (0-31)
+///     - 1: `%L` | 1 Byte | FloatRegisterId | FloatRegisterId of the left term
+///     - 2: `%R` | 1 Byte | FloatRegisterId | FloatRegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FDIV %01 %10 %30 // `E1 01 0A 1E` - (%10 / %30) stored in float register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 225]
+#[signature = "FDIV %D %L %R"]
+pub struct FloatDivide {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatDivide {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        float_arithmetic_execution(self, vm, |left, right| left / right)
+    }
+}
+
+/// FloatCompare: Compares two float registers and only sets the status flags
+/// Zero is set if they are equal, negative if the left is less than the right, and carry if either is NaN
+/// Operands:
+///     - 0: `%L` | 1 Byte | FloatRegisterId | FloatRegisterId of the left term
+///     - 1: `%R` | 1 Byte | FloatRegisterId | FloatRegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FCMP %10 %30 // `E2 0A 1E 00` - Compares float registers 10 and 30
+/// ```
+#[derive(Instruction)]
+#[opcode = 226]
+#[signature = "FCMP %L %R"]
+pub struct FloatCompare {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatCompare {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let left = self.get_float_register_value_for_operand(0, vm)?;
+        let right = self.get_float_register_value_for_operand(1, vm)?;
+
+        vm.set_flags(Flags::compare_floats(left, right));
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// FloatEqual: Loads 1 into an integer register if two float registers are equal, or 0 if not (always 0 if either is NaN)
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `%L` | 1 Byte | FloatRegisterId | FloatRegisterId of the left term
+///     - 2: `%R` | 1 Byte | FloatRegisterId | FloatRegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FEQ $01 %10 %30 // `E3 01 0A 1E` - (%10 == %30) stored in register 1 as 0 or 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 227]
+#[signature = "FEQ $D %L %R"]
+pub struct FloatEqual {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatEqual {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        float_compare_execution(self, vm, |left, right| left == right)
+    }
+}
+
+/// FloatLessThan: Loads 1 into an integer register if two float registers the left is less than the right, or 0 if not (always 0 if either is NaN)
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `%L` | 1 Byte | FloatRegisterId | FloatRegisterId of the left term
+///     - 2: `%R` | 1 Byte | FloatRegisterId | FloatRegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FLT $01 %10 %30 // `E4 01 0A 1E` - (%10 < %30) stored in register 1 as 0 or 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 228]
+#[signature = "FLT $D %L %R"]
+pub struct FloatLessThan {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatLessThan {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        float_compare_execution(self, vm, |left, right| left < right)
+    }
+}
+
+/// FloatLessThanOrEqual: Loads 1 into an integer register if two float registers the left is less than or equal to the right, or 0 if not (always 0 if either is NaN)
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `%L` | 1 Byte | FloatRegisterId | FloatRegisterId of the left term
+///     - 2: `%R` | 1 Byte | FloatRegisterId | FloatRegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// FLTE $01 %10 %30 // `E5 01 0A 1E` - (%10 <= %30) stored in register 1 as 0 or 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 229]
+#[signature = "FLTE $D %L %R"]
+pub struct FloatLessThanOrEqual {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatLessThanOrEqual {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        float_compare_execution(self, vm, |left, right| left <= right)
+    }
+}
+
+/// IntegerToFloat: Converts the value of an integer register to the nearest float and loads it into a float register
+/// Operands:
+///     - 0: `%D` | 1 Byte | FloatRegisterId | FloatRegisterId of the destination register (0-31)
+///     - 1: `$S` | 1 Byte | RegisterId | RegisterId of the integer to convert
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// ITOF %01 $02 // `E6 01 02 00` - Converts register 2 into float register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 230]
+#[signature = "ITOF %D $S"]
+pub struct IntegerToFloat {
+    operand_values: OperandValues,
+}
+
+impl Executable for IntegerToFloat {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let value = self.get_register_value_for_operand(1, vm)?;
+
+        vm.set_float_register(destination, value as FloatRegisterValue)?;
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// FloatToInteger: Converts the value of a float register to an integer, rounding toward zero, and loads it into an integer register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `%S` | 1 Byte | FloatRegisterId | FloatRegisterId of the float to convert
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///     - `ProgramPanic`: `ArithmeticOverflow` if the float is NaN or does not fit in a register
+///
+/// Examples
+/// ```asm
+/// FTOI $01 %02 // `E7 01 02 00` - Converts float register 2 into register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 231]
+#[signature = "FTOI $D %S"]
+pub struct FloatToInteger {
+    operand_values: OperandValues,
+}
+
+impl Executable for FloatToInteger {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let value = self.get_float_register_value_for_operand(1, vm)?.trunc();
+
+        // i32::MAX is not exactly representable as an f32, so the upper bound is exclusive of 2^31
+        if value.is_nan() || value < RegisterValue::MIN as FloatRegisterValue || value >= 2_147_483_648.0 {
+            return Err(ExecutionError::ArithmeticOverflow);
+        }
+
+        vm.set_register(destination, value as RegisterValue)?;
+        Ok(ExecutionResult::Value(value as RegisterValue))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::data::Load;
+    use crate::instructions::float::{FloatAdd, FloatCompare, FloatDivide, FloatEqual, FloatLessThan, FloatLessThanOrEqual, FloatLoadHigh, FloatLoadLow, FloatMultiply, FloatSubtract, FloatToInteger, IntegerToFloat};
+    use crate::program::Program;
+    use crate::vm::{ExecutionError, Flags, Kaylee};
+
+    #[test]
+    fn test_load_float_constants() {
+        let program = Program::from(vec![
+            FloatLoadHigh::OPCODE, 1, 0x3F, 0xC0,   // FLOADH %1 #16320
+            FloatLoadHigh::OPCODE, 2, 0x40, 0x49,   // FLOADH %2 #16457
+            FloatLoadLow::OPCODE, 2, 0x0F, 0xDB,    // FLOADL %2 #4059
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.run(program).unwrap();
+
+        assert_eq!(1.5, vm.float_register(1).unwrap());
+        assert_eq!(std::f32::consts::PI, vm.float_register(2).unwrap());
+    }
+
+    #[test]
+    fn test_float_arithmetic() {
+        let program = Program::from(vec![
+            FloatAdd::OPCODE, 10, 1, 2,         // FADD %10 %1 %2
+            FloatSubtract::OPCODE, 11, 1, 2,    // FSUB %11 %1 %2
+            FloatMultiply::OPCODE, 12, 1, 2,    // FMUL %12 %1 %2
+            FloatDivide::OPCODE, 13, 1, 2,      // FDIV %13 %1 %2
+            FloatDivide::OPCODE, 14, 1, 0,      // FDIV %14 %1 %0
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_float_register(1, 7.5).unwrap();
+        vm.set_float_register(2, 2.5).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(10.0, vm.float_register(10).unwrap());
+        assert_eq!(5.0, vm.float_register(11).unwrap());
+        assert_eq!(18.75, vm.float_register(12).unwrap());
+        assert_eq!(3.0, vm.float_register(13).unwrap());
+        assert_eq!(f32::INFINITY, vm.float_register(14).unwrap());
+        assert_eq!(Flags::default(), vm.flags());
+    }
+
+    #[test]
+    fn test_float_comparisons() {
+        let program = Program::from(vec![
+            FloatEqual::OPCODE, 10, 1, 2,           // FEQ $10 %1 %2
+            FloatLessThan::OPCODE, 11, 1, 2,        // FLT $11 %1 %2
+            FloatLessThanOrEqual::OPCODE, 12, 2, 2, // FLTE $12 %2 %2
+            FloatCompare::OPCODE, 1, 2, 0,          // FCMP %1 %2
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_float_register(1, -1.25).unwrap();
+        vm.set_float_register(2, 3.0).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(0, vm.register(10).unwrap());
+        assert_eq!(1, vm.register(11).unwrap());
+        assert_eq!(1, vm.register(12).unwrap());
+        assert_eq!(Flags { zero: false, negative: true, carry: false, overflow: false }, vm.flags());
+
+        let mut vm = Kaylee::new();
+        vm.set_float_register(1, f32::NAN).unwrap();
+
+        vm.run(Program::from(vec![FloatEqual::OPCODE, 10, 1, 1])).unwrap();
+
+        assert_eq!(0, vm.register(10).unwrap());
+        assert!(vm.flags().carry);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 7,              // LOAD $1 #7
+            IntegerToFloat::OPCODE, 1, 1, 0,    // ITOF %1 $1
+            FloatDivide::OPCODE, 2, 1, 3,       // FDIV %2 %1 %3
+            FloatToInteger::OPCODE, 2, 2, 0,    // FTOI $2 %2
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_float_register(3, -2.0).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(7.0, vm.float_register(1).unwrap());
+        assert_eq!(-3.5, vm.float_register(2).unwrap());
+        assert_eq!(-3, vm.register(2).unwrap());
+    }
+
+    #[test]
+    fn test_float_to_integer_out_of_range() {
+        for value in [f32::NAN, f32::INFINITY, 3.0e9, -3.0e9] {
+            let mut vm = Kaylee::new();
+            vm.set_float_register(1, value).unwrap();
+
+            let error = vm.run(Program::from(vec![FloatToInteger::OPCODE, 1, 1, 0])).unwrap_err();
+            assert_eq!(ExecutionError::ArithmeticOverflow, error.error, "{value}");
+        }
+    }
+
+    #[test]
+    fn test_float_register_out_of_bounds() {
+        let mut vm = Kaylee::new();
+        let error = vm.run(Program::from(vec![FloatAdd::OPCODE, 1, 2, 40])).unwrap_err();
+
+        assert_eq!(ExecutionError::RegisterOutOfBounds(40), error.error);
+        assert_eq!(Some(String::from("FADD %1 %2 %40")), error.instruction);
+    }
+}
@@ -1,15 +1,14 @@
 //! Instructions for controlling the Virtual Machine
 //! Opcodes reserved: 0 - 29
-use std::fmt::Error;
-
 use kaylee_derive::Instruction;
 
 use crate::instructions::{display_instruction_with_values, Executable, Instruction, InstructionDocumentation, InstructionSignature, OperandType, OperandValues};
-use crate::vm::{ExecutionResult, Kaylee};
+use crate::threads::ThreadId;
+use crate::vm::{ExecutionError, ExecutionResult, ExitStatus, Kaylee, RegisterValue};
 
-/// Halt: Gracefully ends the program and shuts down the process
+/// Halt: Gracefully ends the program and shuts down the process with a status code
 /// Operands:
-///     - None
+///     - 0: `#1` | 1 Byte | Constant | Status code to exit with. Omit it, or use 0, for success
 ///
 /// Errors/ Panics
 ///     - None
@@ -17,17 +16,208 @@ use crate::vm::{ExecutionResult, Kaylee};
 /// Examples
 /// ```asm
 /// HALT // `01 00 00 00`
+/// HALT #3 // `01 03 00 00` - Exits with status code 3
 /// ```
 #[derive(Instruction)]
 #[opcode = 1]
-#[signature = "HALT"]
+#[signature = "HALT #1"]
 pub struct Halt {
     operand_values: OperandValues,
 }
 
 impl Executable for Halt {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        vm.halt();
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let code = self.operand_values[0].as_constant_value() as u8;
+
+        vm.exit(ExitStatus::Halted(code));
+        Ok(ExecutionResult::Halted)
+    }
+}
+
+/// Abort: Ends the program because something went wrong, with a status code and a message
+/// Operands:
+///     - 0: `#1` | 1 Byte | Constant | Status code to exit with. The process exits with 1 if this is 0
+///     - 1: `#2` | 2 Bytes | Constant | Memory address of the null-terminated message
+///
+/// Errors/ Panics
+///     - `ProgramPanic`: `MemoryOutOfBounds` if the message is not null-terminated inside of memory
+///
+/// Examples
+/// ```asm
+/// DIE #1 #256 // `02 01 01 00` - Exits with status code 1 and the message stored at address 256
+/// ```
+#[derive(Instruction)]
+#[opcode = 2]
+#[signature = "DIE #1 #2"]
+pub struct Abort {
+    operand_values: OperandValues,
+}
+
+impl Executable for Abort {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let code = self.operand_values[0].as_constant_value() as u8;
+        let address = self.operand_values[1].as_constant_value() as usize;
+
+        let message = vm.read_string(address)?;
+
+        vm.exit(ExitStatus::Aborted { code, message });
         Ok(ExecutionResult::Halted)
     }
+}
+
+/// Spawn: Starts a new thread at a program index. The new thread begins with a copy of this thread's registers
+/// and float registers, an empty stack and clear flags. It first runs once this thread yields.
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | Destination register for the new thread's id
+///     - 1: `#2` | 2 Bytes | Constant | Program index the new thread starts at
+///
+/// Errors/ Panics
+///     - None
+///
+/// Examples
+/// ```asm
+/// SPAWN $10 #64 // `03 0A 00 40` - Starts a thread at index 64 and puts its id in $10
+/// ```
+#[derive(Instruction)]
+#[opcode = 3]
+#[signature = "SPAWN $D #2"]
+pub struct Spawn {
+    operand_values: OperandValues,
+}
+
+impl Executable for Spawn {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let start = self.operand_values[1].as_program_index();
+
+        let id = vm.spawn(start);
+        vm.set_register(destination, id as RegisterValue)?;
+
+        Ok(ExecutionResult::Value(id as RegisterValue))
+    }
+}
+
+/// Yield: Gives the rest of this thread's turn to the next waiting thread
+/// Operands:
+///     - None
+///
+/// Errors/ Panics
+///     - None
+///
+/// Examples
+/// ```asm
+/// YIELD // `04 00 00 00`
+/// ```
+#[derive(Instruction)]
+#[opcode = 4]
+#[signature = "YIELD"]
+pub struct Yield {
+    operand_values: OperandValues,
+}
+
+impl Executable for Yield {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        vm.request_yield();
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// Join: Waits for a thread to end, then puts the value it left in its `$0` into the destination register.
+/// While the thread is still running, this thread is blocked: the scheduler passes over it until the thread ends,
+/// and then it executes the `JOIN` again.
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | Destination register for the thread's result
+///     - 1: `$T` | 1 Byte | RegisterId | Register holding the id of the thread to wait for
+///
+/// Errors/ Panics
+///     - `ProgramPanic`: `InvalidJoin` if the thread was never spawned, is the thread executing the `JOIN`,
+///       or is waiting for the thread executing the `JOIN`, directly or through other threads
+///
+/// Examples
+/// ```asm
+/// JOIN $12 $10 // `05 0C 0A 00` - Waits for the thread whose id is in $10 and puts its result in $12
+/// ```
+#[derive(Instruction)]
+#[opcode = 5]
+#[signature = "JOIN $D $T"]
+pub struct Join {
+    operand_values: OperandValues,
+}
+
+impl Executable for Join {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+        let thread = self.get_register_value_for_operand(1, vm)? as ThreadId;
+
+        match vm.thread_result(thread)? {
+            Some(value) => {
+                vm.stop_waiting();
+                vm.set_register(destination, value)?;
+                Ok(ExecutionResult::Value(value))
+            }
+            None => {
+                vm.wait_for(thread)?;
+
+                // Come back to this JOIN once the thread has ended
+                let index = vm.program_counter() - 4;
+                vm.set_program_counter(index);
+                vm.request_yield();
+
+                Ok(ExecutionResult::Jumped(index))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::data::StoreWord;
+    use crate::instructions::machine::{Abort, Halt};
+    use crate::program::Program;
+    use crate::vm::{ExecutionError, ExitStatus, Kaylee};
+
+    #[test]
+    fn test_halt_with_status_code() {
+        let mut vm = Kaylee::new();
+        assert_eq!(ExitStatus::Halted(0), vm.run(Program::from(vec![Halt::OPCODE, 0, 0, 0])).unwrap());
+
+        let mut vm = Kaylee::new();
+        let status = vm.run(Program::from(vec![Halt::OPCODE, 42, 0, 0, Halt::OPCODE, 0, 0, 0])).unwrap();
+
+        assert_eq!(ExitStatus::Halted(42), status);
+        assert_eq!(42, status.code());
+        assert_eq!(Some(&ExitStatus::Halted(42)), vm.exit_status());
+        assert_eq!(4, vm.program_counter());
+    }
+
+    #[test]
+    fn test_abort_with_message() {
+        let program = Program::from(vec![
+            StoreWord::OPCODE, 1, 0, 100,   // STOREW $1 #100
+            Abort::OPCODE, 7, 0, 100,       // DIE #7 #100
+            Halt::OPCODE, 0, 0, 0,          // HALT
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, i32::from_be_bytes(*b"oops")).unwrap();
+
+        let status = vm.run(program).unwrap();
+
+        assert_eq!(ExitStatus::Aborted { code: 7, message: String::from("oops") }, status);
+        assert_eq!(7, status.code());
+        assert!(!status.is_success());
+        assert_eq!(0, ExitStatus::Finished.code());
+        assert_eq!(1, ExitStatus::Aborted { code: 0, message: String::new() }.code());
+    }
+
+    #[test]
+    fn test_abort_message_must_be_terminated() {
+        let mut vm = Kaylee::new().with_memory_size(8);
+        vm.write_memory(0, &[b'x'; 8]).unwrap();
+
+        let error = vm.run(Program::from(vec![Abort::OPCODE, 1, 0, 2])).unwrap_err();
+
+        assert_eq!(ExecutionError::MemoryOutOfBounds(2), error.error);
+        assert!(!vm.is_halted());
+    }
 }
\ No newline at end of file
@@ -1,12 +1,19 @@
 //! Instructions for arithmetic operations
 //! Opcodes reserved: 70 - 99
-use std::fmt::Error;
-
+//!
+//! Every arithmetic instruction updates the status flags (zero, negative, carry, overflow) from its result,
+//! including one that faults because of an overflow.
+//!
+//! Overflow behaviour is part of each opcode, so it is identical in debug and release builds:
+//!     - `ADD`, `SUB`, `MUL`, `DIV`: Trap. Fault with `ArithmeticOverflow` and leave the destination untouched
+//!     - `ADDW`, `SUBW`, `MULW`: Wrap. Keep the low 32 bits of the result (two's complement)
+//!     - `ADDS`, `SUBS`, `MULS`: Saturate. Clamp the result to `i32::MIN` or `i32::MAX`
+//! A zero divisor always faults with `DivideByZero`.
 use kaylee_derive::Instruction;
 
 use crate::instructions;
-use crate::instructions::{display_instruction_with_values, Executable, Instruction, InstructionDocumentation, InstructionSignature, OperandType, OperandValues};
-use crate::vm::{ExecutionResult, Kaylee, RegisterValue};
+use crate::instructions::{Arithmetic, display_instruction_with_values, Executable, Instruction, InstructionDocumentation, InstructionSignature, OperandType, OperandValues, Overflow};
+use crate::vm::{ExecutionError, ExecutionResult, Flags, Kaylee};
 
 /// Add: Sums the value of two registers and loads the result into a third register
 /// Operands:
@@ -31,10 +38,8 @@ pub struct Add {
 }
 
 impl Executable for Add {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left + right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::add, Overflow::Trap)?;
         Ok(ExecutionResult::Value(result))
     }
 }
@@ -62,10 +67,8 @@ pub struct Subtract {
 }
 
 impl Executable for Subtract {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left - right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::subtract, Overflow::Trap)?;
         Ok(ExecutionResult::Value(result))
     }
 }
@@ -93,10 +96,8 @@ pub struct Multiply {
 }
 
 impl Executable for Multiply {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let callback = |left: RegisterValue, right: RegisterValue| { (left * right) as RegisterValue };
-
-        let result = instructions::basic_register_execution(self, vm, callback);
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::multiply, Overflow::Trap)?;
         Ok(ExecutionResult::Value(result))
     }
 }
@@ -110,6 +111,7 @@ impl Executable for Multiply {
 /// Errors/ Panics
 ///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
 ///     - `RuntimeError`: If the result is too large for a destination register
+///     - `RuntimeError`: If the right term is zero
 ///
 /// Examples
 /// ```asm
@@ -124,27 +126,199 @@ pub struct Divide {
 }
 
 impl Executable for Divide {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
         let destination = self.operand_values[0].as_register_id();
 
-        let left = self.get_register_value_for_operand(1, vm).unwrap();
-        let right = self.get_register_value_for_operand(2, vm).unwrap();
+        let left = self.get_register_value_for_operand(1, vm)?;
+        let right = self.get_register_value_for_operand(2, vm)?;
+
+        if right == 0 {
+            return Err(ExecutionError::DivideByZero);
+        }
+
+        let (value, overflow) = left.overflowing_div(right);
+        vm.set_flags(Flags::arithmetic(value, false, overflow));
+
+        if overflow {
+            return Err(ExecutionError::ArithmeticOverflow);
+        }
 
-        let value = left / right;
         let remainder = (left % right) as u32;
 
-        vm.set_register(destination, value).unwrap();
+        vm.set_register(destination, value)?;
         vm.set_remainder(remainder);
 
         Ok(ExecutionResult::Value(value))
     }
 }
 
+/// WrappingAdd: Sums the values of two registers and loads the result into a third register, wrapping around (two's complement) if the result does not fit in a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$L` | 1 Byte | RegisterId | RegisterId of the left term
+///     - 2: `$R` | 1 Byte | RegisterId | RegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// ADDW $01 $10 $30 // `4A 01 0A 1E` - ($10 + $30) stored in register 1, wrapped on overflow
+/// ```
+#[derive(Instruction)]
+#[opcode = 74]
+#[signature = "ADDW $D $L $R"]
+pub struct WrappingAdd {
+    operand_values: OperandValues,
+}
+
+impl Executable for WrappingAdd {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::add, Overflow::Wrap)?;
+        Ok(ExecutionResult::Value(result))
+    }
+}
+
+/// WrappingSubtract: Subtracts the values of two registers and loads the result into a third register, wrapping around (two's complement) if the result does not fit in a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$L` | 1 Byte | RegisterId | RegisterId of the left term
+///     - 2: `$R` | 1 Byte | RegisterId | RegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// SUBW $01 $10 $30 // `4B 01 0A 1E` - ($10 - $30) stored in register 1, wrapped on overflow
+/// ```
+#[derive(Instruction)]
+#[opcode = 75]
+#[signature = "SUBW $D $L $R"]
+pub struct WrappingSubtract {
+    operand_values: OperandValues,
+}
+
+impl Executable for WrappingSubtract {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::subtract, Overflow::Wrap)?;
+        Ok(ExecutionResult::Value(result))
+    }
+}
+
+/// WrappingMultiply: Multiplies the values of two registers and loads the result into a third register, wrapping around (two's complement) if the result does not fit in a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$L` | 1 Byte | RegisterId | RegisterId of the left term
+///     - 2: `$R` | 1 Byte | RegisterId | RegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// MULW $01 $10 $30 // `4C 01 0A 1E` - ($10 * $30) stored in register 1, wrapped on overflow
+/// ```
+#[derive(Instruction)]
+#[opcode = 76]
+#[signature = "MULW $D $L $R"]
+pub struct WrappingMultiply {
+    operand_values: OperandValues,
+}
+
+impl Executable for WrappingMultiply {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::multiply, Overflow::Wrap)?;
+        Ok(ExecutionResult::Value(result))
+    }
+}
+
+/// SaturatingAdd: Sums the values of two registers and loads the result into a third register, clamping to `i32::MIN` or `i32::MAX` if the result does not fit in a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$L` | 1 Byte | RegisterId | RegisterId of the left term
+///     - 2: `$R` | 1 Byte | RegisterId | RegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// ADDS $01 $10 $30 // `4D 01 0A 1E` - ($10 + $30) stored in register 1, saturated on overflow
+/// ```
+#[derive(Instruction)]
+#[opcode = 77]
+#[signature = "ADDS $D $L $R"]
+pub struct SaturatingAdd {
+    operand_values: OperandValues,
+}
+
+impl Executable for SaturatingAdd {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::add, Overflow::Saturate)?;
+        Ok(ExecutionResult::Value(result))
+    }
+}
+
+/// SaturatingSubtract: Subtracts the values of two registers and loads the result into a third register, clamping to `i32::MIN` or `i32::MAX` if the result does not fit in a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$L` | 1 Byte | RegisterId | RegisterId of the left term
+///     - 2: `$R` | 1 Byte | RegisterId | RegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// SUBS $01 $10 $30 // `4E 01 0A 1E` - ($10 - $30) stored in register 1, saturated on overflow
+/// ```
+#[derive(Instruction)]
+#[opcode = 78]
+#[signature = "SUBS $D $L $R"]
+pub struct SaturatingSubtract {
+    operand_values: OperandValues,
+}
+
+impl Executable for SaturatingSubtract {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::subtract, Overflow::Saturate)?;
+        Ok(ExecutionResult::Value(result))
+    }
+}
+
+/// SaturatingMultiply: Multiplies the values of two registers and loads the result into a third register, clamping to `i32::MIN` or `i32::MAX` if the result does not fit in a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///     - 1: `$L` | 1 Byte | RegisterId | RegisterId of the left term
+///     - 2: `$R` | 1 Byte | RegisterId | RegisterId of the right term
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If any register is out of bounds
+///
+/// Examples
+/// ```asm
+/// MULS $01 $10 $30 // `4F 01 0A 1E` - ($10 * $30) stored in register 1, saturated on overflow
+/// ```
+#[derive(Instruction)]
+#[opcode = 79]
+#[signature = "MULS $D $L $R"]
+pub struct SaturatingMultiply {
+    operand_values: OperandValues,
+}
+
+impl Executable for SaturatingMultiply {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let result = instructions::arithmetic_register_execution(self, vm, Arithmetic::multiply, Overflow::Saturate)?;
+        Ok(ExecutionResult::Value(result))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::instructions::math::{Add, Divide, Multiply, Subtract};
+    use crate::instructions::math::{Add, Divide, Multiply, SaturatingAdd, SaturatingMultiply, SaturatingSubtract, Subtract, WrappingAdd, WrappingMultiply, WrappingSubtract};
     use crate::program::Program;
-    use crate::vm::Kaylee;
+    use crate::vm::{ExecutionError, Flags, Kaylee};
 
     #[test]
     fn test_add() {
@@ -164,7 +338,7 @@ mod tests {
         // $30[17] = 10 + 7
         // $31[529] = 512 + 17
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(512, vm.register(29).unwrap());
         assert_eq!(17, vm.register(30).unwrap());
@@ -189,7 +363,7 @@ mod tests {
         // $30[11] = 14 - 3
         // $31[189] = 200 - 11
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(200, vm.register(29).unwrap());
         assert_eq!(11, vm.register(30).unwrap());
@@ -214,7 +388,7 @@ mod tests {
         // $30[32] = 4 * 8
         // $31[384] = 12 * 32
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(12, vm.register(29).unwrap());
         assert_eq!(32, vm.register(30).unwrap());
@@ -231,7 +405,7 @@ mod tests {
         vm.set_register(0, 16).unwrap();
         vm.set_register(1, 2).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(8, vm.register(31).unwrap());
         assert_eq!(0, vm.remainder());
@@ -247,7 +421,7 @@ mod tests {
         vm.set_register(0, 13).unwrap();
         vm.set_register(1, 5).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(2, vm.register(31).unwrap());
         assert_eq!(3, vm.remainder());
@@ -286,7 +460,7 @@ mod tests {
         // 1[4] = 2 * 2
         // 31[4r1] = 17 / 4
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(19, vm.register(0).unwrap());
         assert_eq!(4, vm.register(1).unwrap());
@@ -299,4 +473,113 @@ mod tests {
         assert_eq!(4, vm.register(31).unwrap());
         assert_eq!(1, vm.remainder());
     }
+
+    #[test]
+    fn test_arithmetic_flags() {
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 5).unwrap();
+        vm.set_register(2, -5).unwrap();
+
+        vm.run(Program::from(vec![Add::OPCODE, 0, 1, 2])).unwrap();
+        assert_eq!(Flags { zero: true, negative: false, carry: true, overflow: false }, vm.flags());
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 5).unwrap();
+        vm.set_register(2, 7).unwrap();
+
+        vm.run(Program::from(vec![Subtract::OPCODE, 0, 1, 2])).unwrap();
+        assert_eq!(Flags { zero: false, negative: true, carry: true, overflow: false }, vm.flags());
+    }
+
+    #[test]
+    fn test_overflow_sets_flag_and_faults() {
+        let mut vm = Kaylee::new();
+        vm.set_register(1, i32::MAX).unwrap();
+        vm.set_register(2, 2).unwrap();
+
+        let error = vm.run(Program::from(vec![Multiply::OPCODE, 0, 1, 2])).unwrap_err();
+
+        assert_eq!(ExecutionError::ArithmeticOverflow, error.error);
+        assert!(vm.flags().overflow);
+        assert_eq!(0, vm.register(0).unwrap());
+    }
+
+    #[test]
+    fn test_trapping_arithmetic() {
+        // MAX + MAX, MIN - MAX, MAX * 7
+        for (opcode, left, right) in [(Add::OPCODE, 2, 2), (Subtract::OPCODE, 1, 2), (Multiply::OPCODE, 2, 3)] {
+            let mut vm = Kaylee::new();
+            vm.set_register(1, i32::MIN).unwrap();
+            vm.set_register(2, i32::MAX).unwrap();
+            vm.set_register(3, 7).unwrap();
+
+            let error = vm.run(Program::from(vec![opcode, 0, left, right])).unwrap_err();
+            assert_eq!(ExecutionError::ArithmeticOverflow, error.error, "opcode {opcode}");
+            assert_eq!(0, vm.register(0).unwrap());
+        }
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, i32::MIN).unwrap();
+        vm.set_register(2, -1).unwrap();
+
+        let error = vm.run(Program::from(vec![Divide::OPCODE, 0, 1, 2])).unwrap_err();
+        assert_eq!(ExecutionError::ArithmeticOverflow, error.error);
+    }
+
+    #[test]
+    fn test_wrapping_arithmetic() {
+        let program = Program::from(vec![
+            WrappingAdd::OPCODE, 10, 2, 3,      // MAX + 7
+            WrappingSubtract::OPCODE, 11, 1, 3, // MIN - 7
+            WrappingMultiply::OPCODE, 12, 2, 3, // MAX * 7
+            WrappingAdd::OPCODE, 13, 3, 3,      // 7 + 7
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, i32::MIN).unwrap();
+        vm.set_register(2, i32::MAX).unwrap();
+        vm.set_register(3, 7).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(i32::MAX.wrapping_add(7), vm.register(10).unwrap());
+        assert_eq!(i32::MIN.wrapping_sub(7), vm.register(11).unwrap());
+        assert_eq!(i32::MAX.wrapping_mul(7), vm.register(12).unwrap());
+        assert_eq!(14, vm.register(13).unwrap());
+        assert!(!vm.flags().overflow);
+    }
+
+    #[test]
+    fn test_saturating_arithmetic() {
+        let program = Program::from(vec![
+            SaturatingAdd::OPCODE, 10, 2, 3,        // MAX + 7
+            SaturatingSubtract::OPCODE, 11, 1, 3,   // MIN - 7
+            SaturatingMultiply::OPCODE, 12, 1, 3,   // MIN * 7
+            SaturatingSubtract::OPCODE, 13, 3, 3,   // 7 - 7
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, i32::MIN).unwrap();
+        vm.set_register(2, i32::MAX).unwrap();
+        vm.set_register(3, 7).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!(i32::MAX, vm.register(10).unwrap());
+        assert_eq!(i32::MIN, vm.register(11).unwrap());
+        assert_eq!(i32::MIN, vm.register(12).unwrap());
+        assert_eq!(0, vm.register(13).unwrap());
+        assert_eq!(Flags { zero: true, negative: false, carry: false, overflow: false }, vm.flags());
+    }
+
+    #[test]
+    fn test_overflow_flag_is_set_without_trapping() {
+        let mut vm = Kaylee::new();
+        vm.set_register(1, i32::MAX).unwrap();
+
+        vm.run(Program::from(vec![SaturatingAdd::OPCODE, 0, 1, 1])).unwrap();
+
+        assert_eq!(i32::MAX, vm.register(0).unwrap());
+        assert!(vm.flags().overflow);
+    }
 }
@@ -1,2 +1,2 @@
 //! Instructions for Misc or overflow operations
-//! Opcodes reserved: 220 - 255
\ No newline at end of file
+//! Opcodes reserved: 240 - 255
\ No newline at end of file
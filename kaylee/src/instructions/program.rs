@@ -1,11 +1,10 @@
 //! Instructions for navigating and manipulating the program
 //! Opcodes reserved: 50 - 69
-use std::fmt::Error;
-
 use kaylee_derive::Instruction;
 
 use crate::instructions::{display_instruction_with_values, Executable, Instruction, InstructionDocumentation, InstructionSignature, OperandType, OperandValues};
-use crate::vm::{ExecutionResult, Kaylee, RegisterId};
+use crate::program::ProgramIndex;
+use crate::vm::{ExecutionError, ExecutionResult, FaultCode, Kaylee, RegisterId, RegisterValue};
 
 /// Jump: Resets the program counter to a constant value
 /// Operands:
@@ -27,7 +26,7 @@ pub struct Jump {
 }
 
 impl Executable for Jump {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
         let destination = self.operand_values[0].as_program_index();
 
         vm.set_program_counter(destination);
@@ -55,7 +54,7 @@ pub struct JumpForward {
 }
 
 impl Executable for JumpForward {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
         let forward = self.operand_values[0].as_constant_value();
         let steps = (forward * 4) as usize;
 
@@ -84,12 +83,15 @@ pub struct JumpBackward {
 }
 
 impl Executable for JumpBackward {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
         let backward = self.operand_values[0].as_constant_value();
         let steps = ((backward + 1) * 4) as usize;
 
-        vm.set_program_counter(vm.program_counter() - steps);
-        Ok(ExecutionResult::Jumped(vm.program_counter()))
+        let destination = vm.program_counter().checked_sub(steps)
+            .ok_or(ExecutionError::ProgramCounterOutOfBounds(vm.program_counter()))?;
+
+        vm.set_program_counter(destination);
+        Ok(ExecutionResult::Jumped(destination))
     }
 }
 
@@ -115,10 +117,10 @@ pub struct JumpEqual {
 }
 
 impl Executable for JumpEqual {
-    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, Error> {
-        let destination = self.get_register_value_for_operand(0, vm).unwrap();
-        let left = self.get_register_value_for_operand(1, vm).unwrap();
-        let right = self.get_register_value_for_operand(2, vm).unwrap();
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.get_register_value_for_operand(0, vm)?;
+        let left = self.get_register_value_for_operand(1, vm)?;
+        let right = self.get_register_value_for_operand(2, vm)?;
 
         if left == right {
             vm.set_program_counter(destination as RegisterId);
@@ -129,13 +131,331 @@ impl Executable for JumpEqual {
     }
 }
 
+/// Call: Pushes the ProgramIndex of the next instruction onto the stack and jumps to a subroutine
+/// Operands:
+///     - 0: `#ADDRESS` | 3 Bytes | ProgramIndex | ProgramIndex of the subroutine
+///
+/// Errors/ Panics
+///     - `AssemblerError`: If the ProgramIndex is a value larger than 3 bytes
+///     - `RuntimeError`: If the stack is already at its maximum depth
+///     - `RuntimeError`: If the target ProgramIndex is out of bounds
+///
+/// Examples
+/// ```asm
+/// CALL #500 // `36 00 01 F4` - Calls the subroutine at program index 500
+/// ```
+#[derive(Instruction)]
+#[opcode = 54]
+#[signature = "CALL #3"]
+pub struct Call {
+    operand_values: OperandValues,
+}
+
+impl Executable for Call {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_program_index();
+
+        vm.push_stack(vm.program_counter() as RegisterValue)?;
+        vm.set_program_counter(destination);
+        Ok(ExecutionResult::Jumped(destination))
+    }
+}
+
+/// Return: Pops a ProgramIndex from the stack and jumps back to it, ending a subroutine
+/// Operands:
+///     - None
+///
+/// Errors/ Panics
+///     - `RuntimeError`: If the stack is empty
+///     - `RuntimeError`: If the popped ProgramIndex is out of bounds
+///
+/// Examples
+/// ```asm
+/// RET // `37 00 00 00`
+/// ```
+#[derive(Instruction)]
+#[opcode = 55]
+#[signature = "RET"]
+pub struct Return {
+    operand_values: OperandValues,
+}
+
+impl Executable for Return {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = vm.pop_stack()?;
+        let destination = ProgramIndex::try_from(value)
+            .map_err(|_| ExecutionError::ProgramCounterOutOfBounds(value as ProgramIndex))?;
+
+        vm.set_program_counter(destination);
+        Ok(ExecutionResult::Jumped(destination))
+    }
+}
+
+/// JumpZero: Resets the program counter to a constant value if the zero flag is set (the last comparison was equal)
+/// Operands:
+///     - 0: `#ADDRESS` | 3 Bytes | ProgramIndex | ProgramIndex to jump to
+///
+/// Errors/ Panics
+///     - `AssemblerError`: If the ProgramIndex is a value larger than 3 bytes
+///     - `RuntimeError`: If the target ProgramIndex is out of bounds
+///
+/// Examples
+/// ```asm
+/// JUMPZ #500 // `38 00 01 F4` - Jumps to program index 500 if the zero flag is set
+/// ```
+#[derive(Instruction)]
+#[opcode = 56]
+#[signature = "JUMPZ #3"]
+pub struct JumpZero {
+    operand_values: OperandValues,
+}
+
+impl Executable for JumpZero {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let flags = vm.flags();
+        jump_if(self, vm, flags.zero)
+    }
+}
+
+/// JumpNotZero: Resets the program counter to a constant value if the zero flag is clear (the last comparison was not equal)
+/// Operands:
+///     - 0: `#ADDRESS` | 3 Bytes | ProgramIndex | ProgramIndex to jump to
+///
+/// Errors/ Panics
+///     - `AssemblerError`: If the ProgramIndex is a value larger than 3 bytes
+///     - `RuntimeError`: If the target ProgramIndex is out of bounds
+///
+/// Examples
+/// ```asm
+/// JUMPNZ #500 // `39 00 01 F4` - Jumps to program index 500 if the zero flag is clear
+/// ```
+#[derive(Instruction)]
+#[opcode = 57]
+#[signature = "JUMPNZ #3"]
+pub struct JumpNotZero {
+    operand_values: OperandValues,
+}
+
+impl Executable for JumpNotZero {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let flags = vm.flags();
+        jump_if(self, vm, !flags.zero)
+    }
+}
+
+/// JumpNegative: Resets the program counter to a constant value if the negative flag is set (the left term of the last comparison was less than the right)
+/// Operands:
+///     - 0: `#ADDRESS` | 3 Bytes | ProgramIndex | ProgramIndex to jump to
+///
+/// Errors/ Panics
+///     - `AssemblerError`: If the ProgramIndex is a value larger than 3 bytes
+///     - `RuntimeError`: If the target ProgramIndex is out of bounds
+///
+/// Examples
+/// ```asm
+/// JUMPN #500 // `3A 00 01 F4` - Jumps to program index 500 if the negative flag is set
+/// ```
+#[derive(Instruction)]
+#[opcode = 58]
+#[signature = "JUMPN #3"]
+pub struct JumpNegative {
+    operand_values: OperandValues,
+}
+
+impl Executable for JumpNegative {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let flags = vm.flags();
+        jump_if(self, vm, flags.negative)
+    }
+}
+
+/// JumpNotNegative: Resets the program counter to a constant value if the negative flag is clear (the left term of the last comparison was greater than or equal to the right)
+/// Operands:
+///     - 0: `#ADDRESS` | 3 Bytes | ProgramIndex | ProgramIndex to jump to
+///
+/// Errors/ Panics
+///     - `AssemblerError`: If the ProgramIndex is a value larger than 3 bytes
+///     - `RuntimeError`: If the target ProgramIndex is out of bounds
+///
+/// Examples
+/// ```asm
+/// JUMPNN #500 // `3B 00 01 F4` - Jumps to program index 500 if the negative flag is clear
+/// ```
+#[derive(Instruction)]
+#[opcode = 59]
+#[signature = "JUMPNN #3"]
+pub struct JumpNotNegative {
+    operand_values: OperandValues,
+}
+
+impl Executable for JumpNotNegative {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let flags = vm.flags();
+        jump_if(self, vm, !flags.negative)
+    }
+}
+
+/// JumpCarry: Resets the program counter to a constant value if the carry flag is set (the last operation carried or borrowed)
+/// Operands:
+///     - 0: `#ADDRESS` | 3 Bytes | ProgramIndex | ProgramIndex to jump to
+///
+/// Errors/ Panics
+///     - `AssemblerError`: If the ProgramIndex is a value larger than 3 bytes
+///     - `RuntimeError`: If the target ProgramIndex is out of bounds
+///
+/// Examples
+/// ```asm
+/// JUMPC #500 // `3C 00 01 F4` - Jumps to program index 500 if the carry flag is set
+/// ```
+#[derive(Instruction)]
+#[opcode = 60]
+#[signature = "JUMPC #3"]
+pub struct JumpCarry {
+    operand_values: OperandValues,
+}
+
+impl Executable for JumpCarry {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let flags = vm.flags();
+        jump_if(self, vm, flags.carry)
+    }
+}
+
+/// JumpOverflow: Resets the program counter to a constant value if the overflow flag is set (the last operation overflowed)
+/// Operands:
+///     - 0: `#ADDRESS` | 3 Bytes | ProgramIndex | ProgramIndex to jump to
+///
+/// Errors/ Panics
+///     - `AssemblerError`: If the ProgramIndex is a value larger than 3 bytes
+///     - `RuntimeError`: If the target ProgramIndex is out of bounds
+///
+/// Examples
+/// ```asm
+/// JUMPO #500 // `3D 00 01 F4` - Jumps to program index 500 if the overflow flag is set
+/// ```
+#[derive(Instruction)]
+#[opcode = 61]
+#[signature = "JUMPO #3"]
+pub struct JumpOverflow {
+    operand_values: OperandValues,
+}
+
+impl Executable for JumpOverflow {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let flags = vm.flags();
+        jump_if(self, vm, flags.overflow)
+    }
+}
+
+/// Trap: Installs a fault handler. When an instruction faults with the FaultCode, control moves to the handler
+/// instead of stopping the machine. The handler receives the FaultCode in the second to last register and the faulting
+/// ProgramIndex in the last, which are `$30` and `$31` with the default 32 registers.
+/// FaultCode 0 installs a handler for every fault that does not have its own handler.
+/// Operands:
+///     - 0: `#1` | 1 Byte | FaultCode | The fault to handle (see `ExecutionError::code`), or 0 for every fault
+///     - 1: `#2` | 2 Bytes | ProgramIndex | ProgramIndex of the handler
+///
+/// Errors/ Panics
+///     - None
+///
+/// Examples
+/// ```asm
+/// TRAP #4 #200 // `3E 04 00 C8` - Divide by zero faults jump to program index 200
+/// ```
+#[derive(Instruction)]
+#[opcode = 62]
+#[signature = "TRAP #1 #2"]
+pub struct Trap {
+    operand_values: OperandValues,
+}
+
+impl Executable for Trap {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let code = self.operand_values[0].as_constant_value() as FaultCode;
+        let handler = self.operand_values[1].as_program_index();
+
+        vm.set_trap(code, handler);
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// RemoveTrap: Removes the fault handler installed for a FaultCode, so the fault stops the machine again
+/// Operands:
+///     - 0: `#1` | 1 Byte | FaultCode | The fault to stop handling, or 0 for the handler of every fault
+///
+/// Errors/ Panics
+///     - None
+///
+/// Examples
+/// ```asm
+/// UNTRAP #4 // `3F 04 00 00` - Divide by zero faults stop the machine again
+/// ```
+#[derive(Instruction)]
+#[opcode = 63]
+#[signature = "UNTRAP #1"]
+pub struct RemoveTrap {
+    operand_values: OperandValues,
+}
+
+impl Executable for RemoveTrap {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let code = self.operand_values[0].as_constant_value() as FaultCode;
+
+        vm.remove_trap(code);
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// ReturnFromTrap: Leaves a fault handler, resuming at the instruction after the one that faulted
+/// A fault inside of a handler (a double fault) is never handled, and stops the machine.
+/// Operands:
+///     - None
+///
+/// Errors/ Panics
+///     - `ProgramPanic`: `NotInTrapHandler` if no handler is running
+///
+/// Examples
+/// ```asm
+/// RETT // `40 00 00 00`
+/// ```
+#[derive(Instruction)]
+#[opcode = 64]
+#[signature = "RETT"]
+pub struct ReturnFromTrap {
+    operand_values: OperandValues,
+}
+
+impl Executable for ReturnFromTrap {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = vm.return_from_trap()?;
+
+        vm.set_program_counter(destination);
+        Ok(ExecutionResult::Jumped(destination))
+    }
+}
+
+/// Helper for the conditional branches. Jumps to the constant ProgramIndex in operand 0 if the condition holds
+fn jump_if<I: Instruction>(instruction: &I, vm: &mut Kaylee, condition: bool) -> Result<ExecutionResult, ExecutionError> {
+    if !condition {
+        return Ok(ExecutionResult::NoAction);
+    }
+
+    let destination = instruction.operand_values()[0].as_program_index();
+
+    vm.set_program_counter(destination);
+    Ok(ExecutionResult::Jumped(destination))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instructions::data::Load;
     use crate::instructions::machine::Halt;
-    use crate::instructions::program::{Jump, JumpBackward, JumpEqual, JumpForward};
+    use crate::instructions::data::{Pop, Push};
+    use crate::instructions::math::{Add, Divide, Subtract};
+    use crate::instructions::compare::Compare;
+    use crate::instructions::program::{Call, Jump, JumpBackward, JumpCarry, JumpEqual, JumpForward, JumpNegative, JumpNotNegative, JumpNotZero, JumpOverflow, JumpZero, RemoveTrap, Return, ReturnFromTrap, Trap};
     use crate::program::Program;
-    use crate::vm::Kaylee;
+    use crate::vm::{ExecutionError, ExitStatus, Kaylee};
 
     #[test]
     fn test_jump() {
@@ -151,7 +471,7 @@ mod tests {
         ]);
 
         let mut vm = Kaylee::new();
-        vm.run(program);
+        vm.run(program).unwrap();
 
         // Should set these
         assert_eq!(100, vm.register(0).unwrap());
@@ -183,7 +503,7 @@ mod tests {
         ]);
 
         let mut vm = Kaylee::new();
-        vm.run(program);
+        vm.run(program).unwrap();
 
         // Should set these
         assert_eq!(100, vm.register(0).unwrap());
@@ -220,7 +540,7 @@ mod tests {
 
         let mut vm = Kaylee::new();
         vm.set_program_counter(12);
-        vm.run(program);
+        vm.run(program).unwrap();
 
         assert_eq!(100, vm.register(0).unwrap());
         assert_eq!(0, vm.register(1).unwrap());
@@ -251,7 +571,7 @@ mod tests {
         vm.set_register(29, 200).unwrap();
         vm.set_register(28, 200).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         // Should set these
         assert_eq!(100, vm.register(0).unwrap());
@@ -287,7 +607,7 @@ mod tests {
         vm.set_register(29, 300).unwrap();
         vm.set_register(28, 200).unwrap();
 
-        vm.run(program);
+        vm.run(program).unwrap();
 
         // Should set these
         assert_eq!(100, vm.register(0).unwrap());
@@ -300,4 +620,215 @@ mod tests {
         // And check on the counter itself
         assert_eq!(28, vm.program_counter());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_call_and_return() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 10,     // LOAD $1 #10
+            Call::OPCODE, 0, 0, 20,     // CALL #20
+            Call::OPCODE, 0, 0, 20,     // CALL #20
+            Load::OPCODE, 3, 0, 1,      // LOAD $3 #1
+            Halt::OPCODE, 0, 0, 0,      // HALT
+
+            // Subroutine: $2 = $2 + $1, preserving $1 on the stack
+            Push::OPCODE, 1, 0, 0,      // PUSH $1
+            Add::OPCODE, 2, 2, 1,       // ADD $2 $2 $1
+            Pop::OPCODE, 1, 0, 0,       // POP $1
+            Return::OPCODE, 0, 0, 0,    // RET
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.run(program).unwrap();
+
+        assert_eq!(20, vm.register(2).unwrap());
+        assert_eq!(1, vm.register(3).unwrap());
+        assert!(vm.stack().is_empty());
+        assert_eq!(20, vm.program_counter());
+    }
+
+    #[test]
+    fn test_return_without_call() {
+        let program = Program::from(vec![
+            Return::OPCODE, 0, 0, 0,
+        ]);
+
+        let mut vm = Kaylee::new();
+        assert_eq!(ExecutionError::StackUnderflow, vm.run(program).unwrap_err().error);
+    }
+
+    #[test]
+    fn test_unbounded_recursion_overflows_the_stack() {
+        let program = Program::from(vec![
+            Call::OPCODE, 0, 0, 0,      // CALL #0
+        ]);
+
+        let mut vm = Kaylee::new().with_max_stack_depth(16);
+        let error = vm.run(program).unwrap_err();
+
+        assert_eq!(ExecutionError::StackOverflow, error.error);
+        assert_eq!(16, vm.stack().len());
+    }
+
+    #[test]
+    fn test_count_down_with_flag_branches() {
+        let program = Program::from(vec![
+            Load::OPCODE, 0, 0, 5,          // 0: LOAD $0 #5
+            Load::OPCODE, 1, 0, 1,          // 4: LOAD $1 #1
+            Add::OPCODE, 2, 2, 1,           // 8: ADD $2 $2 $1
+            Subtract::OPCODE, 0, 0, 1,      // 12: SUB $0 $0 $1
+            JumpNotZero::OPCODE, 0, 0, 8,   // 16: JUMPNZ #8
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.run(program).unwrap();
+
+        assert_eq!(0, vm.register(0).unwrap());
+        assert_eq!(5, vm.register(2).unwrap());
+    }
+
+    #[test]
+    fn test_branch_on_comparison() {
+        // Stores the larger of $1 and $2 in $3
+        let program = Program::from(vec![
+            Compare::OPCODE, 1, 2, 0,       // 0: CMP $1 $2
+            JumpNegative::OPCODE, 0, 0, 16, // 4: JUMPN #16
+            Add::OPCODE, 3, 1, 0,           // 8: ADD $3 $1 $0
+            Halt::OPCODE, 0, 0, 0,          // 12: HALT
+            Add::OPCODE, 3, 2, 0,           // 16: ADD $3 $2 $0
+        ]);
+
+        for (left, right, larger) in [(3, 9, 9), (9, 3, 9), (-4, -8, -4), (5, 5, 5)] {
+            let mut vm = Kaylee::new();
+            vm.set_register(1, left).unwrap();
+            vm.set_register(2, right).unwrap();
+            vm.run(program.clone()).unwrap();
+
+            assert_eq!(larger, vm.register(3).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_each_flag_branch() {
+        let cases = [
+            (JumpZero::OPCODE, 5, 5),
+            (JumpNotZero::OPCODE, 5, 6),
+            (JumpNegative::OPCODE, 5, 6),
+            (JumpNotNegative::OPCODE, 6, 5),
+            (JumpCarry::OPCODE, 1, -1),
+            (JumpOverflow::OPCODE, i32::MIN, 1),
+        ];
+
+        for (opcode, left, right) in cases {
+            let program = Program::from(vec![
+                Compare::OPCODE, 1, 2, 0,   // 0: CMP $1 $2
+                opcode, 0, 0, 12,           // 4: Branch to #12
+                Load::OPCODE, 3, 0, 1,      // 8: LOAD $3 #1 (skipped)
+                Load::OPCODE, 4, 0, 1,      // 12: LOAD $4 #1
+            ]);
+
+            let mut vm = Kaylee::new();
+            vm.set_register(1, left).unwrap();
+            vm.set_register(2, right).unwrap();
+            vm.run(program).unwrap();
+
+            assert_eq!(0, vm.register(3).unwrap(), "opcode {opcode} did not branch");
+            assert_eq!(1, vm.register(4).unwrap());
+
+            // And the inverse case falls through
+            let program = Program::from(vec![
+                Compare::OPCODE, 2, 2, 0,
+                opcode, 0, 0, 12,
+                Load::OPCODE, 3, 0, 1,
+            ]);
+
+            let mut vm = Kaylee::new();
+            vm.set_register(2, right).unwrap();
+            vm.run(program).unwrap();
+
+            let expected = if opcode == JumpZero::OPCODE || opcode == JumpNotNegative::OPCODE { 0 } else { 1 };
+            assert_eq!(expected, vm.register(3).unwrap(), "opcode {opcode} fell through incorrectly");
+        }
+    }
+
+    #[test]
+    fn test_trap_handler_recovers_from_a_fault() {
+        let program = Program::from(vec![
+            Trap::OPCODE, 4, 0, 20,         // 0: TRAP #4 #20
+            Divide::OPCODE, 3, 1, 2,        // 4: DIV $3 $1 $2
+            Load::OPCODE, 4, 0, 1,          // 8: LOAD $4 #1
+            Halt::OPCODE, 0, 0, 0,          // 12: HALT
+            Halt::OPCODE, 9, 0, 0,          // 16: HALT #9
+            Load::OPCODE, 3, 0, 99,         // 20: LOAD $3 #99
+            ReturnFromTrap::OPCODE, 0, 0, 0, // 24: RETT
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 10).unwrap();
+
+        assert_eq!(ExitStatus::Halted(0), vm.run(program).unwrap());
+        assert_eq!(99, vm.register(3).unwrap());
+        assert_eq!(1, vm.register(4).unwrap());
+        assert_eq!(ExecutionError::DivideByZero.code() as i32, vm.register(vm.fault_code_register()).unwrap());
+        assert_eq!(4, vm.register(vm.fault_index_register()).unwrap());
+        assert!(!vm.in_trap_handler());
+    }
+
+    #[test]
+    fn test_catch_all_trap_handles_decode_faults() {
+        let program = Program::from(vec![
+            Trap::OPCODE, 0, 0, 12,         // 0: TRAP #0 #12
+            255, 0, 0, 0,                   // 4: illegal opcode
+            Halt::OPCODE, 0, 0, 0,          // 8: HALT
+            Halt::OPCODE, 7, 0, 0,          // 12: HALT #7
+        ]);
+
+        let mut vm = Kaylee::new();
+
+        assert_eq!(ExitStatus::Halted(7), vm.run(program).unwrap());
+        assert_eq!(ExecutionError::IllegalOpcode(255).code() as i32, vm.register(vm.fault_code_register()).unwrap());
+    }
+
+    #[test]
+    fn test_unhandled_and_double_faults_stop_the_machine() {
+        // Only divide by zero is handled, so the overflow stops the machine
+        let program = Program::from(vec![
+            Trap::OPCODE, 4, 0, 8,          // 0: TRAP #4 #8
+            Add::OPCODE, 0, 1, 1,           // 4: ADD $0 $1 $1
+            Divide::OPCODE, 0, 0, 2,        // 8: DIV $0 $0 $2
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, i32::MAX).unwrap();
+        assert_eq!(ExecutionError::ArithmeticOverflow, vm.run(program.clone()).unwrap_err().error);
+
+        // The handler itself divides by zero, which is a double fault
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 1).unwrap();
+        let program = Program::from(vec![
+            Trap::OPCODE, 4, 0, 8,          // 0: TRAP #4 #8
+            Divide::OPCODE, 0, 0, 2,        // 4: DIV $0 $0 $2
+            Divide::OPCODE, 0, 0, 2,        // 8: DIV $0 $0 $2
+        ]);
+
+        let error = vm.run(program).unwrap_err();
+        assert_eq!(ExecutionError::DivideByZero, error.error);
+        assert_eq!(8, error.program_index);
+        assert!(vm.in_trap_handler());
+    }
+
+    #[test]
+    fn test_remove_trap_and_return_outside_handler() {
+        let program = Program::from(vec![
+            Trap::OPCODE, 4, 0, 12,         // 0: TRAP #4 #12
+            RemoveTrap::OPCODE, 4, 0, 0,    // 4: UNTRAP #4
+            Divide::OPCODE, 0, 0, 2,        // 8: DIV $0 $0 $2
+        ]);
+
+        let mut vm = Kaylee::new();
+        assert_eq!(ExecutionError::DivideByZero, vm.run(program).unwrap_err().error);
+        assert!(vm.traps().is_empty());
+
+        let mut vm = Kaylee::new();
+        assert_eq!(ExecutionError::NotInTrapHandler, vm.run(Program::from(vec![ReturnFromTrap::OPCODE, 0, 0, 0])).unwrap_err().error);
+    }
+}
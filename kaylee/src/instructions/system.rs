@@ -1,3 +1,326 @@
 //! Instructions for interacting the the operating environment
 //! These will be things like file manipulation, environment variables, networking, etc
-//! Opcodes reserved: 130 - 179
\ No newline at end of file
+//! Opcodes reserved: 130 - 179
+use kaylee_derive::Instruction;
+
+use crate::instructions::{display_instruction_with_values, Executable, Instruction, InstructionDocumentation, InstructionSignature, OperandType, OperandValues};
+use crate::vm::{ExecutionError, ExecutionResult, Kaylee, RegisterValue, SyscallId};
+
+/// Syscall: Calls a host function registered by the embedding application with `Kaylee::add_syscall`
+/// The host function reads its arguments from, and writes its results to, registers.
+/// Operands:
+///     - 0: `#2` | 2 Bytes | Constant | The number the host function was registered with
+///
+/// Errors/ Panics
+///     - `ProgramPanic`: `UnknownSyscall` if no host function is registered for the number
+///     - `ProgramPanic`: Any error returned by the host function
+///
+/// Examples
+/// ```asm
+/// SYSCALL #12 // `82 00 0C 00` - Calls host function 12
+/// ```
+#[derive(Instruction)]
+#[opcode = 130]
+#[signature = "SYSCALL #2"]
+pub struct Syscall {
+    operand_values: OperandValues,
+}
+
+impl Executable for Syscall {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let id = self.operand_values[0].as_constant_value() as SyscallId;
+
+        vm.call_host(id)?;
+
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// Print: Writes the value of a register to the console output, followed by a new line
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the value to print (0-31)
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If the register is out of bounds
+///     - `ProgramPanic`: `Io` if the console output cannot be written
+///
+/// Examples
+/// ```asm
+/// PRINT $01 // `83 01 00 00` - Prints the value of register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 131]
+#[signature = "PRINT $S"]
+pub struct Print {
+    operand_values: OperandValues,
+}
+
+impl Executable for Print {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+
+        let output = vm.output();
+        writeln!(output, "{value}")?;
+        output.flush()?;
+
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// PrintCharacter: Writes the lowest byte of a register to the console output, without a new line
+/// Operands:
+///     - 0: `$S` | 1 Byte | RegisterId | RegisterId of the byte to print (0-31)
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If the register is out of bounds
+///     - `ProgramPanic`: `Io` if the console output cannot be written
+///
+/// Examples
+/// ```asm
+/// PRINTC $01 // `84 01 00 00` - Prints the byte in register 1, so 65 prints `A`
+/// ```
+#[derive(Instruction)]
+#[opcode = 132]
+#[signature = "PRINTC $S"]
+pub struct PrintCharacter {
+    operand_values: OperandValues,
+}
+
+impl Executable for PrintCharacter {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let value = self.get_register_value_for_operand(0, vm)?;
+
+        let output = vm.output();
+        output.write_all(&[value as u8])?;
+        output.flush()?;
+
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// PrintString: Writes a null-terminated string from memory to the console output, without a new line
+/// Operands:
+///     - 0: `#2` | 2 Bytes | Constant | Memory address of the first byte of the string
+///
+/// Errors/ Panics
+///     - `ProgramPanic`: `MemoryOutOfBounds` if the string is not null-terminated inside of memory
+///     - `ProgramPanic`: `Io` if the console output cannot be written
+///
+/// Examples
+/// ```asm
+/// PRINTS #256 // `85 01 00 00` - Prints the string stored at address 256
+/// ```
+#[derive(Instruction)]
+#[opcode = 133]
+#[signature = "PRINTS #2"]
+pub struct PrintString {
+    operand_values: OperandValues,
+}
+
+impl Executable for PrintString {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let address = self.operand_values[0].as_constant_value() as usize;
+        let text = vm.read_string(address)?;
+
+        let output = vm.output();
+        output.write_all(text.as_bytes())?;
+        output.flush()?;
+
+        Ok(ExecutionResult::NoAction)
+    }
+}
+
+/// Read: Reads a line from the console input and loads the integer on it into a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If the register is out of bounds
+///     - `ProgramPanic`: `Io` if the input has ended, cannot be read, or the line is not an integer
+///
+/// Examples
+/// ```asm
+/// READ $01 // `86 01 00 00` - Reads an integer into register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 134]
+#[signature = "READ $D"]
+pub struct Read {
+    operand_values: OperandValues,
+}
+
+impl Executable for Read {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+
+        let line = vm.read_input_line()?
+            .ok_or_else(|| ExecutionError::Io(String::from("the console input has ended")))?;
+
+        let value = line.trim().parse::<RegisterValue>()
+            .map_err(|_| ExecutionError::Io(format!("expected an integer, read `{}`", line.trim())))?;
+
+        vm.set_register(destination, value)?;
+        Ok(ExecutionResult::Value(value))
+    }
+}
+
+/// Clock: Loads the number of seconds since the Unix epoch into a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If the register is out of bounds
+///
+/// Examples
+/// ```asm
+/// TIME $01 // `87 01 00 00` - Loads the current time into register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 135]
+#[signature = "TIME $D"]
+pub struct Clock {
+    operand_values: OperandValues,
+}
+
+impl Executable for Clock {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+
+        let value = vm.clock()?;
+        vm.set_register(destination, value)?;
+
+        Ok(ExecutionResult::Value(value))
+    }
+}
+
+/// Random: Loads a random number into a register
+/// Operands:
+///     - 0: `$D` | 1 Byte | RegisterId | RegisterId of the destination register (0-31)
+///
+/// Errors/ Panics
+///     - `AssemblerError` or `ProgramPanic`: If the register is out of bounds
+///
+/// Examples
+/// ```asm
+/// RAND $01 // `88 01 00 00` - Loads a random number into register 1
+/// ```
+#[derive(Instruction)]
+#[opcode = 136]
+#[signature = "RAND $D"]
+pub struct Random {
+    operand_values: OperandValues,
+}
+
+impl Executable for Random {
+    fn execute(&self, vm: &mut Kaylee) -> Result<ExecutionResult, ExecutionError> {
+        let destination = self.operand_values[0].as_register_id();
+
+        let value = vm.random()?;
+        vm.set_register(destination, value)?;
+
+        Ok(ExecutionResult::Value(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::instructions::data::{Load, StoreWord};
+    use crate::instructions::system::{Print, PrintCharacter, PrintString, Read, Syscall};
+    use crate::io::SharedBuffer;
+    use crate::program::Program;
+    use crate::vm::{ExecutionError, Kaylee};
+
+    #[test]
+    fn test_syscall_reads_and_writes_registers() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 6,          // LOAD $1 #6
+            Load::OPCODE, 2, 0, 7,          // LOAD $2 #7
+            Syscall::OPCODE, 1, 0, 0,       // SYSCALL #256
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.add_syscall(256, |vm| {
+            let product = vm.register(1)? * vm.register(2)?;
+            vm.set_register(0, product)
+        });
+
+        vm.run(program).unwrap();
+
+        assert_eq!(42, vm.register(0).unwrap());
+    }
+
+    #[test]
+    fn test_unknown_syscall() {
+        let mut vm = Kaylee::new();
+        vm.add_syscall(1, |_| Ok(()));
+        assert!(vm.remove_syscall(1));
+        assert!(!vm.remove_syscall(1));
+
+        let error = vm.run(Program::from(vec![Syscall::OPCODE, 0, 1, 0])).unwrap_err();
+
+        assert_eq!(ExecutionError::UnknownSyscall(1), error.error);
+        assert_eq!(Some(String::from("SYSCALL #1")), error.instruction);
+    }
+
+    #[test]
+    fn test_host_function_errors_fault() {
+        let mut vm = Kaylee::new();
+        vm.add_syscall(3, |_| Err(ExecutionError::Host(String::from("no network"))));
+
+        let error = vm.run(Program::from(vec![Syscall::OPCODE, 0, 3, 0])).unwrap_err();
+
+        assert_eq!(ExecutionError::Host(String::from("no network")), error.error);
+    }
+
+    #[test]
+    fn test_console_output() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 42,         // LOAD $1 #42
+            Print::OPCODE, 1, 0, 0,         // PRINT $1
+            Load::OPCODE, 2, 0, 65,         // LOAD $2 #65
+            PrintCharacter::OPCODE, 2, 0, 0, // PRINTC $2
+            StoreWord::OPCODE, 3, 0, 16,    // STOREW $3 #16
+            PrintString::OPCODE, 0, 16, 0,  // PRINTS #16
+        ]);
+
+        let output = SharedBuffer::new();
+        let mut vm = Kaylee::new().with_output(output.clone());
+        vm.set_register(3, i32::from_be_bytes(*b"bcd\n")).unwrap();
+
+        vm.run(program).unwrap();
+
+        assert_eq!("42\nAbcd\n", output.text());
+    }
+
+    #[test]
+    fn test_console_input() {
+        let program = Program::from(vec![
+            Read::OPCODE, 1, 0, 0,          // READ $1
+            Read::OPCODE, 2, 0, 0,          // READ $2
+        ]);
+
+        let mut vm = Kaylee::new().with_input(Cursor::new("12\n  -7  \n"));
+        vm.run(program.clone()).unwrap();
+
+        assert_eq!(12, vm.register(1).unwrap());
+        assert_eq!(-7, vm.register(2).unwrap());
+
+        let mut vm = Kaylee::new().with_input(Cursor::new("12\n"));
+        assert_eq!(ExecutionError::Io(String::from("the console input has ended")), vm.run(program.clone()).unwrap_err().error);
+
+        let mut vm = Kaylee::new().with_input(Cursor::new("twelve\n"));
+        assert_eq!(ExecutionError::Io(String::from("expected an integer, read `twelve`")), vm.run(program).unwrap_err().error);
+    }
+
+    #[test]
+    fn test_run_next_reports_through_the_output() {
+        let output = SharedBuffer::new();
+        let mut vm = Kaylee::new().with_output(output.clone());
+
+        vm.run_next(&Program::new()).unwrap();
+
+        assert_eq!("Execution Finished\n", output.text());
+    }
+}
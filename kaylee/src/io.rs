@@ -0,0 +1,64 @@
+//! Console streams for `Kaylee`. By default a VM reads from stdin and writes to stdout.
+//! Replace them with `Kaylee::with_input` and `Kaylee::with_output` to script input or capture output,
+//! for example `std::io::Cursor` for input and a `SharedBuffer` for output.
+use std::io;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// An in-memory output stream that can still be read after it is handed to a VM.
+/// Clones share the same bytes.
+#[derive(Debug, Default, Clone)]
+pub struct SharedBuffer {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far
+    pub fn contents(&self) -> Vec<u8> {
+        self.bytes.lock().expect("SharedBuffer lock poisoned").clone()
+    }
+
+    /// Everything written so far, as text. Invalid UTF-8 is replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.contents()).into_owned()
+    }
+
+    /// Throw away everything written so far
+    pub fn clear(&self) {
+        self.bytes.lock().expect("SharedBuffer lock poisoned").clear();
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.lock().expect("SharedBuffer lock poisoned").extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::io::SharedBuffer;
+
+    #[test]
+    fn test_clones_share_contents() {
+        let buffer = SharedBuffer::new();
+        let mut writer = buffer.clone();
+
+        write!(writer, "hello {}", 42).unwrap();
+        assert_eq!("hello 42", buffer.text());
+
+        buffer.clear();
+        assert!(writer.contents().is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+//! The `.kbc` executable format: a `Program` with everything needed to run it on a compatible VM.
+//! All values are big-endian. Version 2 layout:
+//!
+//! | Field              | Size                                                         |
+//! |--------------------|--------------------------------------------------------------|
+//! | Magic `KBC\0`      | 4 bytes                                                      |
+//! | ISA Version        | u16                                                          |
+//! | Opcode Table       | u64 (see `opcode_fingerprint`)                               |
+//! | Entry Point        | u64                                                          |
+//! | Code               | u64 length, then bytes                                       |
+//! | Data               | u64 length, then bytes                                       |
+//! | Constants          | u64 count, then u8 kind and i32 or u64 each (see `Constant`) |
+//! | Symbols            | u64 count, then u64 length, UTF-8 name and u64 index each    |
+//! | Checksum           | u64 FNV-1a of every byte before it                           |
+//!
+//! A file is refused when its ISA version or opcode table differs from the ones this VM was built with,
+//! since its bytes would decode to different instructions.
+use std::fmt::{Display, Formatter};
+
+use crate::instructions::{Operation, OperandType};
+use crate::program::{Constant, Program, ProgramIndex};
+use crate::shared::{ByteReader, fnv1a};
+use crate::vm::{Byte, ExecutionError};
+
+pub const MAGIC: [u8; 4] = *b"KBC\0";
+/// Bumped whenever this layout or the meaning of existing opcodes changes
+pub const ISA_VERSION: u16 = 2;
+
+/// Errors concerning reading, writing and loading executables
+#[derive(Debug)]
+pub enum KbcError {
+    /// The data does not start with the executable magic bytes
+    NotAnExecutable,
+    /// The executable targets a different version of the instruction set
+    UnsupportedVersion(u16),
+    /// The executable was built against a different opcode table
+    IncompatibleOpcodes { expected: u64, found: u64 },
+    /// The data ended before the executable was complete
+    Truncated,
+    /// The checksum does not match the contents
+    ChecksumMismatch,
+    /// The data is not a valid executable (entry point outside the code, trailing data, etc)
+    Malformed(String),
+    /// The VM could not load the executable, because its data does not fit in memory
+    Load(ExecutionError),
+    Io(std::io::Error),
+}
+
+impl Display for KbcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KbcError::NotAnExecutable => write!(f, "not a Kaylee executable"),
+            KbcError::UnsupportedVersion(version) => write!(f, "unsupported instruction set version {version}"),
+            KbcError::IncompatibleOpcodes { expected, found } => {
+                write!(f, "built against opcode table {found:016x}, but this VM has {expected:016x}")
+            }
+            KbcError::Truncated => write!(f, "executable is truncated"),
+            KbcError::ChecksumMismatch => write!(f, "executable checksum does not match"),
+            KbcError::Malformed(message) => write!(f, "malformed executable: {message}"),
+            KbcError::Load(error) => write!(f, "unable to load executable: {error:?}"),
+            KbcError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for KbcError {}
+
+impl From<std::io::Error> for KbcError {
+    fn from(error: std::io::Error) -> Self {
+        KbcError::Io(error)
+    }
+}
+
+/// A hash of every opcode this VM knows, with its mnemonic and operand layout.
+/// Two builds of Kaylee with the same fingerprint decode every instruction the same way.
+pub fn opcode_fingerprint() -> u64 {
+    let mut table = Vec::new();
+
+    for opcode in 0..=Byte::MAX {
+        if let Some(operation) = Operation::for_opcode(opcode) {
+            let signature = operation.signature();
+            table.push(opcode);
+            table.extend(signature.identifier.as_bytes());
+            table.extend(signature.operands.iter().map(|operand| match operand {
+                OperandType::None => 0,
+                OperandType::RegisterId => 1,
+                OperandType::FloatRegisterId => 2,
+                OperandType::ConstantByte => 3,
+                OperandType::ConstantHalfWord => 4,
+                OperandType::ConstantWord => 5,
+            }));
+        }
+    }
+
+    fnv1a(&table)
+}
+
+pub(crate) fn encode(program: &Program) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend(MAGIC);
+    bytes.extend(ISA_VERSION.to_be_bytes());
+    bytes.extend(opcode_fingerprint().to_be_bytes());
+    bytes.extend((program.entry_point() as u64).to_be_bytes());
+
+    for section in [program.bytes().as_slice(), program.data()] {
+        bytes.extend((section.len() as u64).to_be_bytes());
+        bytes.extend(section);
+    }
+
+    bytes.extend((program.constants().len() as u64).to_be_bytes());
+    for constant in program.constants() {
+        constant.write(&mut bytes);
+    }
+
+    bytes.extend((program.symbols().len() as u64).to_be_bytes());
+    for (name, index) in program.symbols() {
+        bytes.extend((name.len() as u64).to_be_bytes());
+        bytes.extend(name.as_bytes());
+        bytes.extend((*index as u64).to_be_bytes());
+    }
+
+    bytes.extend(fnv1a(&bytes).to_be_bytes());
+    bytes
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<Program, KbcError> {
+    let mut reader = ByteReader::new(bytes);
+
+    if reader.take(4) != Some(&MAGIC[..]) {
+        return Err(KbcError::NotAnExecutable);
+    }
+
+    let version = reader.u16().ok_or(KbcError::Truncated)?;
+    if version != ISA_VERSION {
+        return Err(KbcError::UnsupportedVersion(version));
+    }
+
+    let found = reader.u64().ok_or(KbcError::Truncated)?;
+    let expected = opcode_fingerprint();
+    if found != expected {
+        return Err(KbcError::IncompatibleOpcodes { expected, found });
+    }
+
+    let entry_point = reader.u64().ok_or(KbcError::Truncated)? as ProgramIndex;
+    let code = read_section(&mut reader)?;
+    let data = read_section(&mut reader)?;
+
+    let mut program = Program::from(code).with_data(data).with_entry_point(entry_point);
+
+    let count = reader.u64().ok_or(KbcError::Truncated)?;
+    for _ in 0..count {
+        program = program.with_constant(Constant::read(&mut reader).ok_or(KbcError::Truncated)?);
+    }
+
+    let count = reader.u64().ok_or(KbcError::Truncated)?;
+    for _ in 0..count {
+        let name = String::from_utf8(read_section(&mut reader)?)
+            .map_err(|_| KbcError::Malformed(String::from("a symbol name is not UTF-8")))?;
+        let index = reader.u64().ok_or(KbcError::Truncated)? as ProgramIndex;
+        program = program.with_symbol(&name, index);
+    }
+
+    let checked = bytes.len() - reader.remaining();
+    let checksum = reader.u64().ok_or(KbcError::Truncated)?;
+
+    if !reader.is_empty() {
+        return Err(KbcError::Malformed(String::from("unexpected data after the checksum")));
+    }
+
+    if checksum != fnv1a(&bytes[..checked]) {
+        return Err(KbcError::ChecksumMismatch);
+    }
+
+    if entry_point >= program.len() && !(entry_point == 0 && program.is_empty()) {
+        return Err(KbcError::Malformed(format!("entry point {entry_point} is outside the code")));
+    }
+
+    Ok(program)
+}
+
+fn read_section(reader: &mut ByteReader) -> Result<Vec<u8>, KbcError> {
+    let length = reader.u64().ok_or(KbcError::Truncated)? as usize;
+    Ok(reader.take(length).ok_or(KbcError::Truncated)?.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::data::{Load, LoadWord};
+    use crate::instructions::machine::Halt;
+    use crate::kbc::{self, KbcError};
+    use crate::program::{Constant, Program};
+    use crate::vm::{ExitStatus, Kaylee};
+
+    fn program() -> Program {
+        Program::from(vec![
+            Halt::OPCODE, 9, 0, 0,          // 0: HALT #9
+            LoadWord::OPCODE, 1, 0, 0,      // 4: LOADW $1 #0
+            Load::OPCODE, 2, 0, 5,          // 8: LOAD $2 #5
+            Halt::OPCODE, 0, 0, 0,          // 12: HALT
+        ])
+            .with_data(vec![0, 0, 1, 0])
+            .with_constant(Constant::Integer(-7))
+            .with_constant(Constant::Address(2))
+            .with_entry_point(4)
+            .with_symbol("start", 4)
+    }
+
+    #[test]
+    fn test_executable_round_trip() {
+        let bytes = program().to_kbc();
+        assert_eq!(&kbc::MAGIC[..], &bytes[..4]);
+        assert_eq!(program(), Program::from_kbc(&bytes).unwrap());
+
+        let path = std::env::temp_dir().join(format!("kaylee-{}.kbc", std::process::id()));
+        program().write_kbc_file(&path).unwrap();
+        let read = Program::read_kbc_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(program(), read.unwrap());
+    }
+
+    #[test]
+    fn test_loaded_executable_starts_at_its_entry_point_with_its_data() {
+        let mut vm = Kaylee::new();
+        let loaded = vm.load_kbc(&program().to_kbc()).unwrap();
+
+        assert_eq!(ExitStatus::Halted(0), vm.run(loaded).unwrap());
+        assert_eq!(256, vm.register(1).unwrap());
+        assert_eq!(5, vm.register(2).unwrap());
+
+        let mut vm = Kaylee::new().with_memory_size(2);
+        assert!(matches!(vm.load_kbc(&program().to_kbc()), Err(KbcError::Load(_))));
+    }
+
+    #[test]
+    fn test_invalid_executables() {
+        let bytes = program().to_kbc();
+
+        assert!(matches!(Program::from_kbc(b"nope"), Err(KbcError::NotAnExecutable)));
+        assert!(matches!(Program::from_kbc(&bytes[..bytes.len() - 1]), Err(KbcError::Truncated)));
+
+        let mut version = bytes.clone();
+        version[5] = 99;
+        assert!(matches!(Program::from_kbc(&version), Err(KbcError::UnsupportedVersion(99))));
+
+        let mut opcodes = bytes.clone();
+        opcodes[6] ^= 1;
+        assert!(matches!(Program::from_kbc(&opcodes), Err(KbcError::IncompatibleOpcodes { .. })));
+        assert!(matches!(Kaylee::new().load_kbc(&opcodes), Err(KbcError::IncompatibleOpcodes { .. })));
+
+        let mut corrupted = bytes.clone();
+        corrupted[30] ^= 1;
+        assert!(matches!(Program::from_kbc(&corrupted), Err(KbcError::ChecksumMismatch)));
+
+        let outside = program().with_entry_point(16).to_kbc();
+        assert!(matches!(Program::from_kbc(&outside), Err(KbcError::Malformed(_))));
+    }
+}
@@ -7,3 +7,16 @@ pub mod repl;
 pub mod instructions;
 pub mod asm;
 pub mod program;
+pub mod debugger;
+pub mod observer;
+pub mod gas;
+pub mod config;
+pub mod verifier;
+pub mod snapshot;
+pub mod io;
+pub mod threads;
+pub mod replay;
+pub mod undo;
+pub mod control;
+pub mod kbc;
+pub mod disassembler;
@@ -1,6 +1,94 @@
+use std::{env, fs, process};
+
+use kaylee::asm::Source;
+use kaylee::program::Program;
+use kaylee::replay::Trace;
 use kaylee::repl::Repl;
+use kaylee::vm::{ExitStatus, Kaylee};
 
+/// Starts the REPL, or runs an assembly file or `.kbc` executable and exits with its status code when given a path.
+/// After the path, `--record <trace>` records the run to a trace file and `--replay <trace>` replays one.
 fn main() {
-    let mut repl = Repl::new();
-    repl.run();
+    let args = env::args().collect::<Vec<_>>();
+
+    match args.get(1) {
+        Some(path) => process::exit(run_file(path, &args[2..])),
+        None => {
+            let mut repl = Repl::new();
+            repl.run();
+        }
+    }
+}
+
+/// Assemble and run a file without the REPL, returning the code to exit the process with.
+/// Reading, assembling, loading, tracing and runtime errors exit with 1.
+fn run_file(path: &str, options: &[String]) -> i32 {
+    let program = match read_program(path) {
+        Ok(program) => program,
+        Err(message) => {
+            eprintln!("{message}");
+            return 1;
+        }
+    };
+
+    let mut vm = match options {
+        [] => Kaylee::new(),
+        [flag, _] if flag == "--record" => Kaylee::new().with_recording(&program),
+        [flag, trace] if flag == "--replay" => {
+            match Trace::read_from_file(trace).and_then(|trace| Kaylee::new().with_replay(trace, &program)) {
+                Ok(vm) => vm,
+                Err(error) => {
+                    eprintln!("Unable to replay {trace}: {error}");
+                    return 1;
+                }
+            }
+        }
+        _ => {
+            eprintln!("Usage: kaylee <file> [--record <trace> | --replay <trace>]");
+            return 1;
+        }
+    };
+
+    if let Err(error) = vm.load(&program) {
+        eprintln!("Unable to load {path}: {error:?}");
+        return 1;
+    }
+
+    let code = match vm.run(program) {
+        Ok(status) => {
+            if let ExitStatus::Aborted { message, .. } = &status {
+                eprintln!("{message}");
+            }
+
+            status.code()
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            1
+        }
+    };
+
+    if let [flag, path] = options {
+        let finished = vm.finish_trace().and_then(|trace| match flag.as_str() {
+            "--record" => trace.write_to_file(path),
+            _ => Ok(()),
+        });
+
+        if let Err(error) = finished {
+            eprintln!("Unable to finish the trace {path}: {error}");
+            return 1;
+        }
+    }
+
+    code
+}
+
+/// Read a `.kbc` executable, or assemble any other file
+fn read_program(path: &str) -> Result<Program, String> {
+    if path.ends_with(".kbc") {
+        return Program::read_kbc_file(path).map_err(|error| format!("Unable to read {path}: {error}"));
+    }
+
+    let body = fs::read_to_string(path).map_err(|error| format!("Unable to read {path}: {error}"))?;
+    Program::try_from(Source::from(body)).map_err(|error| format!("Unable to assemble {path}: {error:?}"))
 }
@@ -0,0 +1,88 @@
+//! Hooks for watching a `Kaylee` instance execute, for tracers, profilers and test assertions.
+use crate::instructions::Instruction;
+use crate::program::ProgramIndex;
+use crate::vm::{ExecutionError, ExecutionResult};
+
+/// Receives a callback around every instruction the VM executes.
+/// Register one with `Kaylee::add_observer`. Both methods default to doing nothing.
+pub trait ExecutionObserver: Send {
+    /// Called after an instruction is decoded, but before it is executed
+    fn before_instruction(&mut self, _program_index: ProgramIndex, _instruction: &dyn Instruction) {}
+
+    /// Called after an instruction executes, with the result or the fault it raised
+    fn after_instruction(&mut self, _program_index: ProgramIndex, _instruction: &dyn Instruction, _result: &Result<ExecutionResult, ExecutionError>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::instructions::data::Load;
+    use crate::instructions::Instruction;
+    use crate::instructions::machine::Halt;
+    use crate::instructions::math::Divide;
+    use crate::instructions::program::Jump;
+    use crate::observer::ExecutionObserver;
+    use crate::program::{Program, ProgramIndex};
+    use crate::vm::{ExecutionError, ExecutionResult, Kaylee};
+
+    #[derive(Default)]
+    struct Tracer {
+        lines: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ExecutionObserver for Tracer {
+        fn before_instruction(&mut self, program_index: ProgramIndex, instruction: &dyn Instruction) {
+            self.lines.lock().unwrap().push(format!("{program_index}: {}", instruction.display()));
+        }
+
+        fn after_instruction(&mut self, _program_index: ProgramIndex, _instruction: &dyn Instruction, result: &Result<ExecutionResult, ExecutionError>) {
+            self.lines.lock().unwrap().push(format!("{result:?}"));
+        }
+    }
+
+    #[test]
+    fn test_observer_sees_every_instruction() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 100,
+            Jump::OPCODE, 0, 0, 12,
+            Load::OPCODE, 2, 0, 100,
+            Halt::OPCODE, 0, 0, 0,
+        ]);
+
+        let tracer = Tracer::default();
+        let lines = tracer.lines.clone();
+
+        let mut vm = Kaylee::new();
+        vm.add_observer(tracer);
+        vm.run(program).unwrap();
+
+        assert_eq!(vec![
+            "0: LOAD $1 #100",
+            "Ok(Value(100))",
+            "4: JUMP #12",
+            "Ok(Jumped(12))",
+            "12: HALT #0",
+            "Ok(Halted)",
+        ], *lines.lock().unwrap());
+    }
+
+    #[test]
+    fn test_observer_sees_faults() {
+        let program = Program::from(vec![
+            Divide::OPCODE, 1, 1, 2,
+        ]);
+
+        let tracer = Tracer::default();
+        let lines = tracer.lines.clone();
+
+        let mut vm = Kaylee::new();
+        vm.add_observer(tracer);
+        vm.run(program).unwrap_err();
+
+        assert_eq!(vec![
+            "0: DIV $1 $1 $2",
+            "Err(DivideByZero)",
+        ], *lines.lock().unwrap());
+    }
+}
@@ -1,16 +1,62 @@
+use std::collections::BTreeMap;
+use std::fs;
 use std::ops::Index;
+use std::path::Path;
 use std::vec::IntoIter;
 
 use crate::asm::{Parsed, Source};
 use crate::asm::assembler::{Assembler, AssemblerError};
 use crate::asm::parser::parse_asm;
-use crate::vm::Byte;
+use crate::config::VmConfig;
+use crate::instructions::{decode_operation, InstructionDecodeError, Operation, OperandValues};
+use crate::kbc::{self, KbcError};
+use crate::shared::ByteReader;
+use crate::vm::{Byte, MemoryAddress, RegisterValue};
 
 pub type ProgramIndex = usize;
 
-#[derive(PartialEq, Debug)]
+/// An entry in a Program's constant pool, loaded into a register by `LOADC`
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Constant {
+    /// A 32-bit integer, loaded as it is
+    Integer(RegisterValue),
+    /// A string or byte array in the data section, loaded as its memory address
+    Address(MemoryAddress),
+}
+
+impl Constant {
+    /// Encode as a u8 kind (0 integer, 1 address), then an i32 or u64, for the binary file formats
+    pub(crate) fn write(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Constant::Integer(value) => {
+                bytes.push(0);
+                bytes.extend(value.to_be_bytes());
+            }
+            Constant::Address(address) => {
+                bytes.push(1);
+                bytes.extend((*address as u64).to_be_bytes());
+            }
+        }
+    }
+
+    pub(crate) fn read(reader: &mut ByteReader) -> Option<Constant> {
+        match reader.u8()? {
+            0 => Some(Constant::Integer(reader.i32()?)),
+            1 => Some(Constant::Address(reader.u64()? as MemoryAddress)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Program {
     bytes: Vec<Byte>,
+    /// Copied into the start of memory when the Program is loaded
+    data: Vec<Byte>,
+    /// Where execution starts when the Program is loaded
+    entry_point: ProgramIndex,
+    constants: Vec<Constant>,
+    symbols: BTreeMap<String, ProgramIndex>,
 }
 
 impl Index<ProgramIndex> for Program {
@@ -32,7 +78,7 @@ impl IntoIterator for Program {
 
 impl From<Vec<Byte>> for Program {
     fn from(bytes: Vec<Byte>) -> Self {
-        Program { bytes }
+        Program { bytes, ..Program::new() }
     }
 }
 
@@ -42,10 +88,20 @@ impl Extend<Byte> for Program {
     }
 }
 
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Program {
     pub fn new() -> Self {
         Program {
-            bytes: Vec::new()
+            bytes: Vec::new(),
+            data: Vec::new(),
+            entry_point: 0,
+            constants: Vec::new(),
+            symbols: BTreeMap::new(),
         }
     }
 
@@ -53,9 +109,150 @@ impl Program {
         self.bytes.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
     pub fn bytes(&self) -> &Vec<u8> {
         &self.bytes
     }
+
+    pub fn data(&self) -> &[Byte] {
+        &self.data
+    }
+
+    pub fn with_data(mut self, data: Vec<Byte>) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn entry_point(&self) -> ProgramIndex {
+        self.entry_point
+    }
+
+    pub fn with_entry_point(mut self, entry_point: ProgramIndex) -> Self {
+        self.entry_point = entry_point;
+        self
+    }
+
+    /// The constant pool, indexed by the operand of `LOADC`
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    /// Add an entry to the end of the constant pool
+    pub fn with_constant(mut self, constant: Constant) -> Self {
+        self.constants.push(constant);
+        self
+    }
+
+    /// Names for places in the program, kept for debuggers and disassemblers
+    pub fn symbols(&self) -> &BTreeMap<String, ProgramIndex> {
+        &self.symbols
+    }
+
+    pub fn with_symbol(mut self, name: &str, index: ProgramIndex) -> Self {
+        self.symbols.insert(String::from(name), index);
+        self
+    }
+
+    /// Add another Program to the end of this one, moving its symbols and data addresses to match.
+    /// Its constants follow this Program's, so a `LOADC` in its code names a different entry if this Program has any.
+    pub fn append(&mut self, other: Program) {
+        let (code_start, data_start) = (self.bytes.len(), self.data.len());
+
+        self.bytes.extend(other.bytes);
+        self.data.extend(other.data);
+        self.constants.extend(other.constants.into_iter().map(|constant| match constant {
+            Constant::Address(address) => Constant::Address(data_start + address),
+            constant => constant,
+        }));
+        self.symbols.extend(other.symbols.into_iter().map(|(name, index)| (name, code_start + index)));
+    }
+
+    /// Encode the Program as a `.kbc` executable
+    pub fn to_kbc(&self) -> Vec<u8> {
+        kbc::encode(self)
+    }
+
+    /// Decode a `.kbc` executable, refusing one built for a different instruction set
+    pub fn from_kbc(bytes: &[u8]) -> Result<Program, KbcError> {
+        kbc::decode(bytes)
+    }
+
+    pub fn write_kbc_file<P: AsRef<Path>>(&self, path: P) -> Result<(), KbcError> {
+        fs::write(path, self.to_kbc())?;
+        Ok(())
+    }
+
+    pub fn read_kbc_file<P: AsRef<Path>>(path: P) -> Result<Program, KbcError> {
+        Program::from_kbc(&fs::read(path)?)
+    }
+
+    /// Assemble source for a VM built from `config`, rejecting registers that VM will not have.
+    /// Fails if the parser stops before the end of the source, rather than dropping the lines it could not read.
+    pub fn assemble(source: Source, config: &VmConfig) -> Result<Program, AssemblerError> {
+        match parse_asm(source.body.as_str()) {
+            Ok((remaining, _)) if !remaining.trim().is_empty() => {
+                let line = remaining.trim().lines().next().unwrap_or_default();
+                Err(AssemblerError::Other(format!("Unable to parse `{line}`")))
+            }
+            Ok((_, parsed)) => Assembler::for_config(config).assemble_parsed_asm(parsed),
+            Err(_) => Err(AssemblerError::Other(String::from("Parsing error")))
+        }
+    }
+}
+
+/// A Program decoded once, ahead of time, so it can be run many times without decoding or allocating on every step.
+/// Jumps may land on any byte, so every ProgramIndex is decoded, along with the error if its bytes are not a valid instruction.
+/// Run one with `Kaylee::run_decoded`.
+#[derive(Clone)]
+pub struct DecodedProgram {
+    program: Program,
+    instructions: Vec<Result<DecodedInstruction, InstructionDecodeError>>,
+}
+
+/// A single decoded instruction: how to execute it and the operands to execute it with
+#[derive(Clone, Copy)]
+pub(crate) struct DecodedInstruction {
+    pub(crate) operation: Operation,
+    pub(crate) operand_values: OperandValues,
+}
+
+impl From<Program> for DecodedProgram {
+    fn from(program: Program) -> Self {
+        let instructions = (0..program.len())
+            .map(|index| {
+                let mut program_counter = index;
+                match decode_operation(&program, &mut program_counter) {
+                    Some(decoded) => decoded.map(|(operation, operand_values)| DecodedInstruction { operation, operand_values }),
+                    None => unreachable!("every ProgramIndex being decoded is inside the program"),
+                }
+            })
+            .collect();
+
+        DecodedProgram { program, instructions }
+    }
+}
+
+impl DecodedProgram {
+    /// The Program this was decoded from
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn len(&self) -> usize {
+        self.program.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.program.is_empty()
+    }
+
+    /// The decoded instruction starting at a ProgramIndex, or `None` past the end of the program
+    pub(crate) fn instruction(&self, index: ProgramIndex) -> Option<&Result<DecodedInstruction, InstructionDecodeError>> {
+        self.instructions.get(index)
+    }
 }
 
 impl<'a> TryFrom<Parsed<'a>> for Program {
@@ -71,14 +268,7 @@ impl TryFrom<Source> for Program {
     type Error = AssemblerError;
 
     fn try_from(source: Source) -> Result<Self, Self::Error> {
-        // let parsed = Parsed::try_from(source);
-        let parsed = parse_asm(source.body.as_str());
-        match parsed {
-            Ok(success) => {
-                success.1.try_into()
-            }
-            Err(_) => Err(AssemblerError::Other(String::from("Parsing error")))
-        }
+        Program::assemble(source, &VmConfig::new())
     }
 }
 
@@ -86,4 +276,101 @@ impl TryFrom<Source> for Program {
 //     fn index_mut(&mut self, index: ProgramIndex) -> &mut Self::Output {
 //         todo!()
 //     }
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use crate::asm::assembler::AssemblerError;
+    use crate::asm::Source;
+    use crate::instructions::data::Load;
+    use crate::instructions::machine::Halt;
+    use crate::instructions::math::{Add, Divide, WrappingSubtract};
+    use crate::instructions::program::{Jump, JumpNotZero};
+    use crate::program::{DecodedProgram, Program};
+    use crate::vm::{ExecutionError, Kaylee, RuntimeError};
+
+    fn countdown() -> Program {
+        Program::from(vec![
+            Load::OPCODE, 1, 0, 1,              // 0: LOAD $1 #1
+            Load::OPCODE, 2, 0, 100,            // 4: LOAD $2 #100
+            Add::OPCODE, 3, 3, 2,               // 8: ADD $3 $3 $2
+            WrappingSubtract::OPCODE, 2, 2, 1,  // 12: SUBW $2 $2 $1
+            JumpNotZero::OPCODE, 0, 0, 8,       // 16: JUMPNZ #8
+            Halt::OPCODE, 0, 0, 0,              // 20: HALT
+        ])
+    }
+
+    #[test]
+    fn test_decoded_program_runs_like_the_program() {
+        let mut expected = Kaylee::new();
+        expected.run(countdown()).unwrap();
+
+        let decoded = DecodedProgram::from(countdown());
+        let mut vm = Kaylee::new();
+        vm.run_decoded(&decoded).unwrap();
+
+        assert_eq!(5050, vm.register(3).unwrap());
+        assert_eq!(expected.all_registers(), vm.all_registers());
+        assert_eq!(expected.program_counter(), vm.program_counter());
+        assert_eq!(expected.flags(), vm.flags());
+        assert!(vm.is_halted());
+
+        // The same decoded program can be run again
+        let mut again = Kaylee::new();
+        again.run_decoded(&decoded).unwrap();
+        assert_eq!(vm.all_registers(), again.all_registers());
+    }
+
+    #[test]
+    fn test_decoded_jumps_land_on_any_index() {
+        // The JUMP lands on the ADD hidden in the operands of the LOAD, then runs into the zero padding of the HALT
+        let program = Program::from(vec![
+            Jump::OPCODE, 0, 0, 5,              // 0: JUMP #5
+            Load::OPCODE, Add::OPCODE, 1, 1,    // 4: LOAD $70 #257, 5: ADD $1 $1 $1
+            Halt::OPCODE, 0, 0, 0,              // 8: HALT
+        ]);
+
+        let mut expected = Kaylee::new();
+        let expected_error = expected.run(program.clone()).unwrap_err();
+
+        let mut vm = Kaylee::new();
+        let error = vm.run_decoded(&DecodedProgram::from(program)).unwrap_err();
+
+        assert_eq!(expected_error, error);
+        assert_eq!(ExecutionError::IllegalOpcode(0), error.error);
+        assert_eq!(9, error.program_index);
+    }
+
+    #[test]
+    fn test_decoded_faults() {
+        let program = Program::from(vec![
+            Load::OPCODE, 0, 0, 100,
+            255, 0, 0, 0,
+        ]);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run_decoded(&DecodedProgram::from(program)).unwrap_err();
+        assert_eq!(RuntimeError { error: ExecutionError::IllegalOpcode(255), program_index: 4, instruction: None }, error);
+        assert_eq!(100, vm.register(0).unwrap());
+
+        let mut vm = Kaylee::new();
+        let error = vm.run_decoded(&DecodedProgram::from(Program::from(vec![Divide::OPCODE, 0, 1, 2]))).unwrap_err();
+        assert_eq!(ExecutionError::DivideByZero, error.error);
+        assert_eq!(Some(String::from("DIV $0 $1 $2")), error.instruction);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run_decoded(&DecodedProgram::from(Program::from(vec![Jump::OPCODE, 0, 1, 0]))).unwrap_err();
+        assert_eq!(ExecutionError::ProgramCounterOutOfBounds(256), error.error);
+        assert_eq!(Some(String::from("JUMP #256")), error.instruction);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run_decoded(&DecodedProgram::from(Program::from(vec![Add::OPCODE, 1, 2]))).unwrap_err();
+        assert_eq!(ExecutionError::TruncatedInstruction, error.error);
+    }
+
+    #[test]
+    fn test_assembling_rejects_input_the_parser_left_behind() {
+        let source = Source::from(String::from("LOAD $1 #5\nLOAD $1 ^5\nHALT"));
+
+        assert_eq!(Err(AssemblerError::Other(String::from("Unable to parse `^5`"))), Program::try_from(source));
+    }
+}
@@ -1,96 +1,175 @@
-use std;
-use std::io;
-use std::io::Write;
-
-use nom::IResult;
-
 use crate::asm::assembler::Assembler;
-use crate::asm::parser::{line, parse_asm};
-use crate::instructions::decode_next_instruction;
+use crate::asm::parser::parse_asm;
+use crate::disassembler::Disassembler;
 use crate::program::Program;
-use crate::shared::parse_hex;
 use crate::vm::Kaylee;
 
 /// Core structure for the REPL for the Assembler
+/// Commands are read from, and everything is printed to, the VM's console streams
 pub struct Repl {
     command_buffer: Vec<String>,
     vm: Kaylee,
 }
 
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Repl {
     /// Creates and returns a new asm REPL
     pub fn new() -> Repl {
+        Repl::with_vm(Kaylee::new())
+    }
+
+    /// Creates a REPL around a VM, for example one with its console streams replaced
+    pub fn with_vm(vm: Kaylee) -> Repl {
         Repl {
-            vm: Kaylee::new(),
+            vm,
             command_buffer: vec![],
         }
     }
 
+    /// Runs until `.quit`, the end of the input, or the console streams fail
     pub fn run(&mut self) {
-        println!("Welcome to the Kaylee REPL");
+        if let Err(error) = self.run_commands() {
+            eprintln!("Console error: {error}");
+        }
+    }
+
+    fn run_commands(&mut self) -> std::io::Result<()> {
+        writeln!(self.vm.output(), "Welcome to the Kaylee REPL")?;
 
         let mut program = Program::new();
 
         loop {
             let mut buffer = String::new();
-            let stdin = io::stdin();
-            print!(">>> ");
-            io::stdout().flush().expect("Unable to flush standard out");
+            write!(self.vm.output(), ">>> ")?;
+            self.vm.output().flush()?;
 
-            stdin.read_line(&mut buffer).expect("Unable to read line from user");
+            if self.vm.input().read_line(&mut buffer)? == 0 {
+                return Ok(());
+            }
             let buffer = buffer.trim();
 
             self.command_buffer.push(buffer.to_string());
 
             match buffer {
                 ".quit" => {
-                    println!("Have a great day!");
-                    std::process::exit(0);
+                    writeln!(self.vm.output(), "Have a great day!")?;
+                    return Ok(());
                 }
                 ".history" => {
                     for command in &self.command_buffer {
-                        println!("{command}");
+                        writeln!(self.vm.output(), "{command}")?;
                     }
                 }
                 ".program" => {
-                    println!("Listing entire program instructions");
-                    let mut pc: usize = 0;
-
-                    while let Some(result) = decode_next_instruction(&program, &mut pc) {
-                        match result {
-                            Ok(instruction) => println!("{}", instruction.display()),
-                            Err(_error) => panic!("received an error")
-                        };
-                    }
-
-                    println!("End of instructions");
+                    writeln!(self.vm.output(), "Listing entire program instructions")?;
+                    let listing = Disassembler::for_config(&self.vm.config()).disassemble(program.bytes());
+                    write!(self.vm.output(), "{listing}")?;
+                    writeln!(self.vm.output(), "End of instructions")?;
                 }
                 ".registers" => {
-                    println!("Listing all registers and contents");
-                    println!("{:#?}", self.vm.all_registers());
-                    println!("End of register listing");
+                    writeln!(self.vm.output(), "Listing all registers and contents")?;
+                    let registers = self.vm.all_registers().to_vec();
+                    writeln!(self.vm.output(), "{:#?}", registers)?;
+                    let float_registers = self.vm.all_float_registers();
+                    writeln!(self.vm.output(), "Float registers: {:?}", float_registers)?;
+                    let flags = self.vm.flags();
+                    writeln!(self.vm.output(), "Flags: {} (Zero, Negative, Carry, Overflow)", flags)?;
+                    writeln!(self.vm.output(), "End of register listing")?;
                 }
                 _ => {
-                    match parse_asm(buffer) {
+                    // Each line is assembled on its own, so a constant is added to the pool without a `.data` line
+                    let source = match buffer.split_whitespace().next() {
+                        Some(".int" | ".string" | ".bytes") => format!("{}\n{buffer}", Assembler::DATA_SECTION),
+                        _ => buffer.to_string(),
+                    };
+
+                    match parse_asm(&source) {
                         Ok(parsed) => {
-                            let assembler = Assembler::new();
+                            let assembler = Assembler::for_config(&self.vm.config());
                             let results = assembler.assemble_parsed_asm(parsed.1);
                             match results {
-                                Ok(bytes) => {
-                                    let _ = &program.extend(bytes);
-                                    self.vm.run_next(&program)
+                                Ok(assembled) => {
+                                    let (code_start, data_start) = (program.len(), program.data().len());
+                                    program.append(assembled);
+
+                                    let result = match self.vm.load_appended(&program, data_start) {
+                                        Err(error) => Err(error.to_string()),
+                                        Ok(()) if program.len() == code_start => Ok(()),
+                                        Ok(()) => self.vm.run_next(&program).map_err(|error| error.to_string()),
+                                    };
+
+                                    if let Err(error) = result {
+                                        writeln!(self.vm.output(), "{error}")?;
+                                    }
                                 }
                                 Err(_e) => {
-                                    println!("Invalid string bytecode");
+                                    writeln!(self.vm.output(), "Invalid string bytecode")?;
                                 }
                             }
                         }
                         Err(error) => {
-                            println!("{:?}", error);
+                            writeln!(self.vm.output(), "{:?}", error)?;
                         }
                     }
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::io::SharedBuffer;
+    use crate::repl::Repl;
+    use crate::vm::Kaylee;
+
+    #[test]
+    fn test_repl_uses_the_console_streams() {
+        let output = SharedBuffer::new();
+        let vm = Kaylee::new()
+            .with_input(Cursor::new("LOAD $1 #7\nPRINT $1\n.history\n.quit\nLOAD $2 #1\n"))
+            .with_output(output.clone());
+
+        let mut repl = Repl::with_vm(vm);
+        repl.run();
+
+        assert_eq!(
+            "Welcome to the Kaylee REPL\n>>> >>> 7\n>>> LOAD $1 #7\nPRINT $1\n.history\n>>> Have a great day!\n",
+            output.text()
+        );
+    }
+
+    #[test]
+    fn test_program_listing_shows_bytes_it_cannot_decode() {
+        let output = SharedBuffer::new();
+        let vm = Kaylee::new()
+            .with_input(Cursor::new("LOAD $1 #7\n.byte #255 #0 #0 #0\n.program\n"))
+            .with_output(output.clone());
+
+        let mut repl = Repl::with_vm(vm);
+        repl.run();
+
+        assert!(output.text().contains("    LOAD $1 #7              // 0000: 1E 01 00 07\n"));
+        assert!(output.text().contains("    .byte #255 #0 #0 #0     // 0004: FF 00 00 00\nEnd of instructions\n"));
+    }
+
+    #[test]
+    fn test_constants_added_one_line_at_a_time() {
+        let output = SharedBuffer::new();
+        let vm = Kaylee::new()
+            .with_input(Cursor::new(".string \"hi\"\n.int #70000\nPRINTS #0\nLOADC $1 #1\nPRINT $1\n"))
+            .with_output(output.clone());
+
+        let mut repl = Repl::with_vm(vm);
+        repl.run();
+
+        assert_eq!("Welcome to the Kaylee REPL\n>>> >>> >>> hi>>> >>> 70000\n>>> ", output.text());
+    }
+}
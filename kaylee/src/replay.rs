@@ -0,0 +1,462 @@
+//! Deterministic record and replay of VM runs.
+//! While recording, the VM logs every non-deterministic value it consumes (console input, the clock, random numbers
+//! and the results of host functions) into a `Trace`. While replaying, it takes those values from the `Trace` instead,
+//! so the run is reproduced exactly. A replay stops with `ExecutionError::ReplayDiverged` as soon as the program asks
+//! for something the recording did not. A trace only replays on a VM configured like the one that recorded it.
+//!
+//! All values are big-endian. Version 4 layout:
+//!
+//! | Field        | Size                                               |
+//! |--------------|----------------------------------------------------|
+//! | Magic `KTRC` | 4 bytes                                            |
+//! | Version      | u16                                                |
+//! | Program Hash | u64 (see `program_hash`)                           |
+//! | Registers    | u32 count                                          |
+//! | Memory       | u64 size                                           |
+//! | Stack Depth  | u64 max stack depth                                |
+//! | Groups       | u16 (see `InstructionGroups::to_bits`)             |
+//! | Events       | u64 count, then each event                         |
+//!
+//! Each event is a u64 ProgramIndex, u32 ThreadId and u8 kind, followed by:
+//! - 0 Input: u8 (0 at the end of input), u64 length, then the UTF-8 line
+//! - 1 Clock, 2 Random: i32
+//! - 3 Syscall: u16 id, u8 (1 succeeded), then u32 count and i32 registers and 32 f32 bits float registers,
+//!   or u64 length and the UTF-8 error message
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{InstructionGroups, VmConfig};
+use crate::program::{Program, ProgramIndex};
+use crate::shared::{ByteReader, fnv1a};
+use crate::threads::ThreadId;
+use crate::vm::{FloatRegisterValue, Kaylee, RegisterValue, SyscallId};
+
+/// Errors concerning reading, writing and finishing traces
+#[derive(Debug)]
+pub enum TraceError {
+    /// The data does not start with the trace magic bytes
+    NotATrace,
+    /// The trace was written by an incompatible version of Kaylee
+    UnsupportedVersion(u16),
+    /// The data ended before the trace was complete
+    Truncated,
+    /// The data is not a valid trace (unknown event kinds, trailing data, etc)
+    Malformed(String),
+    /// The trace was recorded from a different Program
+    ProgramMismatch,
+    /// The trace was recorded on a VM with different registers, memory, stack depth or instruction groups
+    ConfigMismatch,
+    /// The replay ended before it consumed every recorded event
+    Incomplete { remaining: usize },
+    /// The VM is neither recording nor replaying
+    NotTracing,
+    Io(std::io::Error),
+}
+
+impl Display for TraceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceError::NotATrace => write!(f, "not a Kaylee trace"),
+            TraceError::UnsupportedVersion(version) => write!(f, "unsupported trace version {version}"),
+            TraceError::Truncated => write!(f, "trace is truncated"),
+            TraceError::Malformed(message) => write!(f, "malformed trace: {message}"),
+            TraceError::ProgramMismatch => write!(f, "the trace was recorded from a different program"),
+            TraceError::ConfigMismatch => write!(f, "the trace was recorded on a differently configured VM"),
+            TraceError::Incomplete { remaining } => write!(f, "the replay ended with {remaining} recorded events left"),
+            TraceError::NotTracing => write!(f, "the VM is not recording or replaying"),
+            TraceError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl From<std::io::Error> for TraceError {
+    fn from(error: std::io::Error) -> Self {
+        TraceError::Io(error)
+    }
+}
+
+/// A non-deterministic value the VM consumed
+#[derive(Debug, PartialEq, Clone)]
+pub enum Event {
+    /// A line of console input, without its line ending. `None` at the end of input
+    Input(Option<String>),
+    /// The value `TIME` loaded
+    Clock(RegisterValue),
+    /// The value `RAND` loaded
+    Random(RegisterValue),
+    /// The registers a host function left behind, or the message of the error it returned
+    Syscall { id: SyscallId, outcome: Result<Box<SyscallEffect>, String> },
+}
+
+impl Event {
+    /// The kind of event, for divergence messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Input(_) => "console input",
+            Event::Clock(_) => "the clock",
+            Event::Random(_) => "a random number",
+            Event::Syscall { .. } => "a host function",
+        }
+    }
+}
+
+/// The registers and float registers after a host function returned
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyscallEffect {
+    pub registers: Vec<RegisterValue>,
+    pub float_registers: [FloatRegisterValue; Kaylee::FLOAT_REGISTER_COUNT],
+}
+
+/// An Event and where in the run it was consumed
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraceEvent {
+    /// The program counter when the value was consumed, which is just past the instruction consuming it
+    pub program_index: ProgramIndex,
+    pub thread: ThreadId,
+    pub event: Event,
+}
+
+/// Every non-deterministic value consumed by one run of a Program, in order
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trace {
+    pub(crate) program_hash: u64,
+    pub(crate) config: VmConfig,
+    pub(crate) events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    pub const MAGIC: [u8; 4] = *b"KTRC";
+    pub const VERSION: u16 = 4;
+
+    /// An empty trace for recording a run of `program` on a VM built from `config`
+    pub fn new(program: &Program, config: &VmConfig) -> Self {
+        Trace { program_hash: program_hash(program), config: Trace::recorded_config(config), events: Vec::new() }
+    }
+
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// The configuration of the VM that recorded the trace. It has no instruction budget
+    pub fn config(&self) -> &VmConfig {
+        &self.config
+    }
+
+    /// True if the trace was recorded from `program`
+    pub fn matches(&self, program: &Program) -> bool {
+        self.program_hash == program_hash(program)
+    }
+
+    /// True if the trace was recorded on a VM with the same registers, memory, stack depth and instruction groups as `config`
+    pub fn matches_config(&self, config: &VmConfig) -> bool {
+        self.config == Trace::recorded_config(config)
+    }
+
+    /// The budget decides when a run stops, not which values it consumes, so a trace does not keep it
+    fn recorded_config(config: &VmConfig) -> VmConfig {
+        VmConfig { instruction_budget: None, ..config.clone() }
+    }
+
+    /// Encode the trace into its binary form
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(Trace::MAGIC);
+        bytes.extend(Trace::VERSION.to_be_bytes());
+        bytes.extend(self.program_hash.to_be_bytes());
+        bytes.extend((self.config.register_count as u32).to_be_bytes());
+        bytes.extend((self.config.memory_size as u64).to_be_bytes());
+        bytes.extend((self.config.max_stack_depth as u64).to_be_bytes());
+        bytes.extend(self.config.groups.to_bits().to_be_bytes());
+
+        bytes.extend((self.events.len() as u64).to_be_bytes());
+        for event in &self.events {
+            bytes.extend((event.program_index as u64).to_be_bytes());
+            bytes.extend(event.thread.to_be_bytes());
+
+            match &event.event {
+                Event::Input(line) => {
+                    bytes.push(0);
+                    bytes.push(line.is_some() as u8);
+                    let line = line.as_deref().unwrap_or("");
+                    bytes.extend((line.len() as u64).to_be_bytes());
+                    bytes.extend(line.as_bytes());
+                }
+                Event::Clock(value) => {
+                    bytes.push(1);
+                    bytes.extend(value.to_be_bytes());
+                }
+                Event::Random(value) => {
+                    bytes.push(2);
+                    bytes.extend(value.to_be_bytes());
+                }
+                Event::Syscall { id, outcome } => {
+                    bytes.push(3);
+                    bytes.extend(id.to_be_bytes());
+
+                    match outcome {
+                        Ok(effect) => {
+                            bytes.push(1);
+                            bytes.extend((effect.registers.len() as u32).to_be_bytes());
+                            for register in &effect.registers {
+                                bytes.extend(register.to_be_bytes());
+                            }
+                            for register in &effect.float_registers {
+                                bytes.extend(register.to_bits().to_be_bytes());
+                            }
+                        }
+                        Err(message) => {
+                            bytes.push(0);
+                            bytes.extend((message.len() as u64).to_be_bytes());
+                            bytes.extend(message.as_bytes());
+                        }
+                    }
+                }
+            }
+        }
+
+        bytes
+    }
+
+    /// Decode a trace from its binary form
+    pub fn from_bytes(bytes: &[u8]) -> Result<Trace, TraceError> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.take(4) != Some(&Trace::MAGIC[..]) {
+            return Err(TraceError::NotATrace);
+        }
+
+        let version = reader.u16().ok_or(TraceError::Truncated)?;
+        if version != Trace::VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+
+        let program_hash = reader.u64().ok_or(TraceError::Truncated)?;
+        let config = VmConfig::new()
+            .with_register_count(reader.u32().ok_or(TraceError::Truncated)? as usize)
+            .with_memory_size(reader.u64().ok_or(TraceError::Truncated)? as usize)
+            .with_max_stack_depth(reader.u64().ok_or(TraceError::Truncated)? as usize)
+            .with_groups(InstructionGroups::from_bits(reader.u16().ok_or(TraceError::Truncated)?));
+        let count = reader.u64().ok_or(TraceError::Truncated)?;
+
+        let mut events = Vec::new();
+        for _ in 0..count {
+            events.push(Trace::read_event(&mut reader)?);
+        }
+
+        if !reader.is_empty() {
+            return Err(TraceError::Malformed(String::from("unexpected data after the events")));
+        }
+
+        Ok(Trace { program_hash, config, events })
+    }
+
+    fn read_event(reader: &mut ByteReader) -> Result<TraceEvent, TraceError> {
+        let program_index = reader.u64().ok_or(TraceError::Truncated)? as ProgramIndex;
+        let thread = reader.u32().ok_or(TraceError::Truncated)?;
+        let kind = reader.u8().ok_or(TraceError::Truncated)?;
+
+        let event = match kind {
+            0 => {
+                let present = reader.u8().ok_or(TraceError::Truncated)? != 0;
+                let line = Trace::read_string(reader)?;
+                Event::Input(present.then_some(line))
+            }
+            1 => Event::Clock(reader.i32().ok_or(TraceError::Truncated)?),
+            2 => Event::Random(reader.i32().ok_or(TraceError::Truncated)?),
+            3 => {
+                let id = reader.u16().ok_or(TraceError::Truncated)?;
+                let succeeded = reader.u8().ok_or(TraceError::Truncated)? != 0;
+
+                let outcome = match succeeded {
+                    true => Ok(Box::new(Trace::read_effect(reader).ok_or(TraceError::Truncated)?)),
+                    false => Err(Trace::read_string(reader)?),
+                };
+
+                Event::Syscall { id, outcome }
+            }
+            _ => return Err(TraceError::Malformed(format!("unknown event kind {kind}"))),
+        };
+
+        Ok(TraceEvent { program_index, thread, event })
+    }
+
+    fn read_effect(reader: &mut ByteReader) -> Option<SyscallEffect> {
+        let register_count = reader.u32()?;
+        let registers = (0..register_count).map(|_| reader.i32()).collect::<Option<Vec<_>>>()?;
+
+        let mut float_registers = [0.0; Kaylee::FLOAT_REGISTER_COUNT];
+        for register in float_registers.iter_mut() {
+            *register = f32::from_bits(reader.u32()?);
+        }
+
+        Some(SyscallEffect { registers, float_registers })
+    }
+
+    fn read_string(reader: &mut ByteReader) -> Result<String, TraceError> {
+        let length = reader.u64().ok_or(TraceError::Truncated)? as usize;
+        let bytes = reader.take(length).ok_or(TraceError::Truncated)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| TraceError::Malformed(String::from("a string is not UTF-8")))
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), TraceError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Trace, TraceError> {
+        Trace::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// Whether a VM is recording a trace or replaying one
+#[derive(Debug)]
+pub(crate) enum Tracer {
+    Recording(Trace),
+    Replaying { trace: Trace, position: usize },
+}
+
+/// A 64-bit FNV-1a hash of the Program's bytes, data section, constant pool and entry point,
+/// so a replay can tell it is running the program it recorded
+pub fn program_hash(program: &Program) -> u64 {
+    let mut bytes = Vec::new();
+
+    for section in [program.bytes().as_slice(), program.data()] {
+        bytes.extend((section.len() as u64).to_be_bytes());
+        bytes.extend(section);
+    }
+
+    for constant in program.constants() {
+        constant.write(&mut bytes);
+    }
+
+    bytes.extend((program.entry_point() as u64).to_be_bytes());
+    fnv1a(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::config::{InstructionGroup, VmConfig};
+    use crate::instructions::data::Load;
+    use crate::instructions::machine::Halt;
+    use crate::instructions::math::Add;
+    use crate::instructions::system::{Clock, Random, Read, Syscall};
+    use crate::program::{Constant, Program};
+    use crate::replay::{Event, Trace, TraceError};
+    use crate::vm::{ExecutionError, ExitStatus, Kaylee};
+
+    fn program() -> Program {
+        Program::from(vec![
+            Read::OPCODE, 1, 0, 0,          // 0: READ $1
+            Clock::OPCODE, 2, 0, 0,         // 4: TIME $2
+            Random::OPCODE, 3, 0, 0,        // 8: RAND $3
+            Syscall::OPCODE, 0, 7, 0,       // 12: SYSCALL #7
+            Add::OPCODE, 5, 1, 4,           // 16: ADD $5 $1 $4
+            Halt::OPCODE, 0, 0, 0,          // 20: HALT
+        ])
+    }
+
+    /// Host function 7 puts 30 in $4
+    fn host(mut vm: Kaylee) -> Kaylee {
+        vm.add_syscall(7, |vm| vm.set_register(4, 30));
+        vm
+    }
+
+    #[test]
+    fn test_replay_reproduces_a_recorded_run() {
+        let mut recording = host(Kaylee::new().with_input(Cursor::new("12\n"))).with_recording(&program());
+        assert_eq!(ExitStatus::Halted(0), recording.run(program()).unwrap());
+
+        let trace = recording.finish_trace().unwrap();
+        assert_eq!(4, trace.events().len());
+        assert_eq!(Event::Input(Some(String::from("12"))), trace.events()[0].event);
+        assert_eq!(4, trace.events()[0].program_index);
+
+        // The replay has no console input and a host function that does something else
+        let trace = Trace::from_bytes(&trace.to_bytes()).unwrap();
+        let mut replay = Kaylee::new().with_input(Cursor::new("")).with_replay(trace, &program()).unwrap();
+        replay.add_syscall(7, |vm| vm.set_register(4, -1));
+
+        assert_eq!(ExitStatus::Halted(0), replay.run(program()).unwrap());
+        assert_eq!(recording.all_registers(), replay.all_registers());
+        assert_eq!(42, replay.register(5).unwrap());
+        assert!(replay.finish_trace().is_ok());
+    }
+
+    #[test]
+    fn test_replay_detects_divergence() {
+        let mut recording = host(Kaylee::new().with_input(Cursor::new("12\n"))).with_recording(&program());
+        recording.run(program()).unwrap();
+        let trace = recording.finish_trace().unwrap();
+
+        // A different program, or the same code with different data, constants or entry point
+        let changed = Program::from(vec![Load::OPCODE, 1, 0, 0]);
+        assert!(matches!(Kaylee::new().with_replay(trace.clone(), &changed), Err(TraceError::ProgramMismatch)));
+
+        for changed in [program().with_data(vec![1]), program().with_constant(Constant::Integer(1)), program().with_entry_point(4)] {
+            assert!(matches!(Kaylee::new().with_replay(trace.clone(), &changed), Err(TraceError::ProgramMismatch)));
+        }
+
+        // The same program on a VM with different registers, memory, stack depth or instruction groups
+        let configs = [
+            VmConfig::new().with_register_count(64),
+            VmConfig::new().with_memory_size(128),
+            VmConfig::new().with_max_stack_depth(3),
+            VmConfig::new().without_group(InstructionGroup::Float),
+        ];
+        for config in configs {
+            assert!(matches!(config.build().unwrap().with_replay(trace.clone(), &program()), Err(TraceError::ConfigMismatch)));
+        }
+
+        // A budget does not change the values a run consumes
+        assert!(VmConfig::new().with_instruction_budget(100).build().unwrap().with_replay(trace.clone(), &program()).is_ok());
+
+        // The same program asking for values in a different order
+        let mut events = trace.clone();
+        events.events.swap(1, 2);
+        let mut replay = host(Kaylee::new()).with_replay(events, &program()).unwrap();
+        let error = replay.run(program()).unwrap_err();
+        assert!(matches!(error.error, ExecutionError::ReplayDiverged(_)));
+        assert_eq!(4, error.program_index);
+
+        // A replay that stops early leaves events behind
+        let mut replay = host(Kaylee::new()).with_replay(trace, &program()).unwrap();
+        replay.run_next(&program()).unwrap();
+        assert!(matches!(replay.finish_trace(), Err(TraceError::Incomplete { remaining: 3 })));
+    }
+
+    #[test]
+    fn test_divergence_is_not_trapped() {
+        let trap_everything = Program::from(vec![
+            crate::instructions::program::Trap::OPCODE, 0, 0, 12, // 0: TRAP #0 #12
+            Random::OPCODE, 1, 0, 0,                              // 4: RAND $1
+            Halt::OPCODE, 0, 0, 0,                                // 8: HALT
+            Halt::OPCODE, 9, 0, 0,                                // 12: HALT #9
+        ]);
+
+        let mut replay = Kaylee::new().with_replay(Trace::new(&trap_everything, &VmConfig::new()), &trap_everything).unwrap();
+        let error = replay.run(trap_everything).unwrap_err();
+        assert!(matches!(error.error, ExecutionError::ReplayDiverged(_)));
+    }
+
+    #[test]
+    fn test_invalid_traces() {
+        let trace = Trace::new(&program(), &VmConfig::new().with_register_count(64).without_group(InstructionGroup::Float));
+        assert_eq!(trace, Trace::from_bytes(&trace.to_bytes()).unwrap());
+
+        let bytes = Trace::new(&program(), &VmConfig::new()).to_bytes();
+
+        assert!(matches!(Trace::from_bytes(b"nope"), Err(TraceError::NotATrace)));
+        assert!(matches!(Trace::from_bytes(&bytes[..bytes.len() - 1]), Err(TraceError::Truncated)));
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(Trace::from_bytes(&trailing), Err(TraceError::Malformed(_))));
+        assert!(matches!(Kaylee::new().finish_trace(), Err(TraceError::NotTracing)));
+    }
+}
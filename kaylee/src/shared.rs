@@ -4,7 +4,7 @@ pub fn parse_hex(hex: &str) -> Result<Vec<u8>, ParseIntError> {
     let split = hex.split(" ").collect::<Vec<&str>>();
     let mut results: Vec<u8> = vec![];
     for hex_string in split {
-        let byte = u8::from_str_radix(&hex_string, 16);
+        let byte = u8::from_str_radix(hex_string, 16);
         match byte {
             Ok(result) => { results.push(result) }
             Err(e) => { return Err(e) }
@@ -12,4 +12,63 @@ pub fn parse_hex(hex: &str) -> Result<Vec<u8>, ParseIntError> {
     }
 
     Ok(results)
-}
\ No newline at end of file
+}
+
+/// A 64-bit FNV-1a hash, for telling binary data apart
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Reads big-endian values from the front of a byte slice, for the binary file formats
+pub(crate) struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        ByteReader { bytes }
+    }
+
+    /// Take the next `length` bytes, or `None` if there are not that many left
+    pub(crate) fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        if length > self.bytes.len() {
+            return None;
+        }
+
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn i32(&mut self) -> Option<i32> {
+        self.u32().map(|value| value as i32)
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        let high = self.u32()? as u64;
+        let low = self.u32()? as u64;
+        Some((high << 32) | low)
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
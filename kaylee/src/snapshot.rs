@@ -0,0 +1,488 @@
+//! Versioned binary snapshots of a complete `Kaylee` and the `Program` it is running.
+//! All values are big-endian. Version 9 layout:
+//!
+//! | Field           | Size                                                |
+//! |-----------------|-----------------------------------------------------|
+//! | Magic `KSNP`    | 4 bytes                                             |
+//! | Version         | u16                                                 |
+//! | Registers       | u32 count, then i32 each                            |
+//! | Float Registers | u32 count, then f32 bits each                       |
+//! | Program Counter | u64                                                 |
+//! | Remainder       | u32                                                 |
+//! | Flags           | u8 (see `Flags::to_bits`)                           |
+//! | Exit Status     | u8 (0 running, 1 halted, 2 aborted), u8 code        |
+//! | Abort Message   | u64 length, then UTF-8 bytes (empty unless aborted) |
+//! | Trap Handlers   | u16 count, then u8 fault code and u64 index each    |
+//! | Trap Return     | u8 (1 inside a handler), u64 index to resume at     |
+//! | Threads         | see below                                           |
+//! | Max Stack Depth | u64                                                 |
+//! | Budget          | u8 (1 with a budget), u64 budget, u64 executed      |
+//! | Groups          | u16 (see `InstructionGroups::to_bits`)              |
+//! | Constants       | u64 count, then u8 kind and i32 or u64 each         |
+//! | Stack           | u64 count, then i32 each                            |
+//! | Memory          | u64 length, then bytes                              |
+//! | Program         | u64 length, then bytes                              |
+//!
+//! The running thread's state is in the fields above. Threads encodes the scheduler:
+//! u32 running thread id, u32 last allocated id, u8 (1 with a time slice) and u64 time slice,
+//! u64 instructions executed this turn, u8 (1 if a yield is pending),
+//! u32 count of waiting threads, each as u32 id, i32 registers (as many as above), 32 f32 bits float registers,
+//! u64 program counter, u32 remainder, u8 flags, u8 and u64 trap return, u64 count then i32 stack values,
+//! then u32 count of ended threads, each as u32 id and i32 result,
+//! then u32 count of threads waiting at a `JOIN`, each as u32 id and u32 id of the thread it waits for.
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{InstructionGroups, VmConfig};
+use crate::program::{Constant, Program, ProgramIndex};
+use crate::shared::ByteReader;
+use crate::threads::{Context, Scheduler};
+use crate::vm::{Byte, ExitStatus, FaultCode, Flags, FloatRegisterValue, Kaylee, RegisterValue};
+
+/// Errors concerning reading and writing snapshots
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The data does not start with the snapshot magic bytes
+    NotASnapshot,
+    /// The snapshot was written by an incompatible version of Kaylee
+    UnsupportedVersion(u16),
+    /// The data ended before the snapshot was complete
+    Truncated,
+    /// The snapshot does not fit this VM (wrong register count, trailing data, etc)
+    Malformed(String),
+    Io(std::io::Error),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "not a Kaylee snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {version}"),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Malformed(message) => write!(f, "malformed snapshot: {message}"),
+            SnapshotError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+/// The complete state of a VM and its Program at a point in time.
+/// Take one with `Kaylee::snapshot` and continue from it with `Kaylee::from_snapshot`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Snapshot {
+    pub(crate) registers: Vec<RegisterValue>,
+    pub(crate) float_registers: Vec<FloatRegisterValue>,
+    pub(crate) program_counter: ProgramIndex,
+    pub(crate) remainder: u32,
+    pub(crate) flags: Flags,
+    pub(crate) exit_status: Option<ExitStatus>,
+    pub(crate) traps: BTreeMap<FaultCode, ProgramIndex>,
+    pub(crate) trap_return: Option<ProgramIndex>,
+    pub(crate) scheduler: Scheduler,
+    pub(crate) max_stack_depth: usize,
+    pub(crate) instruction_budget: Option<u64>,
+    pub(crate) instructions_executed: u64,
+    pub(crate) groups: InstructionGroups,
+    pub(crate) stack: Vec<RegisterValue>,
+    pub(crate) memory: Vec<Byte>,
+    pub(crate) constants: Vec<Constant>,
+    pub(crate) program: Program,
+}
+
+impl Snapshot {
+    pub const MAGIC: [u8; 4] = *b"KSNP";
+    pub const VERSION: u16 = 9;
+
+    /// Encode the snapshot into its binary form
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(Snapshot::MAGIC);
+        bytes.extend(Snapshot::VERSION.to_be_bytes());
+
+        bytes.extend((self.registers.len() as u32).to_be_bytes());
+        for register in &self.registers {
+            bytes.extend(register.to_be_bytes());
+        }
+
+        bytes.extend((self.float_registers.len() as u32).to_be_bytes());
+        for register in &self.float_registers {
+            bytes.extend(register.to_bits().to_be_bytes());
+        }
+
+        bytes.extend((self.program_counter as u64).to_be_bytes());
+        bytes.extend(self.remainder.to_be_bytes());
+        bytes.push(self.flags.to_bits());
+        let (kind, code, message) = match &self.exit_status {
+            None => (0, 0, ""),
+            Some(ExitStatus::Halted(code)) => (1, *code, ""),
+            Some(ExitStatus::Aborted { code, message }) => (2, *code, message.as_str()),
+            // A VM only records the statuses of HALT and DIE
+            Some(ExitStatus::Finished) => (0, 0, ""),
+        };
+        bytes.push(kind);
+        bytes.push(code);
+        bytes.extend((message.len() as u64).to_be_bytes());
+        bytes.extend(message.as_bytes());
+
+        bytes.extend((self.traps.len() as u16).to_be_bytes());
+        for (code, handler) in &self.traps {
+            bytes.push(*code);
+            bytes.extend((*handler as u64).to_be_bytes());
+        }
+
+        bytes.push(self.trap_return.is_some() as u8);
+        bytes.extend((self.trap_return.unwrap_or(0) as u64).to_be_bytes());
+        Snapshot::write_scheduler(&mut bytes, &self.scheduler);
+        bytes.extend((self.max_stack_depth as u64).to_be_bytes());
+        bytes.push(self.instruction_budget.is_some() as u8);
+        bytes.extend(self.instruction_budget.unwrap_or(0).to_be_bytes());
+        bytes.extend(self.instructions_executed.to_be_bytes());
+        bytes.extend(self.groups.to_bits().to_be_bytes());
+
+        bytes.extend((self.constants.len() as u64).to_be_bytes());
+        for constant in &self.constants {
+            constant.write(&mut bytes);
+        }
+
+        bytes.extend((self.stack.len() as u64).to_be_bytes());
+        for value in &self.stack {
+            bytes.extend(value.to_be_bytes());
+        }
+
+        bytes.extend((self.memory.len() as u64).to_be_bytes());
+        bytes.extend(&self.memory);
+
+        bytes.extend((self.program.len() as u64).to_be_bytes());
+        bytes.extend(self.program.bytes());
+
+        bytes
+    }
+
+    /// Decode a snapshot from its binary form
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.take(4) != Some(&Snapshot::MAGIC[..]) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+
+        let version = reader.u16().ok_or(SnapshotError::Truncated)?;
+        if version != Snapshot::VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let snapshot = Snapshot::read_body(&mut reader).ok_or(SnapshotError::Truncated)?;
+
+        if let Err(error) = VmConfig::new().with_register_count(snapshot.registers.len()).validate() {
+            return Err(SnapshotError::Malformed(error.to_string()));
+        }
+
+        if snapshot.float_registers.len() != Kaylee::FLOAT_REGISTER_COUNT {
+            return Err(SnapshotError::Malformed(format!("expected {} float registers, found {}", Kaylee::FLOAT_REGISTER_COUNT, snapshot.float_registers.len())));
+        }
+
+        if !reader.is_empty() {
+            return Err(SnapshotError::Malformed(String::from("unexpected data after the program")));
+        }
+
+        Ok(snapshot)
+    }
+
+    fn read_body(reader: &mut ByteReader) -> Option<Snapshot> {
+        let register_count = reader.u32()?;
+        let registers = (0..register_count).map(|_| reader.i32()).collect::<Option<Vec<_>>>()?;
+
+        let float_register_count = reader.u32()?;
+        let float_registers = (0..float_register_count).map(|_| reader.u32().map(f32::from_bits)).collect::<Option<Vec<_>>>()?;
+
+        let program_counter = reader.u64()? as ProgramIndex;
+        let remainder = reader.u32()?;
+        let flags = Flags::from_bits(reader.u8()?);
+        let kind = reader.u8()?;
+        let code = reader.u8()?;
+        let message_length = reader.u64()? as usize;
+        let message = String::from_utf8_lossy(reader.take(message_length)?).into_owned();
+        let exit_status = match kind {
+            1 => Some(ExitStatus::Halted(code)),
+            2 => Some(ExitStatus::Aborted { code, message }),
+            _ => None,
+        };
+
+        let trap_count = reader.u16()?;
+        let traps = (0..trap_count)
+            .map(|_| Some((reader.u8()?, reader.u64()? as ProgramIndex)))
+            .collect::<Option<BTreeMap<_, _>>>()?;
+
+        let in_handler = reader.u8()? != 0;
+        let trap_return = reader.u64()? as ProgramIndex;
+        let trap_return = in_handler.then_some(trap_return);
+        let scheduler = Snapshot::read_scheduler(reader, registers.len())?;
+        let max_stack_depth = reader.u64()? as usize;
+        let has_budget = reader.u8()? != 0;
+        let instruction_budget = reader.u64()?;
+        let instructions_executed = reader.u64()?;
+        let groups = InstructionGroups::from_bits(reader.u16()?);
+
+        let constant_count = reader.u64()?;
+        let constants = (0..constant_count).map(|_| Constant::read(reader)).collect::<Option<Vec<_>>>()?;
+
+        let stack_length = reader.u64()?;
+        let stack = (0..stack_length).map(|_| reader.i32()).collect::<Option<Vec<_>>>()?;
+
+        let memory_length = reader.u64()? as usize;
+        let memory = reader.take(memory_length)?.to_vec();
+
+        let program_length = reader.u64()? as usize;
+        let program = Program::from(reader.take(program_length)?.to_vec());
+
+        Some(Snapshot {
+            registers,
+            float_registers,
+            program_counter,
+            remainder,
+            flags,
+            exit_status,
+            traps,
+            trap_return,
+            scheduler,
+            max_stack_depth,
+            instruction_budget: has_budget.then_some(instruction_budget),
+            instructions_executed,
+            groups,
+            stack,
+            memory,
+            constants,
+            program,
+        })
+    }
+
+    fn write_scheduler(bytes: &mut Vec<u8>, scheduler: &Scheduler) {
+        bytes.extend(scheduler.current.to_be_bytes());
+        bytes.extend(scheduler.next_id.to_be_bytes());
+        bytes.push(scheduler.time_slice.is_some() as u8);
+        bytes.extend(scheduler.time_slice.unwrap_or(0).to_be_bytes());
+        bytes.extend(scheduler.executed.to_be_bytes());
+        bytes.push(scheduler.yield_requested as u8);
+
+        bytes.extend((scheduler.ready.len() as u32).to_be_bytes());
+        for context in &scheduler.ready {
+            bytes.extend(context.id.to_be_bytes());
+            for register in &context.registers {
+                bytes.extend(register.to_be_bytes());
+            }
+            for register in &context.float_registers {
+                bytes.extend(register.to_bits().to_be_bytes());
+            }
+            bytes.extend((context.program_counter as u64).to_be_bytes());
+            bytes.extend(context.remainder.to_be_bytes());
+            bytes.push(context.flags.to_bits());
+            bytes.push(context.trap_return.is_some() as u8);
+            bytes.extend((context.trap_return.unwrap_or(0) as u64).to_be_bytes());
+            bytes.extend((context.stack.len() as u64).to_be_bytes());
+            for value in &context.stack {
+                bytes.extend(value.to_be_bytes());
+            }
+        }
+
+        bytes.extend((scheduler.finished.len() as u32).to_be_bytes());
+        for (id, result) in &scheduler.finished {
+            bytes.extend(id.to_be_bytes());
+            bytes.extend(result.to_be_bytes());
+        }
+
+        bytes.extend((scheduler.blocked.len() as u32).to_be_bytes());
+        for (id, target) in &scheduler.blocked {
+            bytes.extend(id.to_be_bytes());
+            bytes.extend(target.to_be_bytes());
+        }
+    }
+
+    fn read_scheduler(reader: &mut ByteReader, register_count: usize) -> Option<Scheduler> {
+        let current = reader.u32()?;
+        let next_id = reader.u32()?;
+        let has_time_slice = reader.u8()? != 0;
+        let time_slice = reader.u64()?;
+        let executed = reader.u64()?;
+        let yield_requested = reader.u8()? != 0;
+
+        let ready_count = reader.u32()?;
+        let ready = (0..ready_count).map(|_| Snapshot::read_context(reader, register_count)).collect::<Option<VecDeque<_>>>()?;
+
+        let finished_count = reader.u32()?;
+        let finished = (0..finished_count)
+            .map(|_| Some((reader.u32()?, reader.i32()?)))
+            .collect::<Option<BTreeMap<_, _>>>()?;
+
+        let blocked_count = reader.u32()?;
+        let blocked = (0..blocked_count)
+            .map(|_| Some((reader.u32()?, reader.u32()?)))
+            .collect::<Option<BTreeMap<_, _>>>()?;
+
+        Some(Scheduler {
+            current,
+            next_id,
+            ready,
+            finished,
+            blocked,
+            time_slice: has_time_slice.then_some(time_slice),
+            executed,
+            yield_requested,
+        })
+    }
+
+    fn read_context(reader: &mut ByteReader, register_count: usize) -> Option<Context> {
+        let id = reader.u32()?;
+        let registers = (0..register_count).map(|_| reader.i32()).collect::<Option<Vec<_>>>()?;
+
+        let mut float_registers = [0.0; Kaylee::FLOAT_REGISTER_COUNT];
+        for register in float_registers.iter_mut() {
+            *register = f32::from_bits(reader.u32()?);
+        }
+
+        let program_counter = reader.u64()? as ProgramIndex;
+        let remainder = reader.u32()?;
+        let flags = Flags::from_bits(reader.u8()?);
+        let in_handler = reader.u8()? != 0;
+        let trap_return = reader.u64()? as ProgramIndex;
+
+        let stack_length = reader.u64()?;
+        let stack = (0..stack_length).map(|_| reader.i32()).collect::<Option<Vec<_>>>()?;
+
+        Some(Context {
+            id,
+            registers,
+            float_registers,
+            program_counter,
+            remainder,
+            flags,
+            stack,
+            trap_return: in_handler.then_some(trap_return),
+        })
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Snapshot, SnapshotError> {
+        Snapshot::from_bytes(&fs::read(path)?)
+    }
+
+    /// The Program captured with the VM
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{InstructionGroup, VmConfig};
+    use crate::gas::Gas;
+    use crate::instructions::data::{Load, Push, StoreWord};
+    use crate::instructions::math::{Add, Divide};
+    use crate::instructions::program::JumpBackward;
+    use crate::program::Program;
+    use crate::snapshot::{Snapshot, SnapshotError};
+    use crate::vm::{Kaylee, RunOutcome};
+
+    fn program() -> Program {
+        Program::from(vec![
+            Load::OPCODE, 1, 0, 7,          // LOAD $1 #7
+            Load::OPCODE, 2, 0, 3,          // LOAD $2 #3
+            Add::OPCODE, 3, 3, 1,           // ADD $3 $3 $1
+            Divide::OPCODE, 4, 3, 2,        // DIV $4 $3 $2
+            Push::OPCODE, 4, 0, 0,          // PUSH $4
+            StoreWord::OPCODE, 3, 0, 8,     // STOREW $3 #8
+            JumpBackward::OPCODE, 0, 0, 4,  // JUMPB #4
+        ])
+    }
+
+    #[test]
+    fn test_restored_vm_continues_where_it_stopped() {
+        let mut uninterrupted = Kaylee::new();
+        uninterrupted.run_metered(&program(), &mut Gas::new(40)).unwrap();
+
+        let mut vm = Kaylee::new().with_memory_size(64).with_max_stack_depth(100);
+        assert_eq!(RunOutcome::BudgetExhausted, vm.run_metered(&program(), &mut Gas::new(17)).unwrap());
+
+        let bytes = vm.snapshot(&program()).to_bytes();
+        let (mut restored, restored_program) = Kaylee::from_snapshot(Snapshot::from_bytes(&bytes).unwrap());
+
+        assert_eq!(program(), restored_program);
+        assert_eq!(vm.all_registers(), restored.all_registers());
+        assert_eq!(vm.all_float_registers(), restored.all_float_registers());
+        assert_eq!(vm.program_counter(), restored.program_counter());
+        assert_eq!(vm.remainder(), restored.remainder());
+        assert_eq!(vm.flags(), restored.flags());
+        assert_eq!(vm.stack(), restored.stack());
+        assert_eq!(vm.memory(), restored.memory());
+
+        restored.run_metered(&restored_program, &mut Gas::new(23)).unwrap();
+
+        assert_eq!(uninterrupted.all_registers(), restored.all_registers());
+        assert_eq!(uninterrupted.program_counter(), restored.program_counter());
+        assert_eq!(uninterrupted.remainder(), restored.remainder());
+        assert_eq!(uninterrupted.stack(), restored.stack());
+        assert_eq!(&uninterrupted.memory()[..64], restored.memory());
+    }
+
+    #[test]
+    fn test_snapshot_file_round_trip() {
+        let mut vm = Kaylee::new();
+        vm.run_metered(&program(), &mut Gas::new(5)).unwrap();
+        let snapshot = vm.snapshot(&program());
+
+        let path = std::env::temp_dir().join(format!("kaylee-snapshot-{}.ksnp", std::process::id()));
+        snapshot.write_to_file(&path).unwrap();
+        let read = Snapshot::read_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(snapshot, read);
+    }
+
+    #[test]
+    fn test_snapshot_keeps_the_configuration() {
+        let config = VmConfig::new()
+            .with_register_count(64)
+            .with_memory_size(32)
+            .with_instruction_budget(50)
+            .without_group(InstructionGroup::Float);
+
+        let mut vm = config.clone().build().unwrap();
+        vm.set_register(63, 9).unwrap();
+        vm.run_metered(&program(), &mut Gas::new(5)).unwrap();
+
+        let bytes = vm.snapshot(&program()).to_bytes();
+        let (restored, _) = Kaylee::from_snapshot(Snapshot::from_bytes(&bytes).unwrap());
+
+        assert_eq!(config, restored.config());
+        assert_eq!(9, restored.register(63).unwrap());
+        assert_eq!(5, restored.instructions_executed());
+    }
+
+    #[test]
+    fn test_invalid_snapshots() {
+        let bytes = Kaylee::new().snapshot(&program()).to_bytes();
+
+        assert!(matches!(Snapshot::from_bytes(b"nope"), Err(SnapshotError::NotASnapshot)));
+        assert!(matches!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]), Err(SnapshotError::Truncated)));
+
+        let mut future = bytes.clone();
+        future[5] = 99;
+        assert!(matches!(Snapshot::from_bytes(&future), Err(SnapshotError::UnsupportedVersion(99))));
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(Snapshot::from_bytes(&trailing), Err(SnapshotError::Malformed(_))));
+    }
+}
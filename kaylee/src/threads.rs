@@ -0,0 +1,260 @@
+//! Cooperative green threads that share one `Kaylee`, its memory and its `Program`.
+//! Each thread has its own registers, float registers, program counter, remainder, flags, stack and trap handler state.
+//! Only the running thread's state lives in the VM. The others wait here, in round-robin order, until the scheduler switches to them.
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::program::ProgramIndex;
+use crate::vm::{Flags, FloatRegisterValue, Kaylee, RegisterValue};
+
+/// Identifies a thread. The thread a VM starts with is `MAIN_THREAD`
+pub type ThreadId = u32;
+
+/// The thread every VM starts with. When it stops, the whole VM stops.
+pub const MAIN_THREAD: ThreadId = 0;
+
+/// The state of a thread that is not running
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Context {
+    pub(crate) id: ThreadId,
+    pub(crate) registers: Vec<RegisterValue>,
+    pub(crate) float_registers: [FloatRegisterValue; Kaylee::FLOAT_REGISTER_COUNT],
+    pub(crate) program_counter: ProgramIndex,
+    pub(crate) remainder: u32,
+    pub(crate) flags: Flags,
+    pub(crate) stack: Vec<RegisterValue>,
+    pub(crate) trap_return: Option<ProgramIndex>,
+}
+
+/// Decides which thread runs next
+#[derive(Debug, PartialEq, Clone, Default)]
+pub(crate) struct Scheduler {
+    /// The thread whose state is in the VM
+    pub(crate) current: ThreadId,
+    pub(crate) next_id: ThreadId,
+    /// Threads waiting for their turn, in the order they will run
+    pub(crate) ready: VecDeque<Context>,
+    /// The `$0` of every thread that has ended, for `JOIN`
+    pub(crate) finished: BTreeMap<ThreadId, RegisterValue>,
+    /// The thread each thread stopped at a `JOIN` is waiting for. The scheduler skips it until that thread ends
+    pub(crate) blocked: BTreeMap<ThreadId, ThreadId>,
+    /// Switch threads after this many instructions, even without a `YIELD`
+    pub(crate) time_slice: Option<u64>,
+    /// Instructions the current thread has executed in this turn
+    pub(crate) executed: u64,
+    /// The current thread asked to give up the rest of its turn
+    pub(crate) yield_requested: bool,
+}
+
+impl Scheduler {
+    /// True when the main thread is the only thread, so there is nothing to schedule
+    pub(crate) fn is_idle(&self) -> bool {
+        self.ready.is_empty() && self.current == MAIN_THREAD
+    }
+
+    pub(crate) fn allocate_id(&mut self) -> ThreadId {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// True if the thread is running or waiting to run
+    pub(crate) fn is_alive(&self, id: ThreadId) -> bool {
+        self.current == id || self.ready.iter().any(|context| context.id == id)
+    }
+
+    /// True if the thread is waiting at a `JOIN` for a thread that has not ended yet
+    pub(crate) fn is_blocked(&self, id: ThreadId) -> bool {
+        self.blocked.get(&id).is_some_and(|target| self.is_alive(*target))
+    }
+
+    /// The position in the ready queue of the first thread that is not blocked
+    pub(crate) fn next_runnable(&self) -> Option<usize> {
+        self.ready.iter().position(|context| !self.is_blocked(context.id))
+    }
+
+    /// True if `target` is waiting for the running thread, directly or through other threads,
+    /// so the running thread waiting for `target` would leave them all blocked forever
+    pub(crate) fn would_deadlock(&self, target: ThreadId) -> bool {
+        let mut waiting = Some(target);
+
+        // A thread only blocks when this is false, so following what each thread waits for never goes round in a circle
+        while let Some(id) = waiting {
+            if id == self.current {
+                return true;
+            }
+
+            waiting = self.blocked.get(&id).copied().filter(|next| self.is_alive(*next));
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gas::Gas;
+    use crate::instructions::data::{Load, StoreWord};
+    use crate::instructions::machine::{Halt, Join, Spawn, Yield};
+    use crate::instructions::math::Add;
+    use crate::instructions::program::JumpBackward;
+    use crate::program::{DecodedProgram, Program};
+    use crate::snapshot::Snapshot;
+    use crate::threads::MAIN_THREAD;
+    use crate::vm::{ExecutionError, ExitStatus, Kaylee, RunOutcome};
+
+    /// The main thread spawns two workers that each add their copy of $1 to $0 three times, yielding between additions,
+    /// then joins them and adds up their results
+    fn workers() -> Program {
+        Program::from(vec![
+            Load::OPCODE, 1, 0, 1,              // 0: LOAD $1 #1
+            Spawn::OPCODE, 10, 0, 40,           // 4: SPAWN $10 #40
+            Load::OPCODE, 1, 0, 2,              // 8: LOAD $1 #2
+            Spawn::OPCODE, 11, 0, 40,           // 12: SPAWN $11 #40
+            Join::OPCODE, 12, 10, 0,            // 16: JOIN $12 $10
+            Join::OPCODE, 13, 11, 0,            // 20: JOIN $13 $11
+            Add::OPCODE, 14, 12, 13,            // 24: ADD $14 $12 $13
+            Halt::OPCODE, 0, 0, 0,              // 28: HALT
+            Halt::OPCODE, 0, 0, 0,              // 32: HALT
+            Halt::OPCODE, 0, 0, 0,              // 36: HALT
+            Add::OPCODE, 0, 0, 1,               // 40: ADD $0 $0 $1
+            Yield::OPCODE, 0, 0, 0,             // 44: YIELD
+            Add::OPCODE, 0, 0, 1,               // 48: ADD $0 $0 $1
+            Yield::OPCODE, 0, 0, 0,             // 52: YIELD
+            Add::OPCODE, 0, 0, 1,               // 56: ADD $0 $0 $1
+            Halt::OPCODE, 0, 0, 0,              // 60: HALT
+        ])
+    }
+
+    #[test]
+    fn test_spawn_yield_and_join() {
+        let mut vm = Kaylee::new();
+
+        assert_eq!(ExitStatus::Halted(0), vm.run(workers()).unwrap());
+
+        assert_eq!(1, vm.register(10).unwrap());
+        assert_eq!(2, vm.register(11).unwrap());
+        assert_eq!(3, vm.register(12).unwrap());
+        assert_eq!(6, vm.register(13).unwrap());
+        assert_eq!(9, vm.register(14).unwrap());
+        assert_eq!(MAIN_THREAD, vm.current_thread());
+        assert_eq!(1, vm.thread_count());
+    }
+
+    #[test]
+    fn test_threads_have_their_own_registers() {
+        // Each worker bumps its own $2 by 4 twice, yielding in between, then stores it
+        let program = Program::from(vec![
+            Spawn::OPCODE, 10, 0, 20,           // 0: SPAWN $10 #20
+            Spawn::OPCODE, 11, 0, 20,           // 4: SPAWN $11 #20
+            Join::OPCODE, 12, 10, 0,            // 8: JOIN $12 $10
+            Join::OPCODE, 13, 11, 0,            // 12: JOIN $13 $11
+            Halt::OPCODE, 0, 0, 0,              // 16: HALT
+            Load::OPCODE, 1, 0, 4,              // 20: LOAD $1 #4
+            Add::OPCODE, 2, 2, 1,               // 24: ADD $2 $2 $1
+            Yield::OPCODE, 0, 0, 0,             // 28: YIELD
+            Add::OPCODE, 2, 2, 1,               // 32: ADD $2 $2 $1
+            Yield::OPCODE, 0, 0, 0,             // 36: YIELD
+            StoreWord::OPCODE, 2, 0, 0,         // 40: STOREW $2 #0
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.run(program).unwrap();
+
+        // Both workers wrote 8 to address 0, and neither saw the other's registers
+        assert_eq!(&[0, 0, 0, 8], &vm.memory()[..4]);
+        assert_eq!(0, vm.register(2).unwrap());
+    }
+
+    #[test]
+    fn test_time_slice_preempts_threads_that_never_yield() {
+        // The worker spins forever without yielding
+        let program = Program::from(vec![
+            Spawn::OPCODE, 10, 0, 16,           // 0: SPAWN $10 #16
+            Yield::OPCODE, 0, 0, 0,             // 4: YIELD
+            Load::OPCODE, 1, 0, 5,              // 8: LOAD $1 #5
+            Halt::OPCODE, 0, 0, 0,              // 12: HALT
+            Add::OPCODE, 2, 2, 1,               // 16: ADD $2 $2 $1
+            JumpBackward::OPCODE, 0, 0, 1,      // 20: JUMPB #1
+        ]);
+
+        let mut vm = Kaylee::new();
+        assert_eq!(RunOutcome::BudgetExhausted, vm.run_metered(&program, &mut Gas::new(100)).unwrap());
+        assert_eq!(1, vm.current_thread());
+
+        let mut vm = Kaylee::new().with_time_slice(3);
+        assert_eq!(ExitStatus::Halted(0), vm.run(program).unwrap());
+        assert_eq!(5, vm.register(1).unwrap());
+    }
+
+    #[test]
+    fn test_decoded_programs_and_snapshots_keep_threads() {
+        let mut expected = Kaylee::new();
+        expected.run(workers()).unwrap();
+
+        let mut vm = Kaylee::new();
+        vm.run_decoded(&DecodedProgram::from(workers())).unwrap();
+        assert_eq!(expected.all_registers(), vm.all_registers());
+
+        // Stop part way through, while all three threads are alive
+        let mut vm = Kaylee::new();
+        vm.run_metered(&workers(), &mut Gas::new(7)).unwrap();
+        assert_eq!(3, vm.thread_count());
+
+        let bytes = vm.snapshot(&workers()).to_bytes();
+        let (mut restored, program) = Kaylee::from_snapshot(Snapshot::from_bytes(&bytes).unwrap());
+        assert_eq!(3, restored.thread_count());
+
+        restored.run(program).unwrap();
+        assert_eq!(expected.all_registers(), restored.all_registers());
+    }
+
+    #[test]
+    fn test_join_errors() {
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 7).unwrap();
+        let error = vm.run(Program::from(vec![Join::OPCODE, 0, 1, 0])).unwrap_err();
+        assert_eq!(ExecutionError::InvalidJoin(7), error.error);
+
+        // The main thread cannot join itself
+        let mut vm = Kaylee::new();
+        let error = vm.run(Program::from(vec![Join::OPCODE, 0, 1, 0])).unwrap_err();
+        assert_eq!(ExecutionError::InvalidJoin(MAIN_THREAD), error.error);
+    }
+
+    #[test]
+    fn test_waiting_threads_are_passed_over() {
+        let program = Program::from(vec![
+            Spawn::OPCODE, 10, 0, 16,           // 0: SPAWN $10 #16
+            Join::OPCODE, 11, 10, 0,            // 4: JOIN $11 $10
+            Halt::OPCODE, 0, 0, 0,              // 8: HALT
+            Halt::OPCODE, 0, 0, 0,              // 12: HALT
+            Load::OPCODE, 0, 0, 5,              // 16: LOAD $0 #5
+            Add::OPCODE, 0, 0, 0,               // 20: ADD $0 $0 $0
+            Add::OPCODE, 0, 0, 0,               // 24: ADD $0 $0 $0
+            Halt::OPCODE, 0, 0, 0,              // 28: HALT
+        ]);
+
+        let mut vm = Kaylee::new().with_time_slice(1);
+        assert_eq!(ExitStatus::Halted(0), vm.run(program).unwrap());
+        assert_eq!(20, vm.register(11).unwrap());
+
+        // SPAWN, the JOIN that waits, the four worker instructions, then the JOIN again and HALT
+        assert_eq!(8, vm.instructions_executed());
+    }
+
+    #[test]
+    fn test_threads_joining_each_other_fault() {
+        let program = Program::from(vec![
+            Spawn::OPCODE, 10, 0, 12,           // 0: SPAWN $10 #12
+            Join::OPCODE, 11, 10, 0,            // 4: JOIN $11 $10
+            Halt::OPCODE, 0, 0, 0,              // 8: HALT
+            Join::OPCODE, 12, 20, 0,            // 12: JOIN $12 $20, where $20 is 0, the main thread
+        ]);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run(program).unwrap_err();
+
+        assert_eq!(ExecutionError::InvalidJoin(MAIN_THREAD), error.error);
+        assert_eq!(12, error.program_index);
+        assert_eq!(1, vm.current_thread());
+    }
+}
@@ -0,0 +1,307 @@
+//! An undo log for stepping a `Kaylee` backwards.
+//! When enabled with `Kaylee::with_undo_log`, the VM keeps one `UndoRecord` for every instruction it executes:
+//! the running thread's program counter, remainder and flags from before the instruction, plus every register,
+//! memory, stack, trap handler and thread change it made. Waiting threads are only saved when one ends, so a record
+//! stays small however many threads there are. `Kaylee::step_back` uses the newest record to undo one instruction.
+//! Console input and output are not undone, but a recorded or replayed Trace is rewound to where the instruction found it.
+use std::collections::VecDeque;
+
+use crate::program::ProgramIndex;
+use crate::threads::{Context, ThreadId};
+use crate::vm::{Byte, ExitStatus, FaultCode, Flags, FloatRegisterValue, Kaylee, MemoryAddress, RegisterValue, RegisterWrite};
+
+/// A change to the stack, in the order the instruction made it
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum StackChange {
+    Pushed,
+    Popped(RegisterValue),
+}
+
+/// A change to the threads, in the order the instruction made it
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum SchedulerChange {
+    /// A new thread was added to the back of the ready queue
+    Spawned,
+    /// The running thread moved to the back of the ready queue, and the thread at this position started running
+    Rotated(usize),
+    /// The running thread ended in this state, and the thread at this position of the ready queue started running
+    Retired(Box<Context>, usize),
+    /// The thread's entry in the blocked threads before a `JOIN` changed it
+    Blocked(ThreadId, Option<ThreadId>),
+}
+
+/// Everything needed to undo one instruction
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct UndoRecord {
+    pub(crate) float_registers: [FloatRegisterValue; Kaylee::FLOAT_REGISTER_COUNT],
+    pub(crate) program_counter: ProgramIndex,
+    pub(crate) remainder: u32,
+    pub(crate) flags: Flags,
+    pub(crate) exit_status: Option<ExitStatus>,
+    pub(crate) trap_return: Option<ProgramIndex>,
+    pub(crate) instructions_executed: u64,
+    /// Instructions the running thread had executed in its turn, and whether it had asked to yield
+    pub(crate) turn: (u64, bool),
+    /// Thread starts and switches, oldest first
+    pub(crate) scheduler: Vec<SchedulerChange>,
+    /// The registers the instruction wrote and the values they replaced, oldest first
+    pub(crate) register_writes: Vec<RegisterWrite>,
+    /// The bytes each memory write replaced, oldest first
+    pub(crate) memory: Vec<(MemoryAddress, Vec<Byte>)>,
+    /// The running thread's stack changes, oldest first
+    pub(crate) stack: Vec<StackChange>,
+    /// The handler each `TRAP` or `UNTRAP` replaced, oldest first
+    pub(crate) traps: Vec<(FaultCode, Option<ProgramIndex>)>,
+    /// How many trace events had been recorded or replayed
+    pub(crate) trace_position: usize,
+}
+
+/// The newest UndoRecords, up to a capacity. The oldest are dropped first.
+#[derive(Debug, Clone)]
+pub(crate) struct UndoLog {
+    pub(crate) capacity: usize,
+    pub(crate) records: VecDeque<UndoRecord>,
+    /// The record for the instruction running right now
+    pub(crate) current: Option<UndoRecord>,
+}
+
+impl UndoLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        UndoLog { capacity, records: VecDeque::new(), current: None }
+    }
+
+    /// Keep the record for the instruction that just ran
+    pub(crate) fn commit(&mut self) {
+        if let Some(record) = self.current.take() {
+            if self.records.len() >= self.capacity {
+                self.records.pop_front();
+            }
+
+            if self.capacity > 0 {
+                self.records.push_back(record);
+            }
+        }
+    }
+
+    pub(crate) fn record_register(&mut self, write: RegisterWrite) {
+        if let Some(record) = &mut self.current {
+            record.register_writes.push(write);
+        }
+    }
+
+    pub(crate) fn record_memory(&mut self, address: MemoryAddress, old: &[Byte]) {
+        if let Some(record) = &mut self.current {
+            record.memory.push((address, old.to_vec()));
+        }
+    }
+
+    pub(crate) fn record_stack(&mut self, change: StackChange) {
+        if let Some(record) = &mut self.current {
+            record.stack.push(change);
+        }
+    }
+
+    pub(crate) fn record_scheduler(&mut self, change: SchedulerChange) {
+        if let Some(record) = &mut self.current {
+            record.scheduler.push(change);
+        }
+    }
+
+    pub(crate) fn record_trap(&mut self, code: FaultCode, old: Option<ProgramIndex>) {
+        if let Some(record) = &mut self.current {
+            record.traps.push((code, old));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::data::{Load, Pop, Push, StoreWord};
+    use crate::instructions::machine::{Halt, Join, Spawn, Yield};
+    use crate::instructions::math::{Add, Divide};
+    use crate::instructions::program::{Call, RemoveTrap, Return, Trap};
+    use crate::instructions::system::Random;
+    use crate::program::Program;
+    use crate::vm::{ExitStatus, Kaylee};
+
+    fn program() -> Program {
+        Program::from(vec![
+            Load::OPCODE, 1, 0, 10,         // 0: LOAD $1 #10
+            Load::OPCODE, 2, 0, 3,          // 4: LOAD $2 #3
+            Divide::OPCODE, 3, 1, 2,        // 8: DIV $3 $1 $2
+            StoreWord::OPCODE, 3, 0, 16,    // 12: STOREW $3 #16
+            Push::OPCODE, 1, 0, 0,          // 16: PUSH $1
+            Call::OPCODE, 0, 0, 32,         // 20: CALL #32
+            Pop::OPCODE, 4, 0, 0,           // 24: POP $4
+            Halt::OPCODE, 0, 0, 0,          // 28: HALT
+            Add::OPCODE, 3, 3, 1,           // 32: ADD $3 $3 $1
+            Return::OPCODE, 0, 0, 0,        // 36: RET
+        ])
+    }
+
+    #[test]
+    fn test_step_back_undoes_every_instruction() {
+        let mut vm = Kaylee::new().with_undo_log(100);
+        let mut states = Vec::new();
+
+        for _ in 0..10 {
+            states.push((vm.all_registers().to_vec(), vm.program_counter(), vm.remainder(), vm.flags(), vm.stack().to_vec(), vm.memory()[..32].to_vec()));
+            vm.run_next(&program()).unwrap();
+        }
+
+        assert!(vm.is_halted());
+        assert_eq!(10, vm.undo_depth());
+
+        while let Some(state) = states.pop() {
+            assert!(vm.step_back());
+            assert_eq!(state, (vm.all_registers().to_vec(), vm.program_counter(), vm.remainder(), vm.flags(), vm.stack().to_vec(), vm.memory()[..32].to_vec()));
+        }
+
+        assert!(!vm.step_back());
+        assert!(!vm.is_halted());
+
+        // Running forward again gives the same result
+        assert_eq!(ExitStatus::Halted(0), vm.run(program()).unwrap());
+        assert_eq!(10, vm.register(4).unwrap());
+    }
+
+    #[test]
+    fn test_undo_log_capacity() {
+        let mut vm = Kaylee::new().with_undo_log(3);
+        vm.run(program()).unwrap();
+
+        assert_eq!(3, vm.undo_depth());
+        assert!(vm.step_back());
+        assert!(vm.step_back());
+        assert!(vm.step_back());
+        assert!(!vm.step_back());
+        assert_eq!(36, vm.program_counter());
+
+        let mut vm = Kaylee::new();
+        vm.run(program()).unwrap();
+        assert!(!vm.step_back());
+    }
+
+    #[test]
+    fn test_step_back_across_thread_switches() {
+        let program = Program::from(vec![
+            Spawn::OPCODE, 10, 0, 16,       // 0: SPAWN $10 #16
+            Join::OPCODE, 11, 10, 0,        // 4: JOIN $11 $10
+            Halt::OPCODE, 0, 0, 0,          // 8: HALT
+            Halt::OPCODE, 0, 0, 0,          // 12: HALT
+            Load::OPCODE, 5, 0, 7,          // 16: LOAD $5 #7
+            Push::OPCODE, 5, 0, 0,          // 20: PUSH $5
+            Yield::OPCODE, 0, 0, 0,         // 24: YIELD
+            Pop::OPCODE, 0, 0, 0,           // 28: POP $0
+            Halt::OPCODE, 0, 0, 0,          // 32: HALT
+        ]);
+
+        let mut expected = Kaylee::new();
+        expected.run(program.clone()).unwrap();
+
+        let mut vm = Kaylee::new().with_time_slice(1).with_undo_log(100);
+        vm.run(program.clone()).unwrap();
+        assert_eq!(7, vm.register(11).unwrap());
+
+        while vm.step_back() {}
+        assert_eq!(0, vm.program_counter());
+        assert_eq!(1, vm.thread_count());
+        assert!(vm.stack().is_empty());
+
+        vm.run(program).unwrap();
+        assert_eq!(expected.all_registers(), vm.all_registers());
+    }
+
+    #[test]
+    fn test_step_back_restores_every_thread() {
+        let program = Program::from(vec![
+            Spawn::OPCODE, 10, 0, 20,       // 0: SPAWN $10 #20
+            Spawn::OPCODE, 11, 0, 20,       // 4: SPAWN $11 #20
+            Join::OPCODE, 12, 10, 0,        // 8: JOIN $12 $10
+            Join::OPCODE, 13, 11, 0,        // 12: JOIN $13 $11
+            Halt::OPCODE, 0, 0, 0,          // 16: HALT
+            Push::OPCODE, 10, 0, 0,         // 20: PUSH $10
+            Add::OPCODE, 0, 0, 10,          // 24: ADD $0 $0 $10
+            Yield::OPCODE, 0, 0, 0,         // 28: YIELD
+            Pop::OPCODE, 1, 0, 0,           // 32: POP $1
+            Add::OPCODE, 0, 0, 1,           // 36: ADD $0 $0 $1
+        ]);
+
+        let mut vm = Kaylee::new().with_time_slice(2).with_undo_log(100);
+        let mut snapshots = Vec::new();
+
+        while !vm.is_halted() {
+            snapshots.push(vm.snapshot(&program));
+            vm.run_next(&program).unwrap();
+        }
+
+        let mut expected = Kaylee::new().with_time_slice(2);
+        expected.run(program.clone()).unwrap();
+        assert_eq!(expected.all_registers(), vm.all_registers());
+        assert_eq!(2, vm.register(13).unwrap());
+
+        while let Some(snapshot) = snapshots.pop() {
+            assert!(vm.step_back());
+            assert_eq!(snapshot, vm.snapshot(&program));
+        }
+
+        assert!(!vm.step_back());
+    }
+
+    #[test]
+    fn test_step_back_over_trap_and_untrap() {
+        let program = Program::from(vec![
+            Trap::OPCODE, 4, 0, 16,         // 0: TRAP #4 #16
+            Trap::OPCODE, 4, 0, 20,         // 4: TRAP #4 #20
+            RemoveTrap::OPCODE, 4, 0, 0,    // 8: UNTRAP #4
+            Halt::OPCODE, 0, 0, 0,          // 12: HALT
+            Halt::OPCODE, 1, 0, 0,          // 16: HALT #1
+            Halt::OPCODE, 2, 0, 0,          // 20: HALT #2
+        ]);
+
+        let mut vm = Kaylee::new().with_undo_log(100);
+        let mut traps = Vec::new();
+
+        while !vm.is_halted() {
+            traps.push(vm.traps().clone());
+            vm.run_next(&program).unwrap();
+        }
+
+        assert!(vm.traps().is_empty());
+
+        while let Some(expected) = traps.pop() {
+            assert!(vm.step_back());
+            assert_eq!(expected, *vm.traps());
+        }
+    }
+
+    #[test]
+    fn test_step_back_rewinds_the_trace() {
+        let program = Program::from(vec![
+            Random::OPCODE, 1, 0, 0,        // 0: RAND $1
+            Random::OPCODE, 2, 0, 0,        // 4: RAND $2
+            Halt::OPCODE, 0, 0, 0,          // 8: HALT
+        ]);
+
+        // Stepping back while recording forgets the values, so the trace matches the run that finished
+        let mut recording = Kaylee::new().with_undo_log(100).with_recording(&program);
+        recording.run_next(&program).unwrap();
+        recording.run_next(&program).unwrap();
+        assert!(recording.step_back());
+        recording.run(program.clone()).unwrap();
+
+        let trace = recording.finish_trace().unwrap();
+        assert_eq!(2, trace.events().len());
+
+        // Stepping back while replaying consumes the same value again
+        let mut replay = Kaylee::new().with_undo_log(100).with_replay(trace, &program).unwrap();
+        replay.run_next(&program).unwrap();
+        replay.run_next(&program).unwrap();
+        assert!(replay.step_back());
+        assert!(replay.step_back());
+        replay.run(program).unwrap();
+
+        assert_eq!(recording.all_registers(), replay.all_registers());
+        assert!(replay.finish_trace().is_ok());
+    }
+}
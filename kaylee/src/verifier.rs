@@ -0,0 +1,279 @@
+//! Checks a Program against the VM it will run on, without running it.
+//! The verifier walks the program one four-byte instruction at a time and reports every problem it finds,
+//! with the byte offset of the instruction.
+//! A program that passes becomes a `VerifiedProgram`, which `Kaylee::run_verified` runs without the per-step checks.
+use std::fmt::{Display, Formatter};
+
+use crate::config::VmConfig;
+use crate::instructions::{decode_operation, InstructionDecodeError, OperandType, OperandValues, target_operand};
+use crate::instructions::program::{JumpBackward, JumpForward};
+use crate::program::{DecodedInstruction, Program, ProgramIndex};
+use crate::vm::{Byte, Kaylee, RegisterId};
+
+/// Something wrong with one instruction
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Problem {
+    IllegalOpcode(Byte),
+    /// The program ends in the middle of the instruction
+    TruncatedInstruction,
+    /// The register is at or above the configured register count
+    RegisterOutOfBounds(RegisterId),
+    FloatRegisterOutOfBounds(RegisterId),
+    /// The opcode belongs to an InstructionGroup the configuration disabled
+    DisabledInstruction(Byte),
+    /// A constant jump target is before the start or past the end of the program
+    JumpOutOfRange(i64),
+    /// A constant jump target is inside another instruction
+    MisalignedJump(ProgramIndex),
+}
+
+/// A Problem and the ProgramIndex of the instruction it was found in
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VerifyError {
+    pub offset: ProgramIndex,
+    pub problem: Problem,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.offset)?;
+
+        match self.problem {
+            Problem::IllegalOpcode(opcode) => write!(f, "illegal opcode {opcode}"),
+            Problem::TruncatedInstruction => write!(f, "truncated instruction"),
+            Problem::RegisterOutOfBounds(register) => write!(f, "register ${register} is out of bounds"),
+            Problem::FloatRegisterOutOfBounds(register) => write!(f, "float register %{register} is out of bounds"),
+            Problem::DisabledInstruction(opcode) => write!(f, "opcode {opcode} is disabled"),
+            Problem::JumpOutOfRange(target) => write!(f, "jump target {target} is outside the program"),
+            Problem::MisalignedJump(target) => write!(f, "jump target {target} is not on an instruction boundary"),
+        }
+    }
+}
+
+/// Check every instruction of `program` against a VM built from `config`
+pub fn verify(program: &Program, config: &VmConfig) -> Result<(), Vec<VerifyError>> {
+    let mut errors = Vec::new();
+
+    for offset in (0..program.len()).step_by(4) {
+        let mut problem = |problem| errors.push(VerifyError { offset, problem });
+
+        let opcode = program[offset];
+        if !config.groups().allows(opcode) {
+            problem(Problem::DisabledInstruction(opcode));
+        }
+
+        let mut program_counter = offset;
+        let (operation, operand_values) = match decode_operation(program, &mut program_counter) {
+            Some(Ok(decoded)) => decoded,
+            Some(Err(InstructionDecodeError::IllegalOpcode(opcode))) => {
+                problem(Problem::IllegalOpcode(opcode));
+                continue;
+            }
+            // Decoding never reports `InvalidValueSize`, but an operand that does not fit is cut short all the same
+            Some(Err(InstructionDecodeError::TruncatedInstruction | InstructionDecodeError::InvalidValueSize)) | None => {
+                problem(Problem::TruncatedInstruction);
+                continue;
+            }
+        };
+
+        // Decoding only needs the operand bytes, but every instruction takes four
+        if offset + 4 > program.len() {
+            problem(Problem::TruncatedInstruction);
+        }
+
+        for (operand, value) in operation.signature().operands.iter().zip(operand_values) {
+            match operand {
+                OperandType::RegisterId if value.as_register_id() >= config.register_count() => {
+                    problem(Problem::RegisterOutOfBounds(value.as_register_id()));
+                }
+                OperandType::FloatRegisterId if value.as_register_id() >= Kaylee::FLOAT_REGISTER_COUNT => {
+                    problem(Problem::FloatRegisterOutOfBounds(value.as_register_id()));
+                }
+                _ => {}
+            }
+        }
+
+        match constant_target(opcode, offset, &operand_values) {
+            Some(target) if target < 0 || target > program.len() as i64 => problem(Problem::JumpOutOfRange(target)),
+            Some(target) if target % 4 != 0 => problem(Problem::MisalignedJump(target as ProgramIndex)),
+            _ => {}
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+/// Where an instruction with a constant target may jump to. Targets held in registers are only known at runtime.
+fn constant_target(opcode: Byte, offset: ProgramIndex, operand_values: &OperandValues) -> Option<i64> {
+    let instructions = |operand: usize| operand_values[operand].as_constant_value() as i64 * 4;
+
+    match opcode {
+        // Relative jumps count from the end of the jump
+        JumpForward::OPCODE => Some(offset as i64 + 4 + instructions(0)),
+        JumpBackward::OPCODE => Some(offset as i64 - instructions(0)),
+        _ => target_operand(opcode).map(|operand| operand_values[operand].as_program_index() as i64),
+    }
+}
+
+/// A Program that passed `verify`, decoded one instruction per four bytes
+#[derive(Clone)]
+pub struct VerifiedProgram {
+    program: Program,
+    config: VmConfig,
+    instructions: Vec<DecodedInstruction>,
+}
+
+impl VerifiedProgram {
+    /// Verify `program` for a VM built from `config`, and decode it if it passes
+    pub fn new(program: Program, config: &VmConfig) -> Result<Self, Vec<VerifyError>> {
+        verify(&program, config)?;
+
+        let instructions = (0..program.len())
+            .step_by(4)
+            .map(|offset| {
+                let mut program_counter = offset;
+                match decode_operation(&program, &mut program_counter) {
+                    Some(Ok((operation, operand_values))) => DecodedInstruction { operation, operand_values },
+                    _ => unreachable!("a verified program only holds valid instructions"),
+                }
+            })
+            .collect();
+
+        Ok(VerifiedProgram { program, config: config.clone(), instructions })
+    }
+
+    /// The Program that was verified
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// The configuration the program was verified for
+    pub fn config(&self) -> &VmConfig {
+        &self.config
+    }
+
+    /// True if every check the verifier made also holds on a VM built from `config`
+    pub(crate) fn runs_on(&self, config: &VmConfig) -> bool {
+        let groups = self.config.groups.to_bits();
+
+        config.register_count >= self.config.register_count && config.groups.to_bits() & groups == groups
+    }
+
+    /// The instruction starting at a ProgramIndex, or `None` if no instruction starts there
+    pub(crate) fn instruction(&self, index: ProgramIndex) -> Option<&DecodedInstruction> {
+        match index % 4 {
+            0 => self.instructions.get(index / 4),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{InstructionGroup, VmConfig};
+    use crate::instructions::data::Load;
+    use crate::instructions::float::FloatLoadHigh;
+    use crate::instructions::machine::Halt;
+    use crate::instructions::math::{Add, WrappingSubtract};
+    use crate::instructions::program::{Call, Jump, JumpBackward, JumpForward, JumpNotZero, Return};
+    use crate::instructions::system::Print;
+    use crate::program::Program;
+    use crate::verifier::{Problem, verify, VerifiedProgram, VerifyError};
+    use crate::vm::{ExecutionError, Kaylee};
+
+    #[test]
+    fn test_reports_every_problem_with_its_offset() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 5,              // 0: LOAD $1 #5
+            Add::OPCODE, 40, 1, 33,             // 4: ADD $40 $1 $33
+            FloatLoadHigh::OPCODE, 32, 0, 0,    // 8: FLOADH %32 #0
+            255, 0, 0, 0,                       // 12: not an instruction
+            Print::OPCODE, 1, 0, 0,             // 16: PRINT $1
+            Halt::OPCODE, 0,                    // 20: HALT, without its padding
+        ]);
+
+        let config = VmConfig::new().without_group(InstructionGroup::System);
+        let errors = verify(&program, &config).unwrap_err();
+
+        assert_eq!(vec![
+            VerifyError { offset: 4, problem: Problem::RegisterOutOfBounds(40) },
+            VerifyError { offset: 4, problem: Problem::RegisterOutOfBounds(33) },
+            VerifyError { offset: 8, problem: Problem::FloatRegisterOutOfBounds(32) },
+            VerifyError { offset: 12, problem: Problem::IllegalOpcode(255) },
+            VerifyError { offset: 16, problem: Problem::DisabledInstruction(Print::OPCODE) },
+            VerifyError { offset: 20, problem: Problem::TruncatedInstruction },
+        ], errors);
+        assert_eq!("4: register $40 is out of bounds", errors[0].to_string());
+    }
+
+    #[test]
+    fn test_registers_are_checked_against_the_config() {
+        let program = Program::from(vec![
+            Add::OPCODE, 40, 1, 33,             // ADD $40 $1 $33
+            Halt::OPCODE, 0, 0, 0,              // HALT
+        ]);
+
+        assert!(verify(&program, &VmConfig::new()).is_err());
+        assert_eq!(Ok(()), verify(&program, &VmConfig::new().with_register_count(64)));
+    }
+
+    #[test]
+    fn test_constant_jump_targets() {
+        let program = Program::from(vec![
+            Jump::OPCODE, 0, 0, 6,              // 0: JUMP #6
+            JumpForward::OPCODE, 0, 0, 3,       // 4: JUMPF #3
+            JumpBackward::OPCODE, 0, 0, 3,      // 8: JUMPB #3
+            JumpNotZero::OPCODE, 0, 0, 32,      // 12: JUMPNZ #32
+            Call::OPCODE, 0, 0, 24,             // 16: CALL #24
+            JumpBackward::OPCODE, 0, 0, 5,      // 20: JUMPB #5
+            Return::OPCODE, 0, 0, 0,            // 24: RET
+        ]);
+
+        assert_eq!(Err(vec![
+            VerifyError { offset: 0, problem: Problem::MisalignedJump(6) },
+            VerifyError { offset: 8, problem: Problem::JumpOutOfRange(-4) },
+            VerifyError { offset: 12, problem: Problem::JumpOutOfRange(32) },
+        ]), verify(&program, &VmConfig::new()));
+    }
+
+    #[test]
+    fn test_verified_program_runs_like_the_program() {
+        let program = Program::from(vec![
+            Load::OPCODE, 1, 0, 1,              // 0: LOAD $1 #1
+            Load::OPCODE, 2, 0, 100,            // 4: LOAD $2 #100
+            Add::OPCODE, 3, 3, 2,               // 8: ADD $3 $3 $2
+            WrappingSubtract::OPCODE, 2, 2, 1,  // 12: SUBW $2 $2 $1
+            JumpNotZero::OPCODE, 0, 0, 8,       // 16: JUMPNZ #8
+            Halt::OPCODE, 0, 0, 0,              // 20: HALT
+        ]);
+
+        let mut expected = Kaylee::new();
+        let status = expected.run(program.clone()).unwrap();
+
+        let verified = VerifiedProgram::new(program, &VmConfig::new()).unwrap();
+        let mut vm = Kaylee::new();
+        assert_eq!(status, vm.run_verified(&verified).unwrap());
+        assert_eq!(5050, vm.register(3).unwrap());
+        assert_eq!(expected.all_registers(), vm.all_registers());
+        assert_eq!(expected.instructions_executed(), vm.instructions_executed());
+
+        let rejected = VerifiedProgram::new(Program::from(vec![Halt::OPCODE, 0, 0]), &VmConfig::new());
+        assert!(rejected.is_err());
+    }
+
+    #[test]
+    fn test_verified_program_on_a_smaller_vm_is_still_checked() {
+        let program = Program::from(vec![Load::OPCODE, 40, 0, 7]);
+        let verified = VerifiedProgram::new(program, &VmConfig::new().with_register_count(64)).unwrap();
+
+        let mut vm = VmConfig::new().with_register_count(64).build().unwrap();
+        vm.run_verified(&verified).unwrap();
+        assert_eq!(7, vm.register(40).unwrap());
+
+        let mut vm = Kaylee::new();
+        assert_eq!(ExecutionError::RegisterOutOfBounds(40), vm.run_verified(&verified).unwrap_err().error);
+    }
+}
@@ -1,5 +1,23 @@
-use crate::instructions::{decode_next_instruction, Instruction};
-use crate::program::{Program, ProgramIndex};
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{ConfigError, InstructionGroups, VmConfig};
+use crate::control::{CancellationToken, PauseHandle, WhilePaused, YieldNow};
+use crate::gas::Gas;
+use crate::instructions::{decode_next_instruction, Instruction, InstructionDecodeError};
+use crate::kbc::KbcError;
+use crate::observer::ExecutionObserver;
+use crate::program::{Constant, DecodedProgram, Program, ProgramIndex};
+use crate::replay::{Event, SyscallEffect, Trace, TraceError, TraceEvent, Tracer};
+use crate::snapshot::Snapshot;
+use crate::threads::{Context, MAIN_THREAD, Scheduler, ThreadId};
+use crate::undo::{SchedulerChange, StackChange, UndoLog, UndoRecord};
+use crate::verifier::VerifiedProgram;
 
 // The id used for each register, key in the vector
 pub type RegisterId = usize;
@@ -7,6 +25,9 @@ pub type RegisterId = usize;
 // The value the VM uses
 pub type RegisterValue = i32;
 
+// The value held by the float registers
+pub type FloatRegisterValue = f32;
+
 // A single Opcode or register contents, or whatever
 pub type Byte = u8;
 
@@ -15,6 +36,20 @@ pub type HalfWord = u16;
 pub type Word = u32;
 pub type DoubleWord = u32;
 
+// A byte offset into the VM's linear memory
+pub type MemoryAddress = usize;
+
+// The number identifying a kind of ExecutionError, for trap handlers
+pub type FaultCode = u8;
+
+// The number a `SYSCALL` uses to pick a host function
+pub type SyscallId = u16;
+
+/// A function provided by the embedding application, called by `SYSCALL`.
+/// It reads its arguments from registers and writes its results back to registers.
+pub type HostFunction = Arc<dyn Fn(&mut Kaylee) -> Result<(), ExecutionError> + Send + Sync>;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ExecutionResult {
     Halted,
     NoAction,
@@ -23,94 +58,1453 @@ pub enum ExecutionResult {
     Equality(bool),
 }
 
+/// A write to a register, with the value it replaced
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct RegisterWrite {
+    pub(crate) register: RegisterId,
+    pub(crate) old: RegisterValue,
+    pub(crate) new: RegisterValue,
+}
+
+/// Status flags, updated by arithmetic and comparison instructions and tested by conditional branches
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Flags {
+    /// The result was zero, or the compared terms were equal
+    pub zero: bool,
+    /// The result was negative, or the left compared term was less than the right (signed)
+    pub negative: bool,
+    /// An unsigned carry (or borrow) out of the highest bit
+    pub carry: bool,
+    /// The signed result did not fit in a register
+    pub overflow: bool,
+}
+
+impl Flags {
+    const ZERO: u8 = 0b0001;
+    const NEGATIVE: u8 = 0b0010;
+    const CARRY: u8 = 0b0100;
+    const OVERFLOW: u8 = 0b1000;
+
+    /// Flags for an arithmetic result
+    pub fn arithmetic(result: RegisterValue, carry: bool, overflow: bool) -> Self {
+        Flags {
+            zero: result == 0,
+            negative: result < 0,
+            carry,
+            overflow,
+        }
+    }
+
+    /// Flags for comparing two terms, as if subtracting `right` from `left`
+    pub fn compare(left: RegisterValue, right: RegisterValue) -> Self {
+        Flags {
+            zero: left == right,
+            negative: left < right,
+            carry: (left as u32) < (right as u32),
+            overflow: left.overflowing_sub(right).1,
+        }
+    }
+
+    /// Flags for comparing two floats. Carry means the terms are unordered, because at least one is NaN.
+    pub fn compare_floats(left: FloatRegisterValue, right: FloatRegisterValue) -> Self {
+        Flags {
+            zero: left == right,
+            negative: left < right,
+            carry: left.is_nan() || right.is_nan(),
+            overflow: false,
+        }
+    }
+
+    /// Pack the flags into the low four bits of a byte: zero, negative, carry, overflow
+    pub fn to_bits(self) -> u8 {
+        let mut bits = 0;
+        if self.zero { bits |= Flags::ZERO }
+        if self.negative { bits |= Flags::NEGATIVE }
+        if self.carry { bits |= Flags::CARRY }
+        if self.overflow { bits |= Flags::OVERFLOW }
+        bits
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Flags {
+            zero: bits & Flags::ZERO != 0,
+            negative: bits & Flags::NEGATIVE != 0,
+            carry: bits & Flags::CARRY != 0,
+            overflow: bits & Flags::OVERFLOW != 0,
+        }
+    }
+}
+
+impl Display for Flags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let flag = |set: bool, name: char| if set { name } else { '-' };
+        write!(f, "{}{}{}{}", flag(self.zero, 'Z'), flag(self.negative, 'N'), flag(self.carry, 'C'), flag(self.overflow, 'V'))
+    }
+}
+
+// The status code a program exits with
+pub type ExitCode = u8;
+
+/// How a program stopped running without faulting
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExitStatus {
+    /// The program counter moved past the final instruction
+    Finished,
+    /// The VM executed a `HALT` with this status code
+    Halted(ExitCode),
+    /// The VM executed a `DIE` with this status code and message
+    Aborted {
+        code: ExitCode,
+        message: String,
+    },
+}
+
+impl ExitStatus {
+    /// The code to exit the process with. Finishing is a success (0).
+    /// An abort always fails, so an abort with code 0 exits with 1.
+    pub fn code(&self) -> i32 {
+        match self {
+            ExitStatus::Finished => 0,
+            ExitStatus::Halted(code) => *code as i32,
+            ExitStatus::Aborted { code: 0, .. } => 1,
+            ExitStatus::Aborted { code, .. } => *code as i32,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.code() == 0
+    }
+}
+
+impl Display for ExitStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitStatus::Finished => write!(f, "finished"),
+            ExitStatus::Halted(code) => write!(f, "halted with status {code}"),
+            ExitStatus::Aborted { code, message } => write!(f, "aborted with status {code}: {message}"),
+        }
+    }
+}
+
+/// How a metered or async run handed control back to the caller
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RunOutcome {
+    /// The program stopped, by finishing, halting or aborting
+    Exited(ExitStatus),
+    /// There was not enough gas left for the next instruction, which has not been executed.
+    /// Running again continues from the same program counter.
+    BudgetExhausted,
+    /// The CancellationToken of an async run was cancelled. Running again continues from the same program counter.
+    Cancelled,
+}
+
+/// Errors raised while decoding or executing a single instruction
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ExecutionError {
+    /// The opcode does not belong to any known instruction
+    IllegalOpcode(Byte),
+    /// The program ended before all of the instruction's operands could be read
+    TruncatedInstruction,
+    /// An operand referenced a register that does not exist
+    RegisterOutOfBounds(RegisterId),
+    /// The right term of a division was zero
+    DivideByZero,
+    /// An instruction moved the program counter outside of the program
+    ProgramCounterOutOfBounds(ProgramIndex),
+    /// The result of an arithmetic operation does not fit in a register
+    ArithmeticOverflow,
+    /// A load or store touched an address outside of the VM's memory
+    MemoryOutOfBounds(MemoryAddress),
+    /// A push or call would grow the stack beyond its maximum depth
+    StackOverflow,
+    /// A pop or return was attempted on an empty stack
+    StackUnderflow,
+    /// No host function is registered for the `SYSCALL` number
+    UnknownSyscall(SyscallId),
+    /// A host function called by `SYSCALL` failed
+    Host(String),
+    /// Reading from or writing to the console failed, or the input was not what the instruction expected
+    Io(String),
+    /// A `RETT` was executed outside of a trap handler
+    NotInTrapHandler,
+    /// A `JOIN` named a thread that was never spawned, the thread executing it,
+    /// or a thread that is waiting for the thread executing it, directly or through other threads
+    InvalidJoin(ThreadId),
+    /// A replay asked for a value the recording did not provide. Trap handlers never receive this
+    ReplayDiverged(String),
+    /// The opcode belongs to an InstructionGroup the `VmConfig` disabled
+    DisabledInstruction(Byte),
+    /// The constant pool of the loaded Program has no entry at the index
+    UnknownConstant(usize),
     Unknown(String),
 }
 
+impl ExecutionError {
+    /// False for errors that stop the VM from outside the program, which a trap handler must not swallow
+    pub fn is_trappable(&self) -> bool {
+        !matches!(self, ExecutionError::ReplayDiverged(_))
+    }
+
+    /// The FaultCode a trap handler receives for this error. 0 is never used, so `TRAP #0` can catch every fault.
+    pub fn code(&self) -> FaultCode {
+        match self {
+            ExecutionError::IllegalOpcode(_) => 1,
+            ExecutionError::TruncatedInstruction => 2,
+            ExecutionError::RegisterOutOfBounds(_) => 3,
+            ExecutionError::DivideByZero => 4,
+            ExecutionError::ProgramCounterOutOfBounds(_) => 5,
+            ExecutionError::ArithmeticOverflow => 6,
+            ExecutionError::MemoryOutOfBounds(_) => 7,
+            ExecutionError::StackOverflow => 8,
+            ExecutionError::StackUnderflow => 9,
+            ExecutionError::UnknownSyscall(_) => 10,
+            ExecutionError::Host(_) => 11,
+            ExecutionError::Io(_) => 12,
+            ExecutionError::NotInTrapHandler => 13,
+            ExecutionError::InvalidJoin(_) => 14,
+            ExecutionError::ReplayDiverged(_) => 15,
+            ExecutionError::DisabledInstruction(_) => 17,
+            ExecutionError::UnknownConstant(_) => 18,
+            ExecutionError::Unknown(_) => 255,
+        }
+    }
+}
+
+impl Display for ExecutionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionError::IllegalOpcode(opcode) => write!(f, "illegal opcode {opcode}"),
+            ExecutionError::TruncatedInstruction => write!(f, "truncated instruction"),
+            ExecutionError::RegisterOutOfBounds(register) => write!(f, "register {register} is out of bounds"),
+            ExecutionError::DivideByZero => write!(f, "divide by zero"),
+            ExecutionError::ProgramCounterOutOfBounds(index) => write!(f, "program index {index} is out of bounds"),
+            ExecutionError::ArithmeticOverflow => write!(f, "arithmetic overflow"),
+            ExecutionError::MemoryOutOfBounds(address) => write!(f, "memory address {address} is out of bounds"),
+            ExecutionError::StackOverflow => write!(f, "stack overflow"),
+            ExecutionError::StackUnderflow => write!(f, "stack underflow"),
+            ExecutionError::UnknownSyscall(id) => write!(f, "no host function for syscall {id}"),
+            ExecutionError::Host(message) => write!(f, "host function failed: {message}"),
+            ExecutionError::Io(message) => write!(f, "console error: {message}"),
+            ExecutionError::NotInTrapHandler => write!(f, "return from trap outside of a trap handler"),
+            ExecutionError::InvalidJoin(thread) => write!(f, "cannot join thread {thread}"),
+            ExecutionError::ReplayDiverged(message) => write!(f, "replay diverged: {message}"),
+            ExecutionError::DisabledInstruction(opcode) => write!(f, "opcode {opcode} is disabled"),
+            ExecutionError::UnknownConstant(index) => write!(f, "no constant at index {index}"),
+            ExecutionError::Unknown(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl From<InstructionDecodeError> for ExecutionError {
+    fn from(error: InstructionDecodeError) -> Self {
+        match error {
+            InstructionDecodeError::IllegalOpcode(opcode) => ExecutionError::IllegalOpcode(opcode),
+            InstructionDecodeError::TruncatedInstruction => ExecutionError::TruncatedInstruction,
+            InstructionDecodeError::InvalidValueSize => ExecutionError::Unknown(String::from("invalid operand value size")),
+        }
+    }
+}
+
+impl From<std::io::Error> for ExecutionError {
+    fn from(error: std::io::Error) -> Self {
+        ExecutionError::Io(error.to_string())
+    }
+}
+
+/// A fault that stopped the machine, along with where it happened
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RuntimeError {
+    /// What went wrong
+    pub error: ExecutionError,
+    /// The ProgramIndex of the faulting instruction's opcode
+    pub program_index: ProgramIndex,
+    /// The human-readable form of the faulting instruction, if it could be decoded
+    pub instruction: Option<String>,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.instruction {
+            Some(instruction) => write!(f, "{} at program index {} ({instruction})", self.error, self.program_index),
+            None => write!(f, "{} at program index {}", self.error, self.program_index),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
 pub struct Kaylee {
-    registers: [RegisterValue; Kaylee::REGISTER_COUNT],
+    registers: Vec<RegisterValue>,
+    float_registers: [FloatRegisterValue; Kaylee::FLOAT_REGISTER_COUNT],
     program_counter: RegisterId,
     remainder: u32,
-    halted: bool,
+    flags: Flags,
+    exit_status: Option<ExitStatus>,
+    memory: Vec<Byte>,
+    constants: Vec<Constant>,
+    stack: Vec<RegisterValue>,
+    max_stack_depth: usize,
+    instruction_budget: Option<u64>,
+    instructions_executed: u64,
+    groups: InstructionGroups,
+    observers: Vec<Box<dyn ExecutionObserver>>,
+    syscalls: HashMap<SyscallId, HostFunction>,
+    traps: BTreeMap<FaultCode, ProgramIndex>,
+    trap_return: Option<ProgramIndex>,
+    scheduler: Scheduler,
+    tracer: Option<Tracer>,
+    undo: Option<UndoLog>,
+    /// Every register write since the last `take_register_writes`, while a Debugger watches for them
+    register_writes: Option<Vec<RegisterWrite>>,
+    pause: PauseHandle,
+    input: Box<dyn BufRead + Send>,
+    output: Box<dyn Write + Send>,
+}
+
+impl Default for Kaylee {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Kaylee {
+    /// The number of registers a VM has unless its `VmConfig` says otherwise
     pub const REGISTER_COUNT: usize = 32;
+    pub const FLOAT_REGISTER_COUNT: usize = 32;
+    pub const DEFAULT_MEMORY_SIZE: usize = 64 * 1024;
+    pub const DEFAULT_STACK_DEPTH: usize = 1024;
 
     pub fn new() -> Self {
         Kaylee {
-            registers: [0; Kaylee::REGISTER_COUNT],
+            registers: vec![0; Kaylee::REGISTER_COUNT],
+            float_registers: [0.0; Kaylee::FLOAT_REGISTER_COUNT],
             remainder: 0,
+            flags: Flags::default(),
             program_counter: 0,
-            halted: false,
+            exit_status: None,
+            memory: vec![0; Kaylee::DEFAULT_MEMORY_SIZE],
+            constants: Vec::new(),
+            stack: Vec::new(),
+            max_stack_depth: Kaylee::DEFAULT_STACK_DEPTH,
+            instruction_budget: None,
+            instructions_executed: 0,
+            groups: InstructionGroups::all(),
+            observers: Vec::new(),
+            syscalls: HashMap::new(),
+            traps: BTreeMap::new(),
+            trap_return: None,
+            scheduler: Scheduler::default(),
+            tracer: None,
+            undo: None,
+            register_writes: None,
+            pause: PauseHandle::default(),
+            input: Box::new(BufReader::new(std::io::stdin())),
+            output: Box::new(std::io::stdout()),
+        }
+    }
+
+    /// Build a VM with the registers, memory and limits of a `VmConfig`
+    pub fn from_config(config: VmConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+
+        let mut vm = Kaylee::new()
+            .with_memory_size(config.memory_size)
+            .with_max_stack_depth(config.max_stack_depth);
+
+        vm.registers = vec![0; config.register_count];
+        vm.instruction_budget = config.instruction_budget;
+        vm.groups = config.groups;
+
+        Ok(vm)
+    }
+
+    /// The configuration this VM was built with, or would be built with
+    pub fn config(&self) -> VmConfig {
+        VmConfig {
+            register_count: self.registers.len(),
+            memory_size: self.memory.len(),
+            max_stack_depth: self.max_stack_depth,
+            instruction_budget: self.instruction_budget,
+            groups: self.groups,
+        }
+    }
+
+    pub fn register_count(&self) -> usize {
+        self.registers.len()
+    }
+
+    /// A trap handler receives the FaultCode in the second to last register, `$30` unless the `VmConfig` says otherwise
+    pub fn fault_code_register(&self) -> RegisterId {
+        self.registers.len() - 2
+    }
+
+    /// A trap handler receives the ProgramIndex of the faulting instruction in the last register, `$31` unless the `VmConfig` says otherwise
+    pub fn fault_index_register(&self) -> RegisterId {
+        self.registers.len() - 1
+    }
+
+    /// The number of instructions this VM has started executing, across every run and thread
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    /// Replaces the VM's linear memory with a zeroed region of `size` bytes
+    pub fn with_memory_size(mut self, size: usize) -> Self {
+        self.memory = vec![0; size];
+        self
+    }
+
+    /// Switch threads after every `instructions` instructions, even if the running thread never yields.
+    /// Without a time slice, threads only switch on `YIELD`, `JOIN` or when a thread ends.
+    pub fn with_time_slice(mut self, instructions: u64) -> Self {
+        self.scheduler.time_slice = Some(instructions.max(1));
+        self
+    }
+
+    /// Record every non-deterministic value the VM consumes while running `program`. Take the Trace with `finish_trace`
+    pub fn with_recording(mut self, program: &Program) -> Self {
+        self.tracer = Some(Tracer::Recording(Trace::new(program, &self.config())));
+        self
+    }
+
+    /// Take console input, the clock, random numbers and host function results from a recorded Trace instead.
+    /// Host functions must still be registered, but they are not called.
+    /// Fails if the trace was recorded from a different program, or on a VM with a different configuration.
+    pub fn with_replay(mut self, trace: Trace, program: &Program) -> Result<Self, TraceError> {
+        if !trace.matches(program) {
+            return Err(TraceError::ProgramMismatch);
+        }
+
+        if !trace.matches_config(&self.config()) {
+            return Err(TraceError::ConfigMismatch);
+        }
+
+        self.tracer = Some(Tracer::Replaying { trace, position: 0 });
+        Ok(self)
+    }
+
+    /// Stop recording or replaying. Returns the recorded Trace, or the replayed one once every event was consumed
+    pub fn finish_trace(&mut self) -> Result<Trace, TraceError> {
+        match self.tracer.take() {
+            None => Err(TraceError::NotTracing),
+            Some(Tracer::Recording(trace)) => Ok(trace),
+            Some(Tracer::Replaying { trace, position }) => match trace.events.len() - position {
+                0 => Ok(trace),
+                remaining => Err(TraceError::Incomplete { remaining }),
+            },
         }
     }
 
+    /// Keep an undo log of the last `capacity` instructions, so `step_back` can reverse them
+    pub fn with_undo_log(mut self, capacity: usize) -> Self {
+        self.undo = Some(UndoLog::new(capacity));
+        self
+    }
+
+    /// Replaces the console input stream, which defaults to stdin
+    pub fn with_input<R: BufRead + Send + 'static>(mut self, input: R) -> Self {
+        self.input = Box::new(input);
+        self
+    }
+
+    /// Replaces the console output stream, which defaults to stdout
+    pub fn with_output<W: Write + Send + 'static>(mut self, output: W) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// The console input stream, read by console instructions and the REPL
+    pub fn input(&mut self) -> &mut dyn BufRead {
+        self.input.as_mut()
+    }
+
+    /// The console output stream, written by console instructions and the REPL
+    pub fn output(&mut self) -> &mut dyn Write {
+        self.output.as_mut()
+    }
+
+    /// Prepare to run a Program: copy its data section into the start of memory, take its constant pool
+    /// and jump to its entry point
+    pub fn load(&mut self, program: &Program) -> Result<(), ExecutionError> {
+        self.load_appended(program, 0)?;
+        self.program_counter = program.entry_point();
+        Ok(())
+    }
+
+    /// Copy the data a Program gained after `data_start` (see `Program::append`) into memory and take its
+    /// constant pool, leaving the program counter and the rest of memory alone
+    pub(crate) fn load_appended(&mut self, program: &Program, data_start: usize) -> Result<(), ExecutionError> {
+        self.write_memory(data_start, &program.data()[data_start..])?;
+        self.constants = program.constants().to_vec();
+        Ok(())
+    }
+
+    /// `load` a Program, then `run` it from its entry point
+    pub fn load_and_run(&mut self, program: Program) -> Result<ExitStatus, RuntimeError> {
+        self.load(&program).map_err(|error| RuntimeError { error, program_index: program.entry_point(), instruction: None })?;
+        self.run(program)
+    }
+
+    /// Decode and load a `.kbc` executable, refusing one built against a different opcode table.
+    /// Returns the Program to run.
+    pub fn load_kbc(&mut self, bytes: &[u8]) -> Result<Program, KbcError> {
+        let program = Program::from_kbc(bytes)?;
+        self.load(&program).map_err(KbcError::Load)?;
+        Ok(program)
+    }
+
     /// This will run until one of the following conditions is met
-    /// 1. The Program reaches completes its final instruction
-    /// 2. The VM `halt` flag is set, which will complete the current instruction and then halt
-    pub fn run(&mut self, program: Program) {
-        while let Some(result) = decode_next_instruction(&program, &mut self.program_counter) {
+    /// 1. The Program reaches completes its final instruction, returning `ExitStatus::Finished`
+    /// 2. A `HALT` or `DIE` completes, returning its `ExitStatus`
+    /// 3. An instruction faults, which stops the machine and returns the `RuntimeError`
+    ///
+    /// Running continues from the VM's program counter, memory and constant pool, so a run can be resumed.
+    /// A Program with a data section, constant pool or entry point has to be `load`ed first, or run with `load_and_run`.
+    pub fn run(&mut self, program: Program) -> Result<ExitStatus, RuntimeError> {
+        while self.step(&program)?.is_some() {
+            // @todo: graceful shutdown of the machine/process
+            if let Some(status) = &self.exit_status {
+                return Ok(status.clone());
+            }
+        }
+
+        Ok(ExitStatus::Finished)
+    }
+
+    /// Runs like `run`, but executes a Program that has already been decoded.
+    /// Decode a Program once with `DecodedProgram::from` to run it many times without decoding it again.
+    pub fn run_decoded(&mut self, program: &DecodedProgram) -> Result<ExitStatus, RuntimeError> {
+        while self.step_decoded(program)?.is_some() {
+            if let Some(status) = &self.exit_status {
+                return Ok(status.clone());
+            }
+        }
+
+        Ok(ExitStatus::Finished)
+    }
+
+    /// Runs like `run`, but skips decoding and the per-step opcode, operand and instruction group checks,
+    /// which the verifier already made. Takes the checked path whenever a register-held jump target lands
+    /// between instructions, and for every step on a VM the program was not verified for.
+    pub fn run_verified(&mut self, program: &VerifiedProgram) -> Result<ExitStatus, RuntimeError> {
+        if !program.runs_on(&self.config()) {
+            return self.run(program.program().clone());
+        }
+
+        while self.step_verified(program)?.is_some() {
+            if let Some(status) = &self.exit_status {
+                return Ok(status.clone());
+            }
+        }
+
+        Ok(ExitStatus::Finished)
+    }
+
+    /// Runs like `run`, but charges each instruction against a gas budget before executing it.
+    /// Stops with `RunOutcome::BudgetExhausted` when the next instruction costs more than the gas that remains,
+    /// or when the VM has used up the instruction budget of its `VmConfig`.
+    pub fn run_metered(&mut self, program: &Program, gas: &mut Gas) -> Result<RunOutcome, RuntimeError> {
+        while self.exit_status.is_none() {
+            // Past the end there is nothing to charge for, and a spawned thread that gets there hands over to the next
+            if let Some(opcode) = program.bytes().get(self.program_counter) {
+                if self.budget_exhausted() || !gas.consume(*opcode) {
+                    return Ok(RunOutcome::BudgetExhausted);
+                }
+            }
+
+            if self.step(program)?.is_none() {
+                return Ok(RunOutcome::Exited(ExitStatus::Finished));
+            }
+        }
+
+        Ok(RunOutcome::Exited(self.exit_status.clone().unwrap_or(ExitStatus::Finished)))
+    }
+
+    /// Runs like `run`, as a future that gives its executor a turn every `yield_every` instructions.
+    /// Stops with `RunOutcome::Cancelled` between instructions once `token` is cancelled,
+    /// and waits between instructions while the VM's `pause_handle` is paused.
+    /// Stops with `RunOutcome::BudgetExhausted` when the VM has used up the instruction budget of its `VmConfig`.
+    pub async fn run_async(&mut self, program: &Program, yield_every: u64, token: &CancellationToken) -> Result<RunOutcome, RuntimeError> {
+        let pause = self.pause.clone();
+        let mut executed: u64 = 0;
+
+        loop {
+            if pause.is_paused() {
+                WhilePaused::new(&pause, token).await;
+            }
+
+            if token.is_cancelled() {
+                return Ok(RunOutcome::Cancelled);
+            }
+
+            if self.program_counter < program.len() && self.budget_exhausted() {
+                return Ok(RunOutcome::BudgetExhausted);
+            }
+
+            if self.step(program)?.is_none() {
+                return Ok(RunOutcome::Exited(ExitStatus::Finished));
+            }
+
+            if let Some(status) = &self.exit_status {
+                return Ok(RunOutcome::Exited(status.clone()));
+            }
+
+            executed += 1;
+            if executed.is_multiple_of(yield_every.max(1)) {
+                YieldNow::default().await;
+            }
+        }
+    }
+
+    /// A handle that pauses and resumes `run_async` from another task or thread
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    pub fn run_next(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        if self.step(program)?.is_none() {
+            // The program has already finished, so a console that cannot be written to is not worth faulting over
+            let _ = writeln!(self.output, "Execution Finished");
+        }
+
+        Ok(())
+    }
+
+    /// Decodes and executes the instruction at the program counter, transferring control to a trap handler if it faults.
+    /// Returns `None` once the program counter has moved past the end of the Program
+    pub(crate) fn step(&mut self, program: &Program) -> Result<Option<ExecutionResult>, RuntimeError> {
+        self.begin_undo();
+        let result = self.execute_step(program).or_else(|error| self.trap(error)).map(|result| self.schedule(result));
+        self.finish_undo(&result);
+        result
+    }
+
+    fn execute_step(&mut self, program: &Program) -> Result<Option<ExecutionResult>, RuntimeError> {
+        let program_index = self.program_counter;
+
+        let instruction = match decode_next_instruction(program, &mut self.program_counter) {
+            None => return Ok(None),
+            Some(Ok(instruction)) => instruction,
+            Some(Err(error)) => {
+                return Err(RuntimeError { error: error.into(), program_index, instruction: None });
+            }
+        };
+
+        let fault = |error: ExecutionError| RuntimeError {
+            error,
+            program_index,
+            instruction: Some(instruction.display()),
+        };
+
+        if let Err(error) = self.admit(program[program_index]) {
+            // Leave the program counter on the instruction that did not run
+            self.program_counter = program_index;
+            return Err(fault(error));
+        }
+
+        let result = self.execute_instruction(program_index, instruction.as_ref()).map_err(fault)?;
+
+        if let ExecutionResult::Jumped(index) = result {
+            if index > program.len() {
+                return Err(fault(ExecutionError::ProgramCounterOutOfBounds(index)));
+            }
+        }
+
+        Ok(Some(result))
+    }
+
+    /// Executes the pre-decoded instruction at the program counter, exactly as `step` would.
+    fn step_decoded(&mut self, program: &DecodedProgram) -> Result<Option<ExecutionResult>, RuntimeError> {
+        self.begin_undo();
+        let result = self.execute_step_decoded(program).or_else(|error| self.trap(error)).map(|result| self.schedule(result));
+        self.finish_undo(&result);
+        result
+    }
+
+    /// Executes the verified instruction at the program counter, exactly as `step` would.
+    fn step_verified(&mut self, program: &VerifiedProgram) -> Result<Option<ExecutionResult>, RuntimeError> {
+        self.begin_undo();
+        let result = self.execute_step_verified(program).or_else(|error| self.trap(error)).map(|result| self.schedule(result));
+        self.finish_undo(&result);
+        result
+    }
+
+    /// Start an UndoRecord for the instruction about to run, if the VM keeps an undo log
+    fn begin_undo(&mut self) {
+        let Some(undo) = &mut self.undo else {
+            return;
+        };
+
+        undo.current = Some(UndoRecord {
+            float_registers: self.float_registers,
+            program_counter: self.program_counter,
+            remainder: self.remainder,
+            flags: self.flags,
+            exit_status: self.exit_status.clone(),
+            trap_return: self.trap_return,
+            instructions_executed: self.instructions_executed,
+            turn: (self.scheduler.executed, self.scheduler.yield_requested),
+            scheduler: Vec::new(),
+            register_writes: Vec::new(),
+            memory: Vec::new(),
+            stack: Vec::new(),
+            traps: Vec::new(),
+            trace_position: match &self.tracer {
+                Some(Tracer::Recording(trace)) => trace.events.len(),
+                Some(Tracer::Replaying { position, .. }) => *position,
+                None => 0,
+            },
+        });
+    }
+
+    /// Keep the UndoRecord unless the step found nothing to execute
+    fn finish_undo(&mut self, result: &Result<Option<ExecutionResult>, RuntimeError>) {
+        if let Some(undo) = &mut self.undo {
             match result {
-                Ok(instruction) => { self.execute_instruction(instruction) }
-                Err(_error) => { panic!("Error decoding instruction") }
+                Ok(None) => undo.current = None,
+                _ => undo.commit(),
+            }
+        }
+    }
+
+    /// Undo the most recent instruction. Returns false if the undo log is empty or the VM does not keep one
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self.undo.as_mut().and_then(|undo| undo.records.pop_back()) else {
+            return false;
+        };
+
+        // Bring back the thread that executed the instruction first, so its own changes are undone below
+        for change in record.scheduler.into_iter().rev() {
+            match change {
+                SchedulerChange::Spawned => {
+                    self.scheduler.ready.pop_back();
+                    self.scheduler.next_id -= 1;
+                }
+                SchedulerChange::Rotated(index) => {
+                    if let Some(mut previous) = self.scheduler.ready.pop_back() {
+                        self.swap_context(&mut previous);
+                        self.scheduler.ready.insert(index, previous);
+                    }
+                }
+                SchedulerChange::Retired(mut ended, index) => {
+                    self.swap_context(&mut ended);
+                    self.scheduler.ready.insert(index, *ended);
+                    self.scheduler.finished.remove(&self.scheduler.current);
+                }
+                SchedulerChange::Blocked(thread, previous) => {
+                    match previous {
+                        Some(target) => self.scheduler.blocked.insert(thread, target),
+                        None => self.scheduler.blocked.remove(&thread),
+                    };
+                }
             }
+        }
 
-            if self.halted {
-                break;
-                // @todo: graceful shutdown of the machine/process
+        for (address, old) in record.memory.iter().rev() {
+            self.memory[*address..*address + old.len()].copy_from_slice(old);
+        }
+
+        for change in record.stack.into_iter().rev() {
+            match change {
+                StackChange::Pushed => {
+                    self.stack.pop();
+                }
+                StackChange::Popped(value) => self.stack.push(value),
             }
         }
+
+        for write in record.register_writes.iter().rev() {
+            self.registers[write.register] = write.old;
+        }
+
+        for (code, old) in record.traps.into_iter().rev() {
+            match old {
+                Some(handler) => self.traps.insert(code, handler),
+                None => self.traps.remove(&code),
+            };
+        }
+
+        // The instruction is run again after stepping back, so it records or replays its events again
+        match &mut self.tracer {
+            Some(Tracer::Recording(trace)) => trace.events.truncate(record.trace_position),
+            Some(Tracer::Replaying { position, .. }) => *position = record.trace_position,
+            None => {}
+        }
+
+        self.float_registers = record.float_registers;
+        self.program_counter = record.program_counter;
+        self.remainder = record.remainder;
+        self.flags = record.flags;
+        self.exit_status = record.exit_status;
+        self.trap_return = record.trap_return;
+        self.instructions_executed = record.instructions_executed;
+        (self.scheduler.executed, self.scheduler.yield_requested) = record.turn;
+
+        true
+    }
+
+    /// How many instructions `step_back` can undo
+    pub fn undo_depth(&self) -> usize {
+        self.undo.as_ref().map_or(0, |undo| undo.records.len())
     }
 
-    pub fn run_next(&mut self, program: &Program) {
-        match decode_next_instruction(program, &mut self.program_counter) {
-            Some(Ok(instruction)) => self.execute_instruction(instruction),
-            None => println!("Execution Finished"),
-            Some(Err(_error)) => panic!("received an error"),
+    fn execute_step_decoded(&mut self, program: &DecodedProgram) -> Result<Option<ExecutionResult>, RuntimeError> {
+        let program_index = self.program_counter;
+
+        let decoded = match program.instruction(program_index) {
+            None => return Ok(None),
+            Some(Ok(decoded)) => *decoded,
+            Some(Err(error)) => {
+                return Err(RuntimeError { error: error.clone().into(), program_index, instruction: None });
+            }
         };
+
+        self.admit(program.program()[program_index]).map_err(|error| RuntimeError {
+            error,
+            program_index,
+            instruction: Some(decoded.operation.display(decoded.operand_values)),
+        })?;
+
+        // Every instruction is four bytes: an opcode and three operand bytes
+        self.program_counter = program_index + 4;
+        let result = decoded.operation.execute(decoded.operand_values, program_index, self)?;
+
+        if let ExecutionResult::Jumped(index) = result {
+            if index > program.len() {
+                return Err(RuntimeError {
+                    error: ExecutionError::ProgramCounterOutOfBounds(index),
+                    program_index,
+                    instruction: Some(decoded.operation.display(decoded.operand_values)),
+                });
+            }
+        }
+
+        Ok(Some(result))
     }
 
-    fn execute_instruction(&mut self, instruction: Box<dyn Instruction>) {
-        instruction.execute(self).unwrap();
+    fn execute_step_verified(&mut self, program: &VerifiedProgram) -> Result<Option<ExecutionResult>, RuntimeError> {
+        let program_index = self.program_counter;
+
+        let Some(decoded) = program.instruction(program_index) else {
+            // Past the end, or somewhere a register-held jump target landed
+            return self.execute_step(program.program());
+        };
+
+        let fault = |error| RuntimeError {
+            error,
+            program_index,
+            instruction: Some(decoded.operation.display(decoded.operand_values)),
+        };
+
+        self.instructions_executed += 1;
+        self.program_counter = program_index + 4;
+        let result = decoded.operation.execute(decoded.operand_values, program_index, self)?;
 
-        // I should probably do something with these results, or pass them back
-        // match instruction.execute(self) {
-        //     Ok(ExecutionResult::Value(value)) => println!("{value}"),
-        //     Ok(ExecutionResult::Halted) => println!("Halting"),
-        //     Ok(ExecutionResult::Jumped(index)) => println!("Jumped to {index}"),
-        //     Ok(ExecutionResult::Equality(flag)) => println!("Jumped to {flag}"),
-        //     Ok(ExecutionResult::NoAction) => println!("No Action"),
-        //     Err(_) => panic!("Error")
-        // }
+        // Constant targets were verified, but targets held in registers were not
+        if let ExecutionResult::Jumped(index) = result {
+            if index > program.program().len() {
+                return Err(fault(ExecutionError::ProgramCounterOutOfBounds(index)));
+            }
+        }
+
+        Ok(Some(result))
     }
 
-    pub(crate) fn register(&self, register: RegisterId) -> Result<RegisterValue, ()> {
-        if register > Kaylee::REGISTER_COUNT - 1 {
-            return Err(());
+    /// Count an instruction, if it may run at all
+    fn admit(&mut self, opcode: Byte) -> Result<(), ExecutionError> {
+        if !self.groups.allows(opcode) {
+            return Err(ExecutionError::DisabledInstruction(opcode));
         }
 
-        Ok(*&self.registers[register].clone())
+        self.instructions_executed += 1;
+        Ok(())
     }
 
-    pub(crate) fn all_registers(&self) -> [RegisterValue; Kaylee::REGISTER_COUNT] {
-        *&self.registers.clone()
+    /// True once the VM has executed every instruction its `VmConfig` budget allows
+    fn budget_exhausted(&self) -> bool {
+        self.instruction_budget.is_some_and(|budget| self.instructions_executed >= budget)
     }
 
-    pub(crate) fn set_register(&mut self, register: RegisterId, value: RegisterValue) -> Result<(), ()> {
-        if register > Kaylee::REGISTER_COUNT - 1 {
-            return Err(());
+    /// Switch threads after a step if the running thread ended, yielded or used up its time slice.
+    /// A spawned thread ends when it halts or runs past the end of the program, without stopping the VM.
+    fn schedule(&mut self, result: Option<ExecutionResult>) -> Option<ExecutionResult> {
+        if self.scheduler.is_idle() {
+            return result;
+        }
+
+        let stopped = result.is_none() || self.exit_status.is_some();
+        if stopped && self.scheduler.current != MAIN_THREAD {
+            self.exit_status = None;
+            self.scheduler.finished.insert(self.scheduler.current, self.registers[0]);
+
+            // The main thread is always alive while spawned threads run, and threads never all wait for each other,
+            // so there is always a next thread
+            let index = self.scheduler.next_runnable().unwrap_or(0);
+            if let Some(mut next) = self.scheduler.ready.remove(index) {
+                self.swap_context(&mut next);
+
+                // `next` now holds the thread that ended
+                if let Some(undo) = &mut self.undo {
+                    undo.record_scheduler(SchedulerChange::Retired(Box::new(next), index));
+                }
+            }
+
+            return Some(result.unwrap_or(ExecutionResult::NoAction));
+        }
+
+        if stopped {
+            return result;
+        }
+
+        self.scheduler.executed += 1;
+        let preempted = self.scheduler.time_slice.is_some_and(|slice| self.scheduler.executed >= slice);
+
+        if self.scheduler.yield_requested || preempted {
+            self.scheduler.yield_requested = false;
+            self.scheduler.executed = 0;
+
+            // Threads waiting at a `JOIN` are passed over, and if they are all waiting, the running thread keeps going
+            if let Some(index) = self.scheduler.next_runnable() {
+                if let Some(mut next) = self.scheduler.ready.remove(index) {
+                    self.swap_context(&mut next);
+                    self.scheduler.ready.push_back(next);
+
+                    if let Some(undo) = &mut self.undo {
+                        undo.record_scheduler(SchedulerChange::Rotated(index));
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Put a waiting thread's state into the VM, leaving the running thread's state in `context`
+    fn swap_context(&mut self, context: &mut Context) {
+        std::mem::swap(&mut self.scheduler.current, &mut context.id);
+        std::mem::swap(&mut self.registers, &mut context.registers);
+        std::mem::swap(&mut self.float_registers, &mut context.float_registers);
+        std::mem::swap(&mut self.program_counter, &mut context.program_counter);
+        std::mem::swap(&mut self.remainder, &mut context.remainder);
+        std::mem::swap(&mut self.flags, &mut context.flags);
+        std::mem::swap(&mut self.stack, &mut context.stack);
+        std::mem::swap(&mut self.trap_return, &mut context.trap_return);
+    }
+
+    /// Start a thread at `start` with a copy of the running thread's registers. It first runs after the running thread yields.
+    pub(crate) fn spawn(&mut self, start: ProgramIndex) -> ThreadId {
+        let id = self.scheduler.allocate_id();
+
+        self.scheduler.ready.push_back(Context {
+            id,
+            registers: self.registers.clone(),
+            float_registers: self.float_registers,
+            program_counter: start,
+            remainder: 0,
+            flags: Flags::default(),
+            stack: Vec::new(),
+            trap_return: None,
+        });
+
+        if let Some(undo) = &mut self.undo {
+            undo.record_scheduler(SchedulerChange::Spawned);
+        }
+
+        id
+    }
+
+    /// Give up the rest of the running thread's turn once the current instruction completes
+    pub(crate) fn request_yield(&mut self) {
+        self.scheduler.yield_requested = true;
+    }
+
+    /// Keep the running thread from being scheduled until thread `id` ends.
+    /// Fails with `InvalidJoin` if `id` is waiting for the running thread, directly or through other threads.
+    pub(crate) fn wait_for(&mut self, id: ThreadId) -> Result<(), ExecutionError> {
+        if self.scheduler.would_deadlock(id) {
+            return Err(ExecutionError::InvalidJoin(id));
+        }
+
+        self.set_blocked(Some(id));
+        Ok(())
+    }
+
+    /// The running thread's `JOIN` has finished, so it is no longer waiting for anything
+    pub(crate) fn stop_waiting(&mut self) {
+        self.set_blocked(None);
+    }
+
+    fn set_blocked(&mut self, target: Option<ThreadId>) {
+        let thread = self.scheduler.current;
+        let previous = match target {
+            Some(target) => self.scheduler.blocked.insert(thread, target),
+            None => self.scheduler.blocked.remove(&thread),
+        };
+
+        if previous != target {
+            if let Some(undo) = &mut self.undo {
+                undo.record_scheduler(SchedulerChange::Blocked(thread, previous));
+            }
+        }
+    }
+
+    /// The `$0` of a thread that has ended, or `None` if it is still alive
+    pub(crate) fn thread_result(&self, id: ThreadId) -> Result<Option<RegisterValue>, ExecutionError> {
+        if let Some(value) = self.scheduler.finished.get(&id) {
+            return Ok(Some(*value));
+        }
+
+        if id == self.scheduler.current || !self.scheduler.is_alive(id) {
+            return Err(ExecutionError::InvalidJoin(id));
+        }
+
+        Ok(None)
+    }
+
+    /// The thread whose state is in the VM
+    pub fn current_thread(&self) -> ThreadId {
+        self.scheduler.current
+    }
+
+    /// The number of threads that have not ended, including the running one
+    pub fn thread_count(&self) -> usize {
+        self.scheduler.ready.len() + 1
+    }
+
+    /// Transfer control to the handler installed for a fault, or for every fault (code 0).
+    /// The fault stops the machine if there is no handler, or if it happened inside a handler (a double fault).
+    fn trap(&mut self, error: RuntimeError) -> Result<Option<ExecutionResult>, RuntimeError> {
+        if self.trap_return.is_some() || !error.error.is_trappable() {
+            return Err(error);
+        }
+
+        let code = error.error.code();
+        let handler = match self.traps.get(&code).or_else(|| self.traps.get(&0)) {
+            Some(handler) => *handler,
+            None => return Err(error),
+        };
+
+        // Both fault registers are counted back from the end of the register file, so these always succeed
+        let _ = self.set_register(self.fault_code_register(), code as RegisterValue);
+        let _ = self.set_register(self.fault_index_register(), error.program_index as RegisterValue);
+
+        // Returning from the handler skips the faulting instruction
+        self.trap_return = Some(error.program_index + 4);
+        self.program_counter = handler;
+
+        Ok(Some(ExecutionResult::Jumped(handler)))
+    }
+
+    pub(crate) fn execute_instruction(&mut self, program_index: ProgramIndex, instruction: &dyn Instruction) -> Result<ExecutionResult, ExecutionError> {
+        if self.observers.is_empty() {
+            return instruction.execute(self);
+        }
+
+        // Observers are taken out while the instruction runs so it can borrow the VM mutably
+        let mut observers = std::mem::take(&mut self.observers);
+
+        for observer in observers.iter_mut() {
+            observer.before_instruction(program_index, instruction);
+        }
+
+        let result = instruction.execute(self);
+
+        for observer in observers.iter_mut() {
+            observer.after_instruction(program_index, instruction, &result);
+        }
+
+        self.observers = observers;
+        result
+    }
+
+    /// Capture the complete state of the VM, along with the Program it is running.
+    /// Observers are not part of the state and are not captured.
+    pub fn snapshot(&self, program: &Program) -> Snapshot {
+        Snapshot {
+            registers: self.registers.clone(),
+            float_registers: self.float_registers.to_vec(),
+            program_counter: self.program_counter,
+            remainder: self.remainder,
+            flags: self.flags,
+            exit_status: self.exit_status.clone(),
+            traps: self.traps.clone(),
+            trap_return: self.trap_return,
+            scheduler: self.scheduler.clone(),
+            max_stack_depth: self.max_stack_depth,
+            instruction_budget: self.instruction_budget,
+            instructions_executed: self.instructions_executed,
+            groups: self.groups,
+            stack: self.stack.clone(),
+            memory: self.memory.clone(),
+            constants: self.constants.clone(),
+            program: program.clone(),
+        }
+    }
+
+    /// Rebuild a VM and its Program from a snapshot. Running it continues exactly where the snapshot was taken.
+    pub fn from_snapshot(snapshot: Snapshot) -> (Kaylee, Program) {
+        let mut vm = Kaylee::new();
+
+        vm.registers = snapshot.registers;
+        vm.float_registers.copy_from_slice(&snapshot.float_registers);
+        vm.program_counter = snapshot.program_counter;
+        vm.remainder = snapshot.remainder;
+        vm.flags = snapshot.flags;
+        vm.exit_status = snapshot.exit_status;
+        vm.traps = snapshot.traps;
+        vm.trap_return = snapshot.trap_return;
+        vm.scheduler = snapshot.scheduler;
+        vm.max_stack_depth = snapshot.max_stack_depth;
+        vm.instruction_budget = snapshot.instruction_budget;
+        vm.instructions_executed = snapshot.instructions_executed;
+        vm.groups = snapshot.groups;
+        vm.stack = snapshot.stack;
+        vm.memory = snapshot.memory;
+        vm.constants = snapshot.constants;
+
+        (vm, snapshot.program)
+    }
+
+    /// Register an observer that is called around every instruction this VM executes
+    pub fn add_observer<O: ExecutionObserver + 'static>(&mut self, observer: O) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Remove all registered observers
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// Install a trap handler at `handler` for a FaultCode, or for every fault without its own handler if the code is 0
+    pub(crate) fn set_trap(&mut self, code: FaultCode, handler: ProgramIndex) {
+        let old = self.traps.insert(code, handler);
+
+        if let Some(undo) = &mut self.undo {
+            undo.record_trap(code, old);
+        }
+    }
+
+    /// Remove a trap handler. Returns false if none was installed for the code.
+    pub(crate) fn remove_trap(&mut self, code: FaultCode) -> bool {
+        let old = self.traps.remove(&code);
+
+        if let Some(undo) = &mut self.undo {
+            undo.record_trap(code, old);
+        }
+
+        old.is_some()
+    }
+
+    /// Leave the current trap handler, returning the ProgramIndex to resume from
+    pub(crate) fn return_from_trap(&mut self) -> Result<ProgramIndex, ExecutionError> {
+        self.trap_return.take().ok_or(ExecutionError::NotInTrapHandler)
+    }
+
+    /// The installed trap handlers, by FaultCode
+    pub fn traps(&self) -> &BTreeMap<FaultCode, ProgramIndex> {
+        &self.traps
+    }
+
+    /// True while a trap handler is running
+    pub fn in_trap_handler(&self) -> bool {
+        self.trap_return.is_some()
+    }
+
+    /// Register a host function for `SYSCALL #id`, replacing any function already registered for the id
+    pub fn add_syscall<F>(&mut self, id: SyscallId, function: F)
+        where F: Fn(&mut Kaylee) -> Result<(), ExecutionError> + Send + Sync + 'static {
+        self.syscalls.insert(id, Arc::new(function));
+    }
+
+    /// Remove a host function. Returns false if none was registered for the id.
+    pub fn remove_syscall(&mut self, id: SyscallId) -> bool {
+        self.syscalls.remove(&id).is_some()
+    }
+
+    /// Call the host function registered for `id`. A replay applies the recorded result instead of calling it
+    pub(crate) fn call_host(&mut self, id: SyscallId) -> Result<(), ExecutionError> {
+        let function = self.syscalls.get(&id).cloned().ok_or(ExecutionError::UnknownSyscall(id))?;
+
+        if let Some(event) = self.replay_event("a host function")? {
+            return match event {
+                Event::Syscall { id: recorded, outcome } if recorded == id => match outcome {
+                    Ok(effect) if effect.registers.len() != self.registers.len() => {
+                        Err(ExecutionError::ReplayDiverged(format!("recorded {} registers, but the VM has {}", effect.registers.len(), self.registers.len())))
+                    }
+                    Ok(effect) => {
+                        // Write only the registers the host function changed, as it did when it was recorded
+                        for (register, value) in effect.registers.into_iter().enumerate() {
+                            if self.registers[register] != value {
+                                self.set_register(register, value)?;
+                            }
+                        }
+
+                        self.float_registers = effect.float_registers;
+                        Ok(())
+                    }
+                    Err(message) => Err(ExecutionError::Host(message)),
+                },
+                event => Err(self.divergence(&format!("host function {id}"), &event)),
+            };
+        }
+
+        let result = function(self);
+
+        if matches!(self.tracer, Some(Tracer::Recording(_))) {
+            let outcome = match &result {
+                Ok(()) => Ok(Box::new(SyscallEffect { registers: self.registers.clone(), float_registers: self.float_registers })),
+                Err(ExecutionError::Host(message)) => Err(message.clone()),
+                Err(error) => Err(error.to_string()),
+            };
+
+            self.record(Event::Syscall { id, outcome });
+        }
+
+        result
+    }
+
+    /// Read a line of console input without its line ending, or `None` at the end of input
+    pub(crate) fn read_input_line(&mut self) -> Result<Option<String>, ExecutionError> {
+        if let Some(event) = self.replay_event("console input")? {
+            return match event {
+                Event::Input(line) => Ok(line),
+                event => Err(self.divergence("console input", &event)),
+            };
+        }
+
+        let mut line = String::new();
+        let line = match self.input.read_line(&mut line)? {
+            0 => None,
+            _ => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+        };
+
+        self.record(Event::Input(line.clone()));
+        Ok(line)
+    }
+
+    /// The number of seconds since the Unix epoch, truncated to a register
+    pub(crate) fn clock(&mut self) -> Result<RegisterValue, ExecutionError> {
+        if let Some(event) = self.replay_event("the clock")? {
+            return match event {
+                Event::Clock(value) => Ok(value),
+                event => Err(self.divergence("the clock", &event)),
+            };
+        }
+
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+        let value = seconds as RegisterValue;
+
+        self.record(Event::Clock(value));
+        Ok(value)
+    }
+
+    /// A random number, from any value a register can hold
+    pub(crate) fn random(&mut self) -> Result<RegisterValue, ExecutionError> {
+        if let Some(event) = self.replay_event("a random number")? {
+            return match event {
+                Event::Random(value) => Ok(value),
+                event => Err(self.divergence("a random number", &event)),
+            };
+        }
+
+        // Every RandomState is keyed randomly, so hashing nothing with a new one gives a random number
+        let value = RandomState::new().build_hasher().finish() as RegisterValue;
+
+        self.record(Event::Random(value));
+        Ok(value)
+    }
+
+    fn record(&mut self, event: Event) {
+        if let Some(Tracer::Recording(trace)) = &mut self.tracer {
+            trace.events.push(TraceEvent { program_index: self.program_counter, thread: self.scheduler.current, event });
+        }
+    }
+
+    /// The next recorded event while replaying, checked against where the program is now. `None` when not replaying
+    fn replay_event(&mut self, wanted: &str) -> Result<Option<Event>, ExecutionError> {
+        let program_index = self.program_counter;
+        let thread = self.scheduler.current;
+
+        let Some(Tracer::Replaying { trace, position }) = &mut self.tracer else {
+            return Ok(None);
+        };
+
+        let Some(recorded) = trace.events.get(*position) else {
+            return Err(ExecutionError::ReplayDiverged(format!("asked for {wanted} at {program_index} after the recording ended")));
+        };
+
+        if recorded.program_index != program_index || recorded.thread != thread {
+            return Err(ExecutionError::ReplayDiverged(format!(
+                "asked for {wanted} at {program_index} in thread {thread}, but the recording consumed {} at {} in thread {}",
+                recorded.event.kind(), recorded.program_index, recorded.thread,
+            )));
+        }
+
+        *position += 1;
+        Ok(Some(recorded.event.clone()))
+    }
+
+    fn divergence(&self, wanted: &str, recorded: &Event) -> ExecutionError {
+        ExecutionError::ReplayDiverged(format!("asked for {wanted} at {}, but the recording has {}", self.program_counter, recorded.kind()))
+    }
+
+    pub fn register(&self, register: RegisterId) -> Result<RegisterValue, ExecutionError> {
+        match self.registers.get(register) {
+            Some(value) => Ok(*value),
+            None => Err(ExecutionError::RegisterOutOfBounds(register)),
+        }
+    }
+
+    pub fn all_registers(&self) -> &[RegisterValue] {
+        &self.registers
+    }
+
+    /// Write a register. Mostly used by host functions to return their results.
+    pub fn set_register(&mut self, register: RegisterId, value: RegisterValue) -> Result<(), ExecutionError> {
+        let slot = self.registers.get_mut(register).ok_or(ExecutionError::RegisterOutOfBounds(register))?;
+        let write = RegisterWrite { register, old: *slot, new: value };
+        *slot = value;
+
+        if let Some(writes) = &mut self.register_writes {
+            writes.push(write);
+        }
+
+        if let Some(undo) = &mut self.undo {
+            undo.record_register(write);
         }
 
-        self.registers[register] = value;
         Ok(())
     }
 
-    pub(crate) fn halt(&mut self) {
-        self.halted = true;
+    /// Start keeping every register write, even one that leaves the value as it was, for `take_register_writes`
+    pub(crate) fn log_register_writes(&mut self) {
+        self.register_writes.get_or_insert_with(Vec::new);
+    }
+
+    /// The register writes since the last call, oldest first
+    pub(crate) fn take_register_writes(&mut self) -> Vec<RegisterWrite> {
+        self.register_writes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// The register writes made by the instruction `step_back` would undo, oldest first
+    pub(crate) fn last_register_writes(&self) -> &[RegisterWrite] {
+        self.undo.as_ref()
+            .and_then(|undo| undo.records.back())
+            .map_or(&[], |record| record.register_writes.as_slice())
+    }
+
+    pub fn float_register(&self, register: RegisterId) -> Result<FloatRegisterValue, ExecutionError> {
+        match self.float_registers.get(register) {
+            Some(value) => Ok(*value),
+            None => Err(ExecutionError::RegisterOutOfBounds(register)),
+        }
+    }
+
+    pub fn all_float_registers(&self) -> [FloatRegisterValue; Kaylee::FLOAT_REGISTER_COUNT] {
+        self.float_registers
+    }
+
+    /// Write a float register. Mostly used by host functions to return their results.
+    pub fn set_float_register(&mut self, register: RegisterId, value: FloatRegisterValue) -> Result<(), ExecutionError> {
+        match self.float_registers.get_mut(register) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(ExecutionError::RegisterOutOfBounds(register)),
+        }
+    }
+
+    /// The entire linear memory of the VM
+    pub fn memory(&self) -> &[Byte] {
+        &self.memory
+    }
+
+    /// The entry of the loaded Program's constant pool at `index`
+    pub(crate) fn constant(&self, index: usize) -> Result<Constant, ExecutionError> {
+        self.constants.get(index).copied().ok_or(ExecutionError::UnknownConstant(index))
+    }
+
+    /// Read `length` bytes of memory starting at `address`
+    pub(crate) fn read_memory(&self, address: MemoryAddress, length: usize) -> Result<&[Byte], ExecutionError> {
+        address.checked_add(length)
+            .and_then(|end| self.memory.get(address..end))
+            .ok_or(ExecutionError::MemoryOutOfBounds(address))
+    }
+
+    /// Write all of `bytes` into memory starting at `address`
+    pub(crate) fn write_memory(&mut self, address: MemoryAddress, bytes: &[Byte]) -> Result<(), ExecutionError> {
+        let slot = address.checked_add(bytes.len())
+            .and_then(|end| self.memory.get_mut(address..end))
+            .ok_or(ExecutionError::MemoryOutOfBounds(address))?;
+
+        if let Some(undo) = &mut self.undo {
+            undo.record_memory(address, slot);
+        }
+
+        slot.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Read a null-terminated string from memory starting at `address`. Invalid UTF-8 is replaced.
+    pub(crate) fn read_string(&self, address: MemoryAddress) -> Result<String, ExecutionError> {
+        let bytes = self.memory.get(address..).ok_or(ExecutionError::MemoryOutOfBounds(address))?;
+        let length = bytes.iter().position(|byte| *byte == 0).ok_or(ExecutionError::MemoryOutOfBounds(address))?;
+
+        Ok(String::from_utf8_lossy(&bytes[..length]).into_owned())
     }
 
-    pub(crate) fn remainder(&self) -> u32 {
+    /// Sets how many values the stack may hold before pushes fault with `StackOverflow`
+    pub fn with_max_stack_depth(mut self, depth: usize) -> Self {
+        self.max_stack_depth = depth;
+        self
+    }
+
+    /// The current contents of the stack, from bottom to top
+    pub fn stack(&self) -> &[RegisterValue] {
+        &self.stack
+    }
+
+    pub(crate) fn push_stack(&mut self, value: RegisterValue) -> Result<(), ExecutionError> {
+        if self.stack.len() >= self.max_stack_depth {
+            return Err(ExecutionError::StackOverflow);
+        }
+
+        self.stack.push(value);
+
+        if let Some(undo) = &mut self.undo {
+            undo.record_stack(StackChange::Pushed);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn pop_stack(&mut self) -> Result<RegisterValue, ExecutionError> {
+        let value = self.stack.pop().ok_or(ExecutionError::StackUnderflow)?;
+
+        if let Some(undo) = &mut self.undo {
+            undo.record_stack(StackChange::Popped(value));
+        }
+
+        Ok(value)
+    }
+
+    /// Stop the machine once the current instruction completes
+    pub(crate) fn exit(&mut self, status: ExitStatus) {
+        self.exit_status = Some(status);
+    }
+
+    /// True once a `HALT` or `DIE` has stopped the machine
+    pub fn is_halted(&self) -> bool {
+        self.exit_status.is_some()
+    }
+
+    /// How a `HALT` or `DIE` stopped the machine, if one has
+    pub fn exit_status(&self) -> Option<&ExitStatus> {
+        self.exit_status.as_ref()
+    }
+
+    pub fn remainder(&self) -> u32 {
         self.remainder
     }
 
@@ -118,7 +1512,15 @@ impl Kaylee {
         self.remainder = remainder
     }
 
-    pub(crate) fn program_counter(&self) -> ProgramIndex {
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    pub(crate) fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags
+    }
+
+    pub fn program_counter(&self) -> ProgramIndex {
         self.program_counter
     }
 
@@ -129,5 +1531,116 @@ impl Kaylee {
 
 #[cfg(test)]
 mod tests {
+    use crate::asm::Source;
+    use crate::instructions::data::Load;
+    use crate::instructions::math::{Add, Divide};
+    use crate::instructions::program::{Jump, JumpBackward};
+    use crate::program::Program;
+    use crate::vm::{ExecutionError, ExitStatus, Kaylee, RuntimeError};
+
+    #[test]
+    fn test_illegal_opcode() {
+        let program = Program::from(vec![
+            Load::OPCODE, 0, 0, 100,
+            255, 0, 0, 0,
+        ]);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run(program).unwrap_err();
 
+        assert_eq!(RuntimeError { error: ExecutionError::IllegalOpcode(255), program_index: 4, instruction: None }, error);
+        assert_eq!(100, vm.register(0).unwrap());
+    }
+
+    #[test]
+    fn test_truncated_instruction() {
+        let program = Program::from(vec![
+            Load::OPCODE, 0, 0, 100,
+            Add::OPCODE, 1, 2,
+        ]);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run(program).unwrap_err();
+
+        assert_eq!(ExecutionError::TruncatedInstruction, error.error);
+        assert_eq!(4, error.program_index);
+    }
+
+    #[test]
+    fn test_register_out_of_bounds() {
+        let program = Program::from(vec![
+            Add::OPCODE, 1, 2, 40,
+        ]);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run(program).unwrap_err();
+
+        assert_eq!(ExecutionError::RegisterOutOfBounds(40), error.error);
+        assert_eq!(0, error.program_index);
+        assert_eq!(Some(String::from("ADD $1 $2 $40")), error.instruction);
+    }
+
+    #[test]
+    fn test_divide_by_zero() {
+        let program = Program::from(vec![
+            Divide::OPCODE, 0, 1, 2,
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, 10).unwrap();
+
+        let error = vm.run(program).unwrap_err();
+
+        assert_eq!(ExecutionError::DivideByZero, error.error);
+        assert_eq!(Some(String::from("DIV $0 $1 $2")), error.instruction);
+    }
+
+    #[test]
+    fn test_arithmetic_overflow() {
+        let program = Program::from(vec![
+            Add::OPCODE, 0, 1, 1,
+        ]);
+
+        let mut vm = Kaylee::new();
+        vm.set_register(1, i32::MAX).unwrap();
+
+        assert_eq!(ExecutionError::ArithmeticOverflow, vm.run(program).unwrap_err().error);
+    }
+
+    #[test]
+    fn test_program_counter_out_of_bounds() {
+        let program = Program::from(vec![
+            Load::OPCODE, 0, 0, 100,
+            Jump::OPCODE, 0, 1, 0,
+        ]);
+
+        let mut vm = Kaylee::new();
+        let error = vm.run(program).unwrap_err();
+
+        assert_eq!(ExecutionError::ProgramCounterOutOfBounds(256), error.error);
+        assert_eq!(4, error.program_index);
+
+        let program = Program::from(vec![
+            JumpBackward::OPCODE, 0, 0, 3,
+        ]);
+
+        let mut vm = Kaylee::new();
+        assert!(matches!(vm.run(program).unwrap_err().error, ExecutionError::ProgramCounterOutOfBounds(_)));
+    }
+
+    #[test]
+    fn test_load_and_run_installs_data_and_constants() {
+        let program = Program::try_from(Source::from(String::from(
+            "LOADC $1 @big\nLOADC $2 @table\nLOADB $3 #1\nHALT\n.data\nbig:\n.int #100000\ntable:\n.bytes #4 #5\n"
+        ))).unwrap();
+
+        let error = Kaylee::new().run(program.clone()).unwrap_err();
+        assert_eq!(ExecutionError::UnknownConstant(0), error.error);
+
+        let mut vm = Kaylee::new();
+        assert_eq!(ExitStatus::Halted(0), vm.load_and_run(program).unwrap());
+        assert_eq!(100_000, vm.register(1).unwrap());
+        assert_eq!(0, vm.register(2).unwrap());
+        assert_eq!(5, vm.register(3).unwrap());
+    }
 }
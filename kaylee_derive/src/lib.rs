@@ -56,15 +56,18 @@ pub fn derive_instruction(input: TokenStream) -> TokenStream {
                                 if char == ' ' {
                                     state = match state {
                                         SignatureState::Identifier => {
-                                            identifier = String::from(buffer.clone());
+                                            identifier = buffer.clone();
                                             SignatureState::Operands
                                         }
                                         SignatureState::Operands => {
-                                            let me = buffer.chars().nth(0).unwrap();
+                                            let me = buffer.chars().next().unwrap();
                                             let operand_tokens = match me {
                                                 '$' => {
                                                     quote! { OperandType::RegisterId }
                                                 }
+                                                '%' => {
+                                                    quote! { OperandType::FloatRegisterId }
+                                                }
                                                 '#' => {
                                                     let bytes = &buffer[1..].parse::<i32>().unwrap();
                                                     match bytes {
@@ -80,7 +83,7 @@ pub fn derive_instruction(input: TokenStream) -> TokenStream {
                                             };
 
                                             operands[operand_index] = operand_tokens;
-                                            operand_index = operand_index + 1;
+                                            operand_index += 1;
 
                                             SignatureState::Operands
                                         }
@@ -103,7 +106,7 @@ pub fn derive_instruction(input: TokenStream) -> TokenStream {
     }
 
     let name = format!("{}", struct_name);
-    let identifier_string = format!("{identifier}");
+    let identifier_string = identifier.to_string();
     let const_name = format_ident!("{struct_name}_STATIC");
 
     let op1 = &operands[0];
@@ -158,22 +161,17 @@ pub fn values(_args: TokenStream, input: TokenStream) -> TokenStream {
     let mut ast = parse_macro_input!(input as DeriveInput);
     match &mut ast.data {
         syn::Data::Struct(ref mut struct_data) => {
-            match &mut struct_data.fields {
-                syn::Fields::Named(fields) => {
-                    fields
-                        .named
-                        .push(syn::Field::parse_named.parse2(quote! {
-                            operand_values: OperandValues
-                        }).unwrap());
-                }
-                _ => {
-                    ()
-                }
+            if let syn::Fields::Named(fields) = &mut struct_data.fields {
+                fields
+                    .named
+                    .push(syn::Field::parse_named.parse2(quote! {
+                        operand_values: OperandValues
+                    }).unwrap());
             }
 
-            return quote! {
+            quote! {
                 #ast
-            }.into();
+            }.into()
         }
         _ => panic!("`add_field` has to be used with structs "),
     }